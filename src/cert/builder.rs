@@ -0,0 +1,258 @@
+use result::NSSResult;
+use ffi::{cert, pk11, sec};
+use ffi::nspr::PRBool;
+use crypto::pkey;
+use super::{der, Certificate, Extension, GeneralName, NameBuilder};
+use std::ptr;
+use std::time::{Duration, SystemTime};
+use libc::{c_int, c_void};
+
+/// Builds and signs an X.509 v3 certificate
+#[derive(Clone, Debug)]
+pub struct CertificateBuilder
+{
+    subject: NameBuilder,
+    serial_number: Option<Vec<u8>>,
+    not_before: SystemTime,
+    not_after: SystemTime,
+    names: Vec<GeneralName>,
+    extensions: Vec<Extension>,
+    key_identifiers: bool,
+}
+
+impl CertificateBuilder
+{
+    /// A certificate valid for a year from now, with a random serial number and key identifiers
+    pub fn new(subject: NameBuilder) -> CertificateBuilder
+    {
+        let now = SystemTime::now();
+        CertificateBuilder {
+            subject: subject,
+            serial_number: None,
+            not_before: now,
+            not_after: now + Duration::from_secs(365 * 24 * 60 * 60),
+            names: Vec::new(),
+            extensions: Vec::new(),
+            key_identifiers: true,
+        }
+    }
+
+    /// Sets the serial number as big-endian bytes
+    pub fn serial_number(mut self, serial: &[u8]) -> CertificateBuilder
+    {
+        self.serial_number = Some(serial.to_vec());
+        self
+    }
+
+    pub fn validity(mut self, not_before: SystemTime, not_after: SystemTime) -> CertificateBuilder
+    {
+        self.not_before = not_before;
+        self.not_after = not_after;
+        self
+    }
+
+    pub fn subject_alt_names(mut self, names: &[GeneralName]) -> CertificateBuilder
+    {
+        self.names.extend(names.iter().cloned());
+        self
+    }
+
+    pub fn extension(mut self, extension: Extension) -> CertificateBuilder
+    {
+        self.extensions.push(extension);
+        self
+    }
+
+    /// Whether to add subject and authority key identifiers derived from the keys
+    pub fn key_identifiers(mut self, enabled: bool) -> CertificateBuilder
+    {
+        self.key_identifiers = enabled;
+        self
+    }
+
+    pub fn self_signed(&self, key: &mut pkey::PrivateKey, hash: pkey::HashAlgorithm) -> NSSResult<Certificate>
+    {
+        let public_key = try!(key.public_key());
+        self.build(&public_key, None, key, hash)
+    }
+
+    /// Issues a certificate for the public key, signed by the issuer's key
+    pub fn sign(&self, public_key: &pkey::PublicKey, issuer: &Certificate, issuer_key: &mut pkey::PrivateKey,
+                hash: pkey::HashAlgorithm) -> NSSResult<Certificate>
+    {
+        self.build(public_key, Some(issuer), issuer_key, hash)
+    }
+
+    fn build(&self, public_key: &pkey::PublicKey, issuer: Option<&Certificate>, issuer_key: &mut pkey::PrivateKey,
+             hash: pkey::HashAlgorithm) -> NSSResult<Certificate>
+    {
+        try!(::nss::init());
+
+        let algorithm = try!(issuer_key.signature_algorithm(hash));
+        let serial = match self.serial_number
+        {
+            Some(ref serial) => der::integer_content(serial),
+            None => try!(random_serial()),
+        };
+
+        let mut subject = try!(self.subject.to_ffi());
+        let mut certificate = unsafe
+        {
+            let mut spki = try!(pk11::PublicKeyInfo::wrap(cert::SECKEY_CreateSubjectPublicKeyInfo(public_key.get())));
+            let mut request = try!(cert::CertificateRequest::wrap(cert::CERT_CreateCertificateRequest(subject.get_mut(), spki.get_mut(),
+                                                                                                     ptr::null_mut())));
+            let mut validity = try!(cert::Validity::wrap(cert::CERT_CreateValidity(super::to_prtime(self.not_before),
+                                                                                  super::to_prtime(self.not_after))));
+            // Both names are only copied into the new certificate
+            let issuer_name = match issuer
+            {
+                Some(issuer) => &issuer.raw().subject as *const _ as *mut cert::CERTName,
+                None => subject.get_mut(),
+            };
+            try!(cert::Certificate::wrap(cert::CERT_CreateCertificate(0, issuer_name, validity.get_mut(), request.get_mut())))
+        };
+
+        let mut extensions = Vec::new();
+        if !self.names.is_empty() {
+            extensions.push(try!(Extension::subject_alt_name(&self.names)));
+        }
+        if self.key_identifiers {
+            let subject_key_id = try!(key_id(unsafe { &(*certificate.get()).subject_public_key_info }));
+            let authority_key_id = match issuer
+            {
+                Some(issuer) => match try!(issuer.subject_key_id())
+                {
+                    Some(id) => id,
+                    None => try!(key_id(&issuer.raw().subject_public_key_info)),
+                },
+                None => subject_key_id.clone(),
+            };
+            extensions.push(Extension::subject_key_identifier(&subject_key_id));
+            extensions.push(Extension::authority_key_identifier(&authority_key_id));
+        }
+        extensions.extend(self.extensions.iter().cloned());
+
+        unsafe
+        {
+            let raw = certificate.get_mut();
+            let serial = sec::SECItem::from_buf(&serial);
+            try!(cert::SECITEM_CopyItem((*raw).arena, &mut (*raw).serial_number, serial.get()).to_result());
+            if cert::SEC_ASN1EncodeInteger((*raw).arena, &mut (*raw).version, 2).is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+            try!(cert::SECOID_SetAlgorithmID((*raw).arena, &mut (*raw).signature, algorithm, ptr::null_mut()).to_result());
+            if !extensions.is_empty() {
+                try!(super::add_extensions(cert::CERT_StartCertExtensions(raw), &extensions));
+            }
+        }
+
+        let mut arena = try!(super::new_arena());
+        let mut encoded = sec::SECItem::empty();
+        unsafe
+        {
+            let template = cert::NSS_Get_CERT_CertificateTemplate(ptr::null_mut(), PRBool::False);
+            if cert::SEC_ASN1EncodeItem(arena.get_mut(), encoded.get_mut(), certificate.get() as *const c_void, template).is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+        }
+
+        let signed = try!(super::sign_der(encoded.get().as_slice(), issuer_key, hash));
+        Certificate::from_der(&signed)
+    }
+}
+
+/// A positive 16 byte serial number
+fn random_serial() -> NSSResult<Vec<u8>>
+{
+    let mut serial = vec![0; 16];
+    unsafe { try!(pk11::PK11_GenerateRandom(serial.as_mut_ptr(), serial.len() as c_int).to_result()) };
+    serial[0] = (serial[0] & 0x7F) | 0x40;
+    Ok(serial)
+}
+
+/// The SHA-1 hash of the subjectPublicKey, as in RFC 5280 section 4.2.1.2
+fn key_id(spki: &cert::CERTSubjectPublicKeyInfo) -> NSSResult<Vec<u8>>
+{
+    let key = spki.subject_public_key.as_bit_slice();
+    let mut out = vec![0; pkey::HashAlgorithm::SHA1.output_len()];
+    unsafe { try!(cert::PK11_HashBuf(cert::SEC_OID_SHA1, out.as_mut_ptr(), key.as_ptr(), key.len() as i32).to_result()) };
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::{BasicConstraints, Certificate, ExtendedKeyUsage, Extension, GeneralName, KeyUsage, NameBuilder};
+    use cert::verify::{Usage, Verifier};
+    use crypto::pkey::{Curve, HashAlgorithm, PrivateKey};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn ca(path_len: Option<u32>) -> CertificateBuilder
+    {
+        CertificateBuilder::new(NameBuilder::new().organization("nss-rs").common_name("nss-rs builder CA"))
+            .extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: path_len }))
+            .extension(Extension::key_usage(&[KeyUsage::KeyCertSign, KeyUsage::CRLSign]))
+    }
+
+    #[test]
+    fn hierarchy()
+    {
+        let mut root_key = PrivateKey::gen_ec(Curve::P384).unwrap();
+        let root = ca(Some(1)).self_signed(&mut root_key, HashAlgorithm::SHA384).unwrap();
+
+        let mut intermediate_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let intermediate = CertificateBuilder::new(NameBuilder::new().organization("nss-rs").common_name("nss-rs builder intermediate"))
+                            .extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: Some(0) }))
+                            .extension(Extension::key_usage(&[KeyUsage::KeyCertSign, KeyUsage::CRLSign]))
+                            .extension(Extension::crl_distribution_points(&[GeneralName::URI("http://crl.example.com/root.crl".to_string())]).unwrap())
+                            .sign(&intermediate_key.public_key().unwrap(), &root, &mut root_key, HashAlgorithm::SHA256).unwrap();
+
+        let mut leaf_key = PrivateKey::gen_rsa(1024).unwrap();
+        let not_before = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let not_after = SystemTime::now() + Duration::from_secs(24 * 60 * 60);
+        let leaf = CertificateBuilder::new(NameBuilder::new().common_name("builder.example.com"))
+                    .serial_number(&[0x00, 0x12, 0x34])
+                    .validity(not_before, not_after)
+                    .subject_alt_names(&[GeneralName::DNS("builder.example.com".to_string())])
+                    .extension(Extension::key_usage(&[KeyUsage::DigitalSignature, KeyUsage::KeyEncipherment]))
+                    .extension(Extension::extended_key_usage(&[ExtendedKeyUsage::ServerAuth]).unwrap())
+                    .sign(&leaf_key.public_key().unwrap(), &intermediate, &mut intermediate_key, HashAlgorithm::SHA256).unwrap();
+
+        assert_eq!(root.subject().to_der(), root.issuer().to_der());
+        assert_eq!(root.subject().to_der(), intermediate.issuer().to_der());
+        assert_eq!(intermediate.subject().to_der(), leaf.issuer().to_der());
+        assert_eq!(Some(BasicConstraints { ca: true, path_len: Some(1) }), root.basic_constraints().unwrap());
+        assert_eq!(root.subject_key_id().unwrap(), root.authority_key_id().unwrap());
+        assert_eq!(root.subject_key_id().unwrap(), intermediate.authority_key_id().unwrap());
+        assert_eq!(intermediate.subject_key_id().unwrap(), leaf.authority_key_id().unwrap());
+        assert_eq!(20, leaf.subject_key_id().unwrap().unwrap().len());
+        assert_eq!(vec![GeneralName::URI("http://crl.example.com/root.crl".to_string())],
+                   intermediate.crl_distribution_points().unwrap());
+        assert!(leaf.crl_distribution_points().unwrap().is_empty());
+
+        assert_eq!(&[0x12, 0x34], leaf.serial_number());
+        assert_eq!(16, root.serial_number().len());
+        assert_eq!(not_before, leaf.not_before().unwrap());
+        assert_eq!(Some(vec![KeyUsage::DigitalSignature, KeyUsage::KeyEncipherment]), leaf.key_usage());
+        assert_eq!(Some(vec![ExtendedKeyUsage::ServerAuth]), leaf.extended_key_usage().unwrap());
+        assert_eq!(vec![GeneralName::DNS("builder.example.com".to_string())], leaf.subject_alt_names().unwrap());
+
+        let chain = Verifier::new(Usage::TLSServer).trust_anchor(&root).intermediate(&intermediate).verify(&leaf).unwrap();
+        assert_eq!(3, chain.len());
+        assert_eq!(root.to_der(), chain[2].to_der());
+    }
+
+    #[test]
+    fn without_key_identifiers()
+    {
+        let mut key = PrivateKey::gen_rsa(1024).unwrap();
+        let cert = ca(None).key_identifiers(false).self_signed(&mut key, HashAlgorithm::SHA256).unwrap();
+        assert_eq!(None, cert.subject_key_id().unwrap());
+        assert_eq!(None, cert.authority_key_id().unwrap());
+        assert_eq!(Some(BasicConstraints { ca: true, path_len: None }), cert.basic_constraints().unwrap());
+
+        let reparsed = Certificate::from_pem(&cert.to_pem().unwrap()).unwrap();
+        assert_eq!(cert.to_der(), reparsed.to_der());
+    }
+}
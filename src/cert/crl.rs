@@ -0,0 +1,537 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, sec};
+use ffi::nspr::PRBool;
+use crypto::pkey;
+use super::{der, pem, Certificate, Extension, Name};
+use super::db::Database;
+use std::{mem, ptr};
+use std::time::{Duration, SystemTime};
+use libc::c_void;
+
+/// The reason code of a revoked certificate, as in RFC 5280 section 5.3.1
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum RevocationReason
+{
+    Unspecified,
+    KeyCompromise,
+    CACompromise,
+    AffiliationChanged,
+    Superseded,
+    CessationOfOperation,
+    CertificateHold,
+    RemoveFromCRL,
+    PrivilegeWithdrawn,
+    AACompromise,
+}
+
+impl RevocationReason
+{
+    fn from_ffi(code: i32) -> Option<RevocationReason>
+    {
+        match code
+        {
+            0 => Some(RevocationReason::Unspecified),
+            1 => Some(RevocationReason::KeyCompromise),
+            2 => Some(RevocationReason::CACompromise),
+            3 => Some(RevocationReason::AffiliationChanged),
+            4 => Some(RevocationReason::Superseded),
+            5 => Some(RevocationReason::CessationOfOperation),
+            6 => Some(RevocationReason::CertificateHold),
+            8 => Some(RevocationReason::RemoveFromCRL),
+            9 => Some(RevocationReason::PrivilegeWithdrawn),
+            10 => Some(RevocationReason::AACompromise),
+            _ => None,
+        }
+    }
+
+    fn to_ffi(&self) -> u8
+    {
+        match *self
+        {
+            RevocationReason::Unspecified => 0,
+            RevocationReason::KeyCompromise => 1,
+            RevocationReason::CACompromise => 2,
+            RevocationReason::AffiliationChanged => 3,
+            RevocationReason::Superseded => 4,
+            RevocationReason::CessationOfOperation => 5,
+            RevocationReason::CertificateHold => 6,
+            RevocationReason::RemoveFromCRL => 8,
+            RevocationReason::PrivilegeWithdrawn => 9,
+            RevocationReason::AACompromise => 10,
+        }
+    }
+}
+
+/// An entry of a CRL
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RevokedCertificate
+{
+    /// The serial number as the content bytes of its DER INTEGER, like `Certificate::serial_number`
+    pub serial_number: Vec<u8>,
+    pub revocation_date: SystemTime,
+    pub reason: Option<RevocationReason>,
+}
+
+impl RevokedCertificate
+{
+    fn from_ffi(entry: &cert::CERTCrlEntry) -> NSSResult<RevokedCertificate>
+    {
+        let mut code = 0;
+        let reason = match unsafe { cert::CERT_FindCRLEntryReasonExten(entry as *const _ as *mut _, &mut code).to_result() }
+        {
+            Ok(..) => RevocationReason::from_ffi(code),
+            Err(NSSError::NSS(sec::SEC_ERROR_EXTENSION_NOT_FOUND)) => None,
+            Err(e) => return Err(e),
+        };
+
+        Ok(RevokedCertificate {
+            serial_number: entry.serial_number.as_slice().to_vec(),
+            revocation_date: try!(decode_time(&entry.revocation_date)),
+            reason: reason,
+        })
+    }
+}
+
+/// A parsed X.509 certificate revocation list
+pub struct Crl
+{
+    crl: cert::SignedCrl,
+}
+
+impl Crl
+{
+    pub fn from_der(data: &[u8]) -> NSSResult<Crl>
+    {
+        try!(::nss::init());
+
+        // The DER is copied into the CRL's own arena
+        let mut der = sec::SECItem::from_buf(data);
+        let crl = unsafe { try!(cert::SignedCrl::wrap(cert::CERT_DecodeDERCrl(ptr::null_mut(), der.get_mut(), cert::SEC_CRL_TYPE))) };
+        Ok(Crl { crl: crl })
+    }
+
+    pub fn from_pem(data: &str) -> NSSResult<Crl>
+    {
+        let der = try!(pem::decode("X509 CRL", data));
+        Crl::from_der(&der)
+    }
+
+    fn raw(&self) -> &cert::CERTSignedCrl
+    {
+        unsafe { &*self.crl.get() }
+    }
+
+    pub fn to_der(&self) -> Vec<u8>
+    {
+        unsafe { (*self.raw().der_crl).as_slice().to_vec() }
+    }
+
+    pub fn to_pem(&self) -> NSSResult<String>
+    {
+        pem::encode("X509 CRL", &self.to_der())
+    }
+
+    pub fn issuer<'a>(&'a self) -> Name<'a>
+    {
+        Name { name: &self.raw().crl.name, der: self.raw().crl.der_name.as_slice() }
+    }
+
+    pub fn this_update(&self) -> NSSResult<SystemTime>
+    {
+        decode_time(&self.raw().crl.last_update)
+    }
+
+    pub fn next_update(&self) -> NSSResult<Option<SystemTime>>
+    {
+        match self.raw().crl.next_update.as_slice().is_empty()
+        {
+            true => Ok(None),
+            false => decode_time(&self.raw().crl.next_update).map(Some),
+        }
+    }
+
+    pub fn number(&self) -> NSSResult<Option<u64>>
+    {
+        let mut arena = try!(super::new_arena());
+        let mut item = sec::SECItem::empty();
+        let crl = &self.raw().crl as *const _ as *mut cert::CERTCrl;
+        match unsafe { cert::CERT_FindCRLNumberExten(arena.get_mut(), crl, item.get_mut()).to_result() }
+        {
+            Ok(..) => {},
+            Err(NSSError::NSS(sec::SEC_ERROR_EXTENSION_NOT_FOUND)) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let bytes: Vec<u8> = item.get().as_slice().iter().cloned().skip_while(|&b| b == 0).collect();
+        if bytes.len() > 8 {
+            return Err(NSSError::NSS(sec::SEC_ERROR_BAD_DER));
+        }
+        Ok(Some(bytes.iter().fold(0, |number, &b| (number << 8) | b as u64)))
+    }
+
+    pub fn entries(&self) -> NSSResult<Vec<RevokedCertificate>>
+    {
+        let mut result = Vec::new();
+        let mut current = self.raw().crl.entries;
+        unsafe
+        {
+            while !current.is_null() && !(*current).is_null()
+            {
+                result.push(try!(RevokedCertificate::from_ffi(&**current)));
+                current = current.offset(1);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Finds the entry for the certificate, if this CRL covers its issuer
+    pub fn lookup(&self, certificate: &Certificate) -> NSSResult<Option<RevokedCertificate>>
+    {
+        if self.raw().crl.der_name.as_slice() != certificate.raw().der_issuer.as_slice() {
+            return Ok(None);
+        }
+
+        let entries = try!(self.entries());
+        Ok(entries.into_iter().find(|entry| &*entry.serial_number == certificate.serial_number()))
+    }
+
+    /// Checks that the CRL is signed by the issuer
+    pub fn verify(&self, issuer: &Certificate) -> NSSResult<()>
+    {
+        unsafe
+        {
+            let signed = &self.raw().signature_wrap as *const _ as *mut cert::CERTSignedData;
+            let spki = &issuer.raw().subject_public_key_info as *const _ as *mut cert::CERTSubjectPublicKeyInfo;
+            cert::CERT_VerifySignedDataWithPublicKeyInfo(signed, spki, ptr::null_mut()).to_result()
+        }
+    }
+
+    /// Imports the CRL into the database, replacing older CRLs of the same issuer, so chain verification consults it
+    ///
+    /// The issuer has to be known to verify the CRL, such as by being in one of the open databases.
+    pub fn import(&self, database: &mut Database) -> NSSResult<()>
+    {
+        let mut der = sec::SECItem::from_buf(unsafe { (*self.raw().der_crl).as_slice() });
+        unsafe
+        {
+            try!(cert::SignedCrl::wrap(cert::PK11_ImportCRL(database.slot.get_mut(), der.get_mut(), ptr::null_mut(),
+                                                            cert::SEC_CRL_TYPE, ptr::null_mut(), 0, ptr::null_mut(), 0)));
+        }
+        Ok(())
+    }
+
+    /// Adds the CRL to the in-memory CRL cache, which chain verification consults until the result is dropped
+    pub fn cache(&self) -> NSSResult<CachedCrl>
+    {
+        let mut der = try!(sec::SECItem::dup(unsafe { (*self.raw().der_crl).as_slice() }));
+        unsafe { try!(cert::CERT_CacheCRL(cert::CERT_GetDefaultCertDB(), der.get_mut()).to_result()) };
+        Ok(CachedCrl { der: der })
+    }
+}
+
+/// A CRL in the CRL cache, see `Crl::cache`
+pub struct CachedCrl
+{
+    // NSS keeps using this item until the CRL is removed from the cache
+    der: sec::SECItem<'static>,
+}
+
+impl Drop for CachedCrl
+{
+    fn drop(&mut self)
+    {
+        unsafe { let _ = cert::CERT_UncacheCRL(cert::CERT_GetDefaultCertDB(), self.der.get_mut()); }
+    }
+}
+
+/// Builds and signs a version 2 CRL
+#[derive(Clone, Debug)]
+pub struct CrlBuilder
+{
+    this_update: SystemTime,
+    next_update: SystemTime,
+    number: Option<u64>,
+    entries: Vec<RevokedCertificate>,
+}
+
+impl CrlBuilder
+{
+    /// A CRL issued now, with the next update due in a week
+    pub fn new() -> CrlBuilder
+    {
+        let now = SystemTime::now();
+        CrlBuilder {
+            this_update: now,
+            next_update: now + Duration::from_secs(7 * 24 * 60 * 60),
+            number: None,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn this_update(mut self, time: SystemTime) -> CrlBuilder
+    {
+        self.this_update = time;
+        self
+    }
+
+    pub fn next_update(mut self, time: SystemTime) -> CrlBuilder
+    {
+        self.next_update = time;
+        self
+    }
+
+    pub fn number(mut self, number: u64) -> CrlBuilder
+    {
+        self.number = Some(number);
+        self
+    }
+
+    /// Revokes the certificate with the serial number, given as the content bytes of its DER INTEGER
+    ///
+    /// This is what `Certificate::serial_number` returns. Other bytes are taken as an unsigned big-endian number, and
+    /// the entry reads back as its DER content instead, such as `[0x00, 0xFF]` for `[0xFF]`.
+    pub fn revoke(mut self, serial_number: &[u8], revocation_date: SystemTime, reason: Option<RevocationReason>) -> CrlBuilder
+    {
+        self.entries.push(RevokedCertificate {
+            serial_number: serial_number.to_vec(),
+            revocation_date: revocation_date,
+            reason: reason,
+        });
+        self
+    }
+
+    pub fn sign(&self, issuer: &Certificate, issuer_key: &mut pkey::PrivateKey, hash: pkey::HashAlgorithm) -> NSSResult<Crl>
+    {
+        try!(::nss::init());
+
+        let mut fields = vec![
+            der::small_integer(1),
+            try!(encode_algorithm(try!(issuer_key.signature_algorithm(hash)))),
+            issuer.subject().to_der(),
+            try!(encode_time(self.this_update)),
+            try!(encode_time(self.next_update)),
+        ];
+
+        if !self.entries.is_empty() {
+            let mut entries = Vec::new();
+            for entry in &self.entries
+            {
+                let mut entry_fields = vec![der::integer(&entry.serial_number), try!(encode_time(entry.revocation_date))];
+                if let Some(reason) = entry.reason {
+                    let extension = Extension::new(OID_REASON_CODE, false, &der::encode(der::ENUMERATED, &[reason.to_ffi()]));
+                    entry_fields.push(der::sequence(&[try!(extension.to_der())]));
+                }
+                entries.push(der::sequence(&entry_fields));
+            }
+            fields.push(der::sequence(&entries));
+        }
+
+        let mut extensions = Vec::new();
+        if let Some(key_id) = try!(issuer.subject_key_id()) {
+            extensions.push(try!(Extension::authority_key_identifier(&key_id).to_der()));
+        }
+        if let Some(number) = self.number {
+            extensions.push(try!(Extension::new(OID_CRL_NUMBER, false, &der::small_integer(number)).to_der()));
+        }
+        if !extensions.is_empty() {
+            fields.push(der::encode(0xA0, &der::sequence(&extensions)));
+        }
+
+        let signed = try!(super::sign_der(&der::sequence(&fields), issuer_key, hash));
+        Crl::from_der(&signed)
+    }
+}
+
+impl Default for CrlBuilder
+{
+    fn default() -> CrlBuilder
+    {
+        CrlBuilder::new()
+    }
+}
+
+static OID_CRL_NUMBER : &'static str = "2.5.29.20";
+static OID_REASON_CODE : &'static str = "2.5.29.21";
+
+fn decode_time(item: &sec::SECItemFFI) -> NSSResult<SystemTime>
+{
+    let mut time = 0;
+    unsafe { try!(cert::DER_DecodeTimeChoice(&mut time, item).to_result()) };
+    Ok(super::from_prtime(time))
+}
+
+/// Encodes a UTCTime before 2050 and a GeneralizedTime after, as RFC 5280 requires
+fn encode_time(time: SystemTime) -> NSSResult<Vec<u8>>
+{
+    let mut arena = try!(super::new_arena());
+    let mut item = sec::SECItem::empty();
+    unsafe { try!(cert::DER_EncodeTimeChoice(arena.get_mut(), item.get_mut(), super::to_prtime(time)).to_result()) };
+
+    // NSS only returns the content, the format tells which of both it is
+    let content = item.get().as_slice();
+    let tag = match content.len()
+    {
+        13 => 0x17,
+        _ => 0x18,
+    };
+    Ok(der::encode(tag, content))
+}
+
+fn encode_algorithm(tag: cert::SECOidTag) -> NSSResult<Vec<u8>>
+{
+    let mut arena = try!(super::new_arena());
+    let mut algorithm: cert::SECAlgorithmID = unsafe { mem::zeroed() };
+    let mut item = sec::SECItem::empty();
+    unsafe
+    {
+        try!(cert::SECOID_SetAlgorithmID(arena.get_mut(), &mut algorithm, tag, ptr::null_mut()).to_result());
+        let template = cert::NSS_Get_SECOID_AlgorithmIDTemplate(ptr::null_mut(), PRBool::False);
+        if cert::SEC_ASN1EncodeItem(arena.get_mut(), item.get_mut(), &algorithm as *const _ as *const c_void, template).is_null() {
+            return Err(::ffi::nspr::get_error_code());
+        }
+    }
+    Ok(item.get().as_slice().to_vec())
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::{BasicConstraints, Certificate, Extension, KeyUsage, NameBuilder};
+    use cert::builder::CertificateBuilder;
+    use cert::db::test::TempDir;
+    use cert::verify::{Revocation, Usage, Verifier, VerifyError};
+    use crypto::pkey::{Curve, HashAlgorithm, PrivateKey};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    struct Pki
+    {
+        root: Certificate,
+        root_key: PrivateKey,
+        intermediate: Certificate,
+        intermediate_key: PrivateKey,
+        leaf: Certificate,
+        other_leaf: Certificate,
+    }
+
+    fn pki(name: &str) -> Pki
+    {
+        let ca_extensions = |builder: CertificateBuilder| {
+            builder.extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: None }))
+                   .extension(Extension::key_usage(&[KeyUsage::KeyCertSign, KeyUsage::CRLSign]))
+        };
+
+        let mut root_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let root = ca_extensions(CertificateBuilder::new(NameBuilder::new().common_name(&format!("{} root", name))))
+                    .self_signed(&mut root_key, HashAlgorithm::SHA256).unwrap();
+        let mut intermediate_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let intermediate = ca_extensions(CertificateBuilder::new(NameBuilder::new().common_name(&format!("{} intermediate", name))))
+                            .sign(&intermediate_key.public_key().unwrap(), &root, &mut root_key, HashAlgorithm::SHA256).unwrap();
+
+        let leaf_key = PrivateKey::gen_ec(Curve::P256).unwrap().public_key().unwrap();
+        let leaf = CertificateBuilder::new(NameBuilder::new().common_name(&format!("{} leaf", name)))
+                    .serial_number(&[0x01, 0x02])
+                    .sign(&leaf_key, &intermediate, &mut intermediate_key, HashAlgorithm::SHA256).unwrap();
+        let other_leaf = CertificateBuilder::new(NameBuilder::new().common_name(&format!("{} other leaf", name)))
+                          .serial_number(&[0x03])
+                          .sign(&leaf_key, &intermediate, &mut intermediate_key, HashAlgorithm::SHA256).unwrap();
+
+        Pki { root: root, root_key: root_key, intermediate: intermediate, intermediate_key: intermediate_key,
+              leaf: leaf, other_leaf: other_leaf }
+    }
+
+    #[test]
+    fn build_and_parse()
+    {
+        let mut pki = pki("nss-rs crl");
+        let this_update = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let next_update = UNIX_EPOCH + Duration::from_secs(2_600_000_000);
+        let revoked_at = UNIX_EPOCH + Duration::from_secs(1_690_000_000);
+
+        let crl = CrlBuilder::new()
+                    .this_update(this_update)
+                    .next_update(next_update)
+                    .number(42)
+                    .revoke(&[0x01, 0x02], revoked_at, Some(RevocationReason::KeyCompromise))
+                    .revoke(&[0xFF], revoked_at, None)
+                    .sign(&pki.intermediate, &mut pki.intermediate_key, HashAlgorithm::SHA256).unwrap();
+
+        assert_eq!(pki.intermediate.subject().to_string(), crl.issuer().to_string());
+        assert_eq!(this_update, crl.this_update().unwrap());
+        assert_eq!(Some(next_update), crl.next_update().unwrap());
+        assert_eq!(Some(42), crl.number().unwrap());
+        assert!(crl.verify(&pki.intermediate).is_ok());
+        assert!(crl.verify(&pki.root).is_err());
+
+        let entries = crl.entries().unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(vec![0x00, 0xFF], entries[1].serial_number);
+        assert_eq!(None, entries[1].reason);
+
+        let revoked = crl.lookup(&pki.leaf).unwrap().unwrap();
+        assert_eq!(vec![0x01, 0x02], revoked.serial_number);
+        assert_eq!(revoked_at, revoked.revocation_date);
+        assert_eq!(Some(RevocationReason::KeyCompromise), revoked.reason);
+        assert_eq!(None, crl.lookup(&pki.other_leaf).unwrap());
+        assert_eq!(None, crl.lookup(&pki.intermediate).unwrap());
+
+        let parsed = Crl::from_pem(&crl.to_pem().unwrap()).unwrap();
+        assert_eq!(crl.to_der(), parsed.to_der());
+        assert!(Crl::from_der(b"not a crl").is_err());
+
+        let empty = CrlBuilder::new().sign(&pki.root, &mut pki.root_key, HashAlgorithm::SHA256).unwrap();
+        assert!(empty.entries().unwrap().is_empty());
+        assert_eq!(None, empty.number().unwrap());
+    }
+
+    #[test]
+    fn cache()
+    {
+        let mut pki = pki("nss-rs crl cache");
+        let verifier = Verifier::new(Usage::EmailSigning).trust_anchor(&pki.root).intermediate(&pki.intermediate)
+                        .revocation(Revocation::HardFail);
+        assert!(verifier.verify(&pki.other_leaf).is_err());
+
+        let now = SystemTime::now();
+        let root_crl = CrlBuilder::new().this_update(now - Duration::from_secs(60)).number(1)
+                        .sign(&pki.root, &mut pki.root_key, HashAlgorithm::SHA256).unwrap();
+        let crl = CrlBuilder::new().this_update(now - Duration::from_secs(60)).number(1)
+                    .revoke(pki.leaf.serial_number(), now - Duration::from_secs(120), Some(RevocationReason::Superseded))
+                    .sign(&pki.intermediate, &mut pki.intermediate_key, HashAlgorithm::SHA256).unwrap();
+        let cached = (root_crl.cache().unwrap(), crl.cache().unwrap());
+
+        assert!(verifier.verify(&pki.other_leaf).is_ok());
+        assert_eq!(Err(VerifyError::Revoked), verifier.verify(&pki.leaf).map(|_| ()));
+        assert!(Verifier::new(Usage::EmailSigning).trust_anchor(&pki.root).intermediate(&pki.intermediate)
+                .verify(&pki.leaf).is_ok());
+
+        drop(cached);
+        assert!(verifier.verify(&pki.leaf).is_err());
+        assert!(verifier.verify(&pki.other_leaf).is_err());
+    }
+
+    #[test]
+    fn import()
+    {
+        let mut pki = pki("nss-rs crl import");
+        let dir = TempDir::new("crl");
+        let mut db = dir.open("nss-rs crl");
+        // The issuers verify the CRLs when they are imported
+        db.add(&pki.root, "root").unwrap();
+        db.add(&pki.intermediate, "intermediate").unwrap();
+
+        let now = SystemTime::now();
+        CrlBuilder::new().this_update(now - Duration::from_secs(60)).number(1)
+            .sign(&pki.root, &mut pki.root_key, HashAlgorithm::SHA256).unwrap()
+            .import(&mut db).unwrap();
+        CrlBuilder::new().this_update(now - Duration::from_secs(60)).number(1)
+            .revoke(pki.leaf.serial_number(), now - Duration::from_secs(120), Some(RevocationReason::KeyCompromise))
+            .sign(&pki.intermediate, &mut pki.intermediate_key, HashAlgorithm::SHA256).unwrap()
+            .import(&mut db).unwrap();
+
+        let verifier = Verifier::new(Usage::EmailSigning).trust_anchor(&pki.root).intermediate(&pki.intermediate)
+                        .revocation(Revocation::HardFail);
+        assert!(verifier.verify(&pki.other_leaf).is_ok());
+        assert_eq!(Err(VerifyError::Revoked), verifier.verify(&pki.leaf).map(|_| ()));
+    }
+}
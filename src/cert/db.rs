@@ -0,0 +1,403 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, pk11, sec};
+use ffi::nspr::PRBool;
+use crypto::pkey;
+use super::{Certificate, Name};
+use std::ffi::{CStr, CString};
+use std::{fmt, ptr};
+use std::time::SystemTime;
+use libc::c_uint;
+
+/// Trust settings of a certificate, written as for `certutil -t`
+///
+/// The three comma separated fields hold the trust for TLS, email and object signing, for example "CT,C,C".
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Trust
+{
+    ssl: c_uint,
+    email: c_uint,
+    object_signing: c_uint,
+}
+
+impl Trust
+{
+    pub fn parse(trust: &str) -> NSSResult<Trust>
+    {
+        try!(::nss::init());
+
+        let trust = try!(c_string(trust));
+        let mut result = cert::CERTCertTrust::default();
+        unsafe { try!(cert::CERT_DecodeTrustString(&mut result, trust.as_ptr()).to_result()) };
+        Ok(Trust::from_ffi(result))
+    }
+
+    /// A CA trusted to issue certificates for all uses, "CT,C,C"
+    pub fn ca() -> Trust
+    {
+        let flags = cert::CERTDB_VALID_CA | cert::CERTDB_TRUSTED_CA;
+        Trust { ssl: flags | cert::CERTDB_TRUSTED_CLIENT_CA, email: flags, object_signing: flags }
+    }
+
+    /// A certificate that is trusted itself for all uses, "P,P,P"
+    pub fn peer() -> Trust
+    {
+        let flags = cert::CERTDB_TERMINAL_RECORD | cert::CERTDB_TRUSTED;
+        Trust { ssl: flags, email: flags, object_signing: flags }
+    }
+
+    pub(crate) fn from_ffi(trust: cert::CERTCertTrust) -> Trust
+    {
+        Trust { ssl: trust.ssl_flags, email: trust.email_flags, object_signing: trust.object_signing_flags }
+    }
+
+    pub(crate) fn to_ffi(&self) -> cert::CERTCertTrust
+    {
+        cert::CERTCertTrust { ssl_flags: self.ssl, email_flags: self.email, object_signing_flags: self.object_signing }
+    }
+}
+
+impl fmt::Display for Trust
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error>
+    {
+        // The same letters and order as certutil uses
+        let letters = |flags: c_uint| {
+            let mut result = String::new();
+            if flags & cert::CERTDB_VALID_CA != 0 && flags & (cert::CERTDB_TRUSTED_CA | cert::CERTDB_TRUSTED_CLIENT_CA) == 0 {
+                result.push('c');
+            }
+            if flags & cert::CERTDB_TERMINAL_RECORD != 0 && flags & cert::CERTDB_TRUSTED == 0 {
+                result.push('p');
+            }
+            for &(flag, letter) in &[(cert::CERTDB_TRUSTED_CA, 'C'), (cert::CERTDB_TRUSTED_CLIENT_CA, 'T'), (cert::CERTDB_TRUSTED, 'P'),
+                                     (cert::CERTDB_USER, 'u'), (cert::CERTDB_SEND_WARN, 'w'), (cert::CERTDB_INVISIBLE_CA, 'I'),
+                                     (cert::CERTDB_GOVT_APPROVED_CA, 'G')]
+            {
+                if flags & flag != 0 {
+                    result.push(letter);
+                }
+            }
+            result
+        };
+        write!(formatter, "{},{},{}", letters(self.ssl), letters(self.email), letters(self.object_signing))
+    }
+}
+
+/// A certificate and key database, as managed with certutil
+pub struct Database
+{
+    pub(crate) slot: pk11::SlotInfo,
+    user_db: bool,
+}
+
+impl Database
+{
+    /// The database NSS was initialized with, see `init_with_db`
+    pub fn internal() -> NSSResult<Database>
+    {
+        try!(::nss::init());
+
+        let slot = try!(pk11::SlotInfo::get_internal());
+        Ok(Database { slot: slot, user_db: false })
+    }
+
+    /// Opens the database in the directory next to the one NSS was initialized with, creating it if needed.
+    ///
+    /// A new database gets an empty password. The database is closed again when this is dropped.
+    pub fn open(config_dir: &str, name: &str) -> NSSResult<Database>
+    {
+        try!(::nss::init());
+
+        if config_dir.contains('\'') || name.contains('\'') {
+            return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+        }
+        let spec = try!(c_string(&format!("configdir='{}' tokenDescription='{}'", config_dir, name)));
+        let mut slot = try!(pk11::SlotInfo::open_user_db(&spec));
+        unsafe
+        {
+            if let PRBool::True = pk11::PK11_NeedUserInit(slot.get_mut()) {
+                let empty = c_string("").unwrap();
+                try!(pk11::PK11_InitPin(slot.get_mut(), empty.as_ptr(), empty.as_ptr()).to_result());
+            }
+        }
+        Ok(Database { slot: slot, user_db: true })
+    }
+
+    /// Logs in to a database that is protected with a password, which private keys need
+    pub fn login(&mut self, password: &str) -> NSSResult<()>
+    {
+        let password = try!(c_string(password));
+        unsafe { pk11::PK11_CheckUserPassword(self.slot.get_mut(), password.as_ptr()).to_result() }
+    }
+
+    pub fn token_name(&self) -> String
+    {
+        unsafe
+        {
+            let name = pk11::PK11_GetTokenName(self.slot.get() as *mut _);
+            String::from_utf8_lossy(CStr::from_ptr(name).to_bytes()).into_owned()
+        }
+    }
+
+    /// All certificates in the database, like `certutil -L`
+    pub fn certificates(&self) -> NSSResult<Vec<Certificate>>
+    {
+        let list = unsafe { try!(cert::CertList::wrap(cert::PK11_ListCertsInSlot(self.slot.get() as *mut _))) };
+        super::from_cert_list(&list)
+    }
+
+    pub fn find(&self, nickname: &str) -> NSSResult<Option<Certificate>>
+    {
+        // Certificates on other tokens than the internal one are addressed with the token name
+        let nickname = match unsafe { pk11::PK11_IsInternalKeySlot(self.slot.get() as *mut _) }
+        {
+            PRBool::True => nickname.to_string(),
+            PRBool::False => format!("{}:{}", self.token_name(), nickname),
+        };
+        let found = unsafe { cert::PK11_FindCertFromNickname(try!(c_string(&nickname)).as_ptr(), ptr::null_mut()) };
+        if found.is_null() {
+            return Ok(None);
+        }
+
+        // NSS keeps finding renamed certificates by their old nickname as well
+        let certificate = try!(Certificate::from_raw(found));
+        match certificate.nickname()
+        {
+            Some(ref found_nickname) if *found_nickname == nickname => Ok(Some(certificate)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Stores the certificate in the database under the nickname, like `certutil -A`
+    pub fn add(&mut self, certificate: &Certificate, nickname: &str) -> NSSResult<()>
+    {
+        let nickname = try!(c_string(nickname));
+        unsafe
+        {
+            cert::PK11_ImportCert(self.slot.get_mut(), certificate.cert.get() as *mut _, pk11::CK_INVALID_HANDLE,
+                                  nickname.as_ptr(), PRBool::False).to_result()
+        }
+    }
+
+    /// Removes the certificate from the database, leaving its private key; like `certutil -D`
+    pub fn delete(&mut self, certificate: &Certificate) -> NSSResult<()>
+    {
+        unsafe { cert::SEC_DeletePermCertificate(certificate.cert.get() as *mut _).to_result() }
+    }
+
+    /// Changes the nickname of a certificate in the database, like `certutil --rename`
+    pub fn rename(&mut self, certificate: &Certificate, nickname: &str) -> NSSResult<()>
+    {
+        let nickname = try!(c_string(nickname));
+        unsafe
+        {
+            try!(cert::__PK11_SetCertificateNickname(certificate.cert.get() as *mut _, nickname.as_ptr()).to_result());
+            // Looking the certificate up on the token again updates the nickname NSS keeps in memory
+            try!(Certificate::from_raw(cert::PK11_FindCertFromDERCertItem(self.slot.get_mut(), &certificate.raw().der_cert,
+                                                                          ptr::null_mut())));
+        }
+        Ok(())
+    }
+
+    /// Stores a PKCS#8 encoded private key in the database
+    pub fn import_private_key(&mut self, data: &[u8]) -> NSSResult<pkey::PrivateKey>
+    {
+        let mut der = sec::SECItem::from_buf(data);
+        let mut key = ptr::null_mut();
+        unsafe
+        {
+            try!(pk11::PK11_ImportDERPrivateKeyInfoAndReturnKey(self.slot.get_mut(), der.get_mut(), ptr::null_mut(),
+                                                                ptr::null_mut(), PRBool::True, PRBool::True, pk11::KU_ALL,
+                                                                &mut key, ptr::null_mut()).to_result());
+        }
+        pkey::PrivateKey::from_raw(key)
+    }
+}
+
+impl Drop for Database
+{
+    fn drop(&mut self)
+    {
+        if self.user_db {
+            unsafe { let _ = pk11::SECMOD_CloseUserDB(self.slot.get_mut()); }
+        }
+    }
+}
+
+/// The certificates with the subject in all open databases, the newest first
+pub fn find_by_subject(subject: &Name) -> NSSResult<Vec<Certificate>>
+{
+    try!(::nss::init());
+
+    let name = sec::SECItem::from_buf(subject.der);
+    let time = super::to_prtime(SystemTime::now());
+    let list = unsafe
+    {
+        cert::CERT_CreateSubjectCertList(ptr::null_mut(), cert::CERT_GetDefaultCertDB(), name.get(), time, PRBool::False)
+    };
+    match list.is_null()
+    {
+        true => Ok(Vec::new()),
+        false => super::from_cert_list(&try!(cert::CertList::wrap(list))),
+    }
+}
+
+/// The certificates for the email address in all open databases
+pub fn find_by_email(email: &str) -> NSSResult<Vec<Certificate>>
+{
+    try!(::nss::init());
+
+    let email = try!(c_string(email));
+    let list = unsafe { cert::PK11_FindCertsFromEmailAddress(email.as_ptr(), ptr::null_mut()) };
+    match list.is_null()
+    {
+        true => Ok(Vec::new()),
+        false => super::from_cert_list(&try!(cert::CertList::wrap(list))),
+    }
+}
+
+/// The certificate for the private key, if a database has one
+pub fn find_by_key(key: &mut pkey::PrivateKey) -> NSSResult<Option<Certificate>>
+{
+    let found = unsafe { cert::PK11_GetCertFromPrivateKey(key.get_mut()) };
+    match found.is_null()
+    {
+        true => Ok(None),
+        false => Certificate::from_raw(found).map(Some),
+    }
+}
+
+/// The private key for the certificate, if a database has one
+pub fn find_private_key(certificate: &Certificate) -> NSSResult<Option<pkey::PrivateKey>>
+{
+    let found = unsafe { cert::PK11_FindKeyByAnyCert(certificate.cert.get() as *mut _, ptr::null_mut()) };
+    match found.is_null()
+    {
+        true => Ok(None),
+        false => pkey::PrivateKey::from_raw(found).map(Some),
+    }
+}
+
+pub(crate) fn c_string(value: &str) -> NSSResult<CString>
+{
+    CString::new(value).map_err(|_| NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS))
+}
+
+#[cfg(test)]
+pub(crate) mod test
+{
+    use super::*;
+    use cert::{BasicConstraints, Extension, GeneralName, KeyUsage, NameBuilder};
+    use cert::builder::CertificateBuilder;
+    use crypto::pkey::{Curve, HashAlgorithm, PrivateKey};
+    use std::path::PathBuf;
+    use std::{env, fs, process};
+
+    /// A temporary directory for databases, which is removed when it is dropped, even when a test fails
+    ///
+    /// Databases in it should be dropped first, so they are declared after it.
+    pub struct TempDir(PathBuf);
+
+    impl TempDir
+    {
+        /// An empty directory, whose name is unique to the test process
+        pub fn new(name: &str) -> TempDir
+        {
+            let dir = env::temp_dir().join(format!("nss-rs-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        pub fn config_dir(&self) -> String
+        {
+            format!("sql:{}", self.0.display())
+        }
+
+        /// Opens the database in the directory, creating it the first time
+        pub fn open(&self, name: &str) -> Database
+        {
+            Database::open(&self.config_dir(), name).unwrap()
+        }
+    }
+
+    impl Drop for TempDir
+    {
+        fn drop(&mut self)
+        {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn trust()
+    {
+        assert_eq!("CT,C,C", Trust::ca().to_string());
+        assert_eq!("P,P,P", Trust::peer().to_string());
+        assert_eq!(Trust::ca(), Trust::parse("CT,C,C").unwrap());
+        assert_eq!("c,p,", Trust::parse("c,p,").unwrap().to_string());
+        assert_eq!(",,", Trust::default().to_string());
+        assert!(Trust::parse("X,,").is_err());
+    }
+
+    #[test]
+    fn database()
+    {
+        let dir = TempDir::new("db");
+
+        let mut root_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let root = CertificateBuilder::new(NameBuilder::new().common_name("nss-rs db root"))
+                    .extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: None }))
+                    .extension(Extension::key_usage(&[KeyUsage::KeyCertSign]))
+                    .self_signed(&mut root_key, HashAlgorithm::SHA256).unwrap();
+        let mut leaf_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let leaf = CertificateBuilder::new(NameBuilder::new().common_name("nss-rs db leaf"))
+                    .subject_alt_names(&[GeneralName::Email("db@example.com".to_string())])
+                    .sign(&leaf_key.public_key().unwrap(), &root, &mut root_key, HashAlgorithm::SHA256).unwrap();
+
+        {
+            let mut db = dir.open("nss-rs test");
+            assert_eq!("nss-rs test", db.token_name());
+            assert!(db.certificates().unwrap().is_empty());
+
+            db.add(&root, "root").unwrap();
+            db.add(&leaf, "leaf").unwrap();
+            let mut stored_key = db.import_private_key(&leaf_key.save().unwrap()).unwrap();
+            assert_eq!(2, db.certificates().unwrap().len());
+
+            let found = db.find("leaf").unwrap().unwrap();
+            assert_eq!(leaf.to_der(), found.to_der());
+            // Like certutil, NSS prefixes nicknames with the token name outside the internal database
+            assert_eq!(Some("nss-rs test:leaf".to_string()), found.nickname());
+            assert!(db.find("missing").unwrap().is_none());
+
+            db.rename(&found, "renamed").unwrap();
+            assert!(db.find("leaf").unwrap().is_none());
+            assert_eq!(leaf.to_der(), db.find("renamed").unwrap().unwrap().to_der());
+
+            let found_root = db.find("root").unwrap().unwrap();
+            found_root.set_trust(&Trust::ca()).unwrap();
+            assert_eq!(Some(Trust::ca()), db.find("root").unwrap().unwrap().trust());
+
+            assert!(find_by_subject(&root.subject()).unwrap().iter().any(|c| c.to_der() == root.to_der()));
+            assert!(find_by_email("db@example.com").unwrap().iter().any(|c| c.to_der() == leaf.to_der()));
+            let mut key = find_private_key(&leaf).unwrap().unwrap();
+            assert_eq!(leaf.to_der(), find_by_key(&mut key).unwrap().unwrap().to_der());
+            assert_eq!(stored_key.public_key().unwrap().save().unwrap(), key.public_key().unwrap().save().unwrap());
+            assert!(find_private_key(&root).unwrap().is_none());
+        }
+
+        {
+            // The changes are stored
+            let mut db = dir.open("nss-rs test");
+            let names: Vec<_> = db.certificates().unwrap().iter().map(|c| c.nickname().unwrap()).collect();
+            assert_eq!(2, names.len());
+            assert!(names.contains(&"nss-rs test:renamed".to_string()));
+
+            let found = db.find("renamed").unwrap().unwrap();
+            db.delete(&found).unwrap();
+            assert!(db.find("renamed").unwrap().is_none());
+            assert_eq!(1, db.certificates().unwrap().len());
+        }
+    }
+}
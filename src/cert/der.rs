@@ -0,0 +1,76 @@
+// Minimal DER encoding, for the extensions NSS only offers encoders through ASN.1 templates for
+
+pub const BOOLEAN : u8 = 0x01;
+pub const INTEGER : u8 = 0x02;
+pub const BIT_STRING : u8 = 0x03;
+pub const OCTET_STRING : u8 = 0x04;
+pub const OID : u8 = 0x06;
+pub const ENUMERATED : u8 = 0x0A;
+pub const SEQUENCE : u8 = 0x30;
+
+pub fn encode(tag: u8, content: &[u8]) -> Vec<u8>
+{
+    let mut result = vec![tag];
+    match content.len()
+    {
+        len if len < 0x80 => result.push(len as u8),
+        len => {
+            let bytes: Vec<u8> = (0..8).rev().map(|i| (len >> (i * 8)) as u8).skip_while(|&b| b == 0).collect();
+            result.push(0x80 | bytes.len() as u8);
+            result.extend(bytes);
+        },
+    }
+    result.extend(content);
+    result
+}
+
+pub fn sequence(items: &[Vec<u8>]) -> Vec<u8>
+{
+    encode(SEQUENCE, &items.concat())
+}
+
+pub fn boolean(value: bool) -> Vec<u8>
+{
+    encode(BOOLEAN, &[if value { 0xFF } else { 0x00 }])
+}
+
+/// Encodes a non-negative integer given as big-endian bytes
+pub fn integer(value: &[u8]) -> Vec<u8>
+{
+    encode(INTEGER, &integer_content(value))
+}
+
+/// The minimal content octets of a non-negative integer
+pub fn integer_content(value: &[u8]) -> Vec<u8>
+{
+    let mut content: Vec<u8> = value.iter().cloned().skip_while(|&b| b == 0).collect();
+    if content.is_empty() || content[0] & 0x80 != 0 {
+        content.insert(0, 0);
+    }
+    content
+}
+
+pub fn small_integer(value: u64) -> Vec<u8>
+{
+    let bytes: Vec<u8> = (0..8).rev().map(|i| (value >> (i * 8)) as u8).collect();
+    integer(&bytes)
+}
+
+/// Encodes a named bit list, bit 0 being the most significant bit of the first byte
+pub fn bit_string(bits: &[u8]) -> Vec<u8>
+{
+    let len = bits.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+    let unused = match len
+    {
+        0 => 0,
+        _ => bits[len - 1].trailing_zeros() as u8,
+    };
+    let mut content = vec![unused];
+    content.extend(&bits[..len]);
+    encode(BIT_STRING, &content)
+}
+
+pub fn octet_string(value: &[u8]) -> Vec<u8>
+{
+    encode(OCTET_STRING, value)
+}
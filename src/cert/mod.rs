@@ -0,0 +1,1052 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, sec};
+use ffi::nspr::PRBool;
+use crypto::pkey;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{mem, ptr};
+use libc::{c_char, c_int, c_void};
+
+pub mod builder;
+pub mod crl;
+pub mod db;
+pub mod ocsp;
+pub mod pkcs12;
+mod der;
+mod pem;
+pub mod request;
+pub mod verify;
+
+pub struct Certificate
+{
+    cert: cert::Certificate,
+}
+
+impl Certificate
+{
+    pub fn from_der(data: &[u8]) -> NSSResult<Certificate>
+    {
+        try!(::nss::init());
+
+        let mut der = sec::SECItem::from_buf(data);
+        let cert = unsafe
+        {
+            try!(cert::Certificate::wrap(cert::CERT_NewTempCertificate(cert::CERT_GetDefaultCertDB(), der.get_mut(),
+                                                                       ptr::null_mut(), PRBool::False, PRBool::True)))
+        };
+
+        Ok(Certificate { cert: cert })
+    }
+
+    /// Parses the first certificate of a PEM encoded package
+    pub fn from_pem(data: &str) -> NSSResult<Certificate>
+    {
+        try!(::nss::init());
+
+        let cert = unsafe
+        {
+            try!(cert::Certificate::wrap(cert::CERT_DecodeCertFromPackage(data.as_ptr() as *mut c_char, data.len() as c_int)))
+        };
+
+        Ok(Certificate { cert: cert })
+    }
+
+    pub(crate) fn from_raw(raw: *mut cert::CERTCertificate) -> NSSResult<Certificate>
+    {
+        let cert = try!(cert::Certificate::wrap(raw));
+        Ok(Certificate { cert: cert })
+    }
+
+    pub(crate) fn get(&self) -> *const cert::CERTCertificate
+    {
+        self.cert.get()
+    }
+
+    fn raw(&self) -> &cert::CERTCertificate
+    {
+        unsafe { &*self.cert.get() }
+    }
+
+    pub fn to_der(&self) -> Vec<u8>
+    {
+        self.raw().der_cert.as_slice().to_vec()
+    }
+
+    pub fn to_pem(&self) -> NSSResult<String>
+    {
+        pem::encode("CERTIFICATE", self.raw().der_cert.as_slice())
+    }
+
+    pub fn subject<'a>(&'a self) -> Name<'a>
+    {
+        Name { name: &self.raw().subject, der: self.raw().der_subject.as_slice() }
+    }
+
+    pub fn issuer<'a>(&'a self) -> Name<'a>
+    {
+        Name { name: &self.raw().issuer, der: self.raw().der_issuer.as_slice() }
+    }
+
+    /// The nickname, for certificates stored in a database
+    pub fn nickname(&self) -> Option<String>
+    {
+        match self.raw().nickname.is_null()
+        {
+            true => None,
+            false => Some(unsafe { String::from_utf8_lossy(CStr::from_ptr(self.raw().nickname).to_bytes()).into_owned() }),
+        }
+    }
+
+    /// The trust settings, if any were set
+    pub fn trust(&self) -> Option<db::Trust>
+    {
+        let mut trust = cert::CERTCertTrust::default();
+        match unsafe { cert::CERT_GetCertTrust(self.cert.get(), &mut trust).to_result() }
+        {
+            Ok(..) => Some(db::Trust::from_ffi(trust)),
+            Err(..) => None,
+        }
+    }
+
+    /// Changes the trust settings, in the database for stored certificates and in memory otherwise
+    pub fn set_trust(&self, trust: &db::Trust) -> NSSResult<()>
+    {
+        let mut trust = trust.to_ffi();
+        unsafe { cert::CERT_ChangeCertTrust(cert::CERT_GetDefaultCertDB(), self.cert.get() as *mut _, &mut trust).to_result() }
+    }
+
+    /// The serial number as big-endian bytes
+    pub fn serial_number(&self) -> &[u8]
+    {
+        self.raw().serial_number.as_slice()
+    }
+
+    pub fn not_before(&self) -> NSSResult<SystemTime>
+    {
+        let (not_before, _) = try!(self.validity());
+        Ok(not_before)
+    }
+
+    pub fn not_after(&self) -> NSSResult<SystemTime>
+    {
+        let (_, not_after) = try!(self.validity());
+        Ok(not_after)
+    }
+
+    fn validity(&self) -> NSSResult<(SystemTime, SystemTime)>
+    {
+        let mut not_before = 0;
+        let mut not_after = 0;
+        unsafe { try!(cert::CERT_GetCertTimes(self.cert.get(), &mut not_before, &mut not_after).to_result()) };
+        Ok((from_prtime(not_before), from_prtime(not_after)))
+    }
+
+    pub fn subject_alt_names(&self) -> NSSResult<Vec<GeneralName>>
+    {
+        match try!(self.find_extension(cert::SEC_OID_X509_SUBJECT_ALT_NAME))
+        {
+            Some(encoded) => GeneralName::decode_list(&encoded),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// The key usage, or None when the extension is absent
+    pub fn key_usage(&self) -> Option<Vec<KeyUsage>>
+    {
+        let raw = self.raw();
+        match raw.key_usage_present
+        {
+            PRBool::False => None,
+            PRBool::True => Some(KeyUsage::all().iter()
+                                 .filter(|usage| raw.raw_key_usage & usage.to_ffi() != 0)
+                                 .cloned()
+                                 .collect()),
+        }
+    }
+
+    /// The extended key usage, or None when the extension is absent
+    pub fn extended_key_usage(&self) -> NSSResult<Option<Vec<ExtendedKeyUsage>>>
+    {
+        let encoded = match try!(self.find_extension(cert::SEC_OID_X509_EXT_KEY_USAGE))
+        {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+
+        let item = sec::SECItem::from_buf(&encoded);
+        let sequence = unsafe { try!(cert::OidSequence::wrap(cert::CERT_DecodeOidSequence(item.get()))) };
+
+        let mut result = Vec::new();
+        unsafe
+        {
+            let mut oid = (*sequence.get()).oids;
+            while !oid.is_null() && !(*oid).is_null() {
+                result.push(ExtendedKeyUsage::from_ffi(&**oid));
+                oid = oid.offset(1);
+            }
+        }
+
+        Ok(Some(result))
+    }
+
+    /// The basic constraints, or None when the extension is absent
+    pub fn basic_constraints(&self) -> NSSResult<Option<BasicConstraints>>
+    {
+        let encoded = match try!(self.find_extension(cert::SEC_OID_X509_BASIC_CONSTRAINTS))
+        {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+
+        let item = sec::SECItem::from_buf(&encoded);
+        let mut value = cert::CERTBasicConstraints { is_ca: PRBool::False, path_len_constraint: 0 };
+        unsafe { try!(cert::CERT_DecodeBasicConstraintValue(&mut value, item.get()).to_result()) };
+
+        let path_len = match value.path_len_constraint
+        {
+            cert::CERT_UNLIMITED_PATH_CONSTRAINT => None,
+            len => Some(len as u32),
+        };
+        Ok(Some(BasicConstraints
+        {
+            ca: match value.is_ca { PRBool::True => true, PRBool::False => false },
+            path_len: match value.is_ca { PRBool::True => path_len, PRBool::False => None },
+        }))
+    }
+
+    /// The subject key identifier extension, if the certificate has one
+    pub fn subject_key_id(&self) -> NSSResult<Option<Vec<u8>>>
+    {
+        let mut item = sec::SECItem::empty();
+        match unsafe { cert::CERT_FindSubjectKeyIDExtension(self.cert.get() as *mut _, item.get_mut()).to_result() }
+        {
+            Ok(..) => Ok(Some(item.take_buf())),
+            Err(NSSError::NSS(sec::SEC_ERROR_EXTENSION_NOT_FOUND)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The key identifier from the authority key identifier extension
+    pub fn authority_key_id(&self) -> NSSResult<Option<Vec<u8>>>
+    {
+        let encoded = match try!(self.find_extension(cert::SEC_OID_X509_AUTH_KEY_ID))
+        {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+
+        let mut arena = try!(new_arena());
+        let item = sec::SECItem::from_buf(&encoded);
+        let key_id = unsafe
+        {
+            let auth_key_id = cert::CERT_DecodeAuthKeyID(arena.get_mut(), item.get());
+            if auth_key_id.is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+            (*auth_key_id).key_id.as_slice().to_vec()
+        };
+
+        match key_id.is_empty()
+        {
+            true => Ok(None),
+            false => Ok(Some(key_id)),
+        }
+    }
+
+    /// The full names of all CRL distribution points
+    pub fn crl_distribution_points(&self) -> NSSResult<Vec<GeneralName>>
+    {
+        let encoded = match try!(self.find_extension(cert::SEC_OID_X509_CRL_DIST_POINTS))
+        {
+            Some(encoded) => encoded,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut arena = try!(new_arena());
+        let mut item = sec::SECItem::from_buf(&encoded);
+        let mut result = Vec::new();
+        unsafe
+        {
+            let points = cert::CERT_DecodeCRLDistributionPoints(arena.get_mut(), item.get_mut());
+            if points.is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+
+            let mut current = (*points).dist_points;
+            while !current.is_null() && !(*current).is_null()
+            {
+                let point = &**current;
+                if point.dist_point_type == cert::DIST_POINT_GENERAL_NAME && !point.full_name.is_null() {
+                    result.extend(GeneralName::from_ffi_list(point.full_name));
+                }
+                current = current.offset(1);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Hash of the DER encoding of the certificate
+    pub fn fingerprint(&self, hash: pkey::HashAlgorithm) -> NSSResult<Vec<u8>>
+    {
+        let der = self.raw().der_cert.as_slice();
+        let mut out = vec![0; hash.output_len()];
+        unsafe { try!(cert::PK11_HashBuf(hash.to_oid_tag(), out.as_mut_ptr(), der.as_ptr(), der.len() as i32).to_result()) };
+        Ok(out)
+    }
+
+    pub fn public_key(&mut self) -> NSSResult<pkey::PublicKey>
+    {
+        pkey::PublicKey::from_raw(unsafe { cert::CERT_ExtractPublicKey(self.cert.get_mut()) })
+    }
+
+    pub fn rsa_public_key(&mut self) -> NSSResult<pkey::RSAPublicKey>
+    {
+        try!(self.public_key()).into_rsa()
+    }
+
+    fn find_extension(&self, tag: cert::SECOidTag) -> NSSResult<Option<Vec<u8>>>
+    {
+        let mut item = sec::SECItem::empty();
+        match unsafe { cert::CERT_FindCertExtension(self.cert.get(), tag, item.get_mut()).to_result() }
+        {
+            Ok(..) => Ok(Some(item.take_buf())),
+            Err(NSSError::NSS(sec::SEC_ERROR_EXTENSION_NOT_FOUND)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Clone for Certificate
+{
+    fn clone(&self) -> Certificate
+    {
+        let cert = unsafe { cert::CERT_DupCertificate(self.cert.get() as *mut _) };
+        Certificate::from_raw(cert).unwrap()
+    }
+}
+
+/// A distinguished name
+pub struct Name<'a>
+{
+    name: &'a cert::CERTName,
+    der: &'a [u8],
+}
+
+impl<'a> Name<'a>
+{
+    pub fn common_name(&self) -> Option<String>
+    {
+        unsafe { take_string(cert::CERT_GetCommonName(self.name)) }
+    }
+
+    pub fn to_der(&self) -> Vec<u8>
+    {
+        self.der.to_vec()
+    }
+}
+
+impl<'a> fmt::Display for Name<'a>
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error>
+    {
+        match unsafe { take_string(cert::CERT_NameToAscii(self.name)) }
+        {
+            Some(name) => formatter.write_str(&name),
+            None => Err(fmt::Error),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GeneralName
+{
+    DNS(String),
+    IP(IpAddr),
+    Email(String),
+    URI(String),
+    /// The DER encoding of the name
+    Directory(Vec<u8>),
+    /// Any other type, as the DER encoding of its value
+    Other(Vec<u8>),
+}
+
+impl GeneralName
+{
+    fn decode_list(encoded: &[u8]) -> NSSResult<Vec<GeneralName>>
+    {
+        let mut arena = try!(new_arena());
+        let mut item = sec::SECItem::from_buf(encoded);
+        let head = unsafe { cert::CERT_DecodeAltNameExtension(arena.get_mut(), item.get_mut()) };
+        if head.is_null() {
+            return Err(::ffi::nspr::get_error_code());
+        }
+
+        Ok(GeneralName::from_ffi_list(head))
+    }
+
+    // NSS keeps general names in a circular list
+    fn from_ffi_list(head: *mut cert::CERTGeneralName) -> Vec<GeneralName>
+    {
+        let mut result = Vec::new();
+        let mut current = head;
+        loop
+        {
+            result.push(GeneralName::from_ffi(unsafe { &*current }));
+            current = unsafe { cert::CERT_GetNextGeneralName(current) };
+            if current == head {
+                break;
+            }
+        }
+        result
+    }
+
+    fn to_der(&self) -> NSSResult<Vec<u8>>
+    {
+        let der = match *self
+        {
+            GeneralName::Email(ref email) => der::encode(0x81, email.as_bytes()),
+            GeneralName::DNS(ref dns) => der::encode(0x82, dns.as_bytes()),
+            GeneralName::Directory(ref name) => der::encode(0xA4, name),
+            GeneralName::URI(ref uri) => der::encode(0x86, uri.as_bytes()),
+            GeneralName::IP(IpAddr::V4(ref ip)) => der::encode(0x87, &ip.octets()),
+            GeneralName::IP(IpAddr::V6(ref ip)) => der::encode(0x87, &ip.octets()),
+            GeneralName::Other(..) => return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS)),
+        };
+        Ok(der)
+    }
+
+    fn from_ffi(name: &cert::CERTGeneralName) -> GeneralName
+    {
+        let value = name.name.as_slice();
+        let text = || String::from_utf8_lossy(value).into_owned();
+        match name.typ
+        {
+            cert::CERTGeneralNameType::DNSName => GeneralName::DNS(text()),
+            cert::CERTGeneralNameType::RFC822Name => GeneralName::Email(text()),
+            cert::CERTGeneralNameType::URI => GeneralName::URI(text()),
+            cert::CERTGeneralNameType::DirectoryName => GeneralName::Directory(name.der_directory_name.as_slice().to_vec()),
+            cert::CERTGeneralNameType::IPAddress if value.len() == 4 => {
+                GeneralName::IP(IpAddr::V4(Ipv4Addr::new(value[0], value[1], value[2], value[3])))
+            },
+            cert::CERTGeneralNameType::IPAddress if value.len() == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(value);
+                GeneralName::IP(IpAddr::V6(Ipv6Addr::from(octets)))
+            },
+            _ => GeneralName::Other(value.to_vec()),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum KeyUsage
+{
+    DigitalSignature,
+    NonRepudiation,
+    KeyEncipherment,
+    DataEncipherment,
+    KeyAgreement,
+    KeyCertSign,
+    CRLSign,
+    EncipherOnly,
+}
+
+impl KeyUsage
+{
+    fn all() -> &'static [KeyUsage]
+    {
+        static ALL : [KeyUsage; 8] = [KeyUsage::DigitalSignature, KeyUsage::NonRepudiation, KeyUsage::KeyEncipherment,
+                                      KeyUsage::DataEncipherment, KeyUsage::KeyAgreement, KeyUsage::KeyCertSign,
+                                      KeyUsage::CRLSign, KeyUsage::EncipherOnly];
+        &ALL
+    }
+
+    fn to_ffi(&self) -> ::libc::c_uint
+    {
+        match *self
+        {
+            KeyUsage::DigitalSignature => cert::KU_DIGITAL_SIGNATURE,
+            KeyUsage::NonRepudiation => cert::KU_NON_REPUDIATION,
+            KeyUsage::KeyEncipherment => cert::KU_KEY_ENCIPHERMENT,
+            KeyUsage::DataEncipherment => cert::KU_DATA_ENCIPHERMENT,
+            KeyUsage::KeyAgreement => cert::KU_KEY_AGREEMENT,
+            KeyUsage::KeyCertSign => cert::KU_KEY_CERT_SIGN,
+            KeyUsage::CRLSign => cert::KU_CRL_SIGN,
+            KeyUsage::EncipherOnly => cert::KU_ENCIPHER_ONLY,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ExtendedKeyUsage
+{
+    ServerAuth,
+    ClientAuth,
+    CodeSigning,
+    EmailProtection,
+    TimeStamping,
+    OCSPSigning,
+    /// Any other purpose, as a dotted OID
+    Other(String),
+}
+
+impl ExtendedKeyUsage
+{
+    /// The dotted OID of the purpose
+    pub fn oid(&self) -> String
+    {
+        match *self
+        {
+            ExtendedKeyUsage::ServerAuth => "1.3.6.1.5.5.7.3.1".to_string(),
+            ExtendedKeyUsage::ClientAuth => "1.3.6.1.5.5.7.3.2".to_string(),
+            ExtendedKeyUsage::CodeSigning => "1.3.6.1.5.5.7.3.3".to_string(),
+            ExtendedKeyUsage::EmailProtection => "1.3.6.1.5.5.7.3.4".to_string(),
+            ExtendedKeyUsage::TimeStamping => "1.3.6.1.5.5.7.3.8".to_string(),
+            ExtendedKeyUsage::OCSPSigning => "1.3.6.1.5.5.7.3.9".to_string(),
+            ExtendedKeyUsage::Other(ref oid) => oid.clone(),
+        }
+    }
+
+    fn from_ffi(oid: &sec::SECItemFFI) -> ExtendedKeyUsage
+    {
+        match unsafe { cert::SECOID_FindOIDTag(oid) }
+        {
+            cert::SEC_OID_EXT_KEY_USAGE_SERVER_AUTH => ExtendedKeyUsage::ServerAuth,
+            cert::SEC_OID_EXT_KEY_USAGE_CLIENT_AUTH => ExtendedKeyUsage::ClientAuth,
+            cert::SEC_OID_EXT_KEY_USAGE_CODE_SIGN => ExtendedKeyUsage::CodeSigning,
+            cert::SEC_OID_EXT_KEY_USAGE_EMAIL_PROTECT => ExtendedKeyUsage::EmailProtection,
+            cert::SEC_OID_EXT_KEY_USAGE_TIME_STAMP => ExtendedKeyUsage::TimeStamping,
+            cert::SEC_OID_OCSP_RESPONDER => ExtendedKeyUsage::OCSPSigning,
+            _ => ExtendedKeyUsage::Other(oid_to_string(oid.as_slice())),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct BasicConstraints
+{
+    pub ca: bool,
+    /// Maximum number of intermediate certificates below this one, None when unlimited
+    pub path_len: Option<u32>,
+}
+
+/// An X.509 extension with its DER encoded value
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Extension
+{
+    /// Dotted OID of the extension
+    pub oid: String,
+    pub critical: bool,
+    pub value: Vec<u8>,
+}
+
+impl Extension
+{
+    pub fn new(oid: &str, critical: bool, value: &[u8]) -> Extension
+    {
+        Extension { oid: oid.to_string(), critical: critical, value: value.to_vec() }
+    }
+
+    pub fn subject_alt_name(names: &[GeneralName]) -> NSSResult<Extension>
+    {
+        let names = try!(names.iter().map(|name| name.to_der()).collect::<NSSResult<Vec<_>>>());
+        Ok(Extension::new(OID_SUBJECT_ALT_NAME, false, &der::sequence(&names)))
+    }
+
+    fn to_der(&self) -> NSSResult<Vec<u8>>
+    {
+        let mut fields = vec![try!(der_oid(&self.oid))];
+        if self.critical {
+            fields.push(der::boolean(true));
+        }
+        fields.push(der::octet_string(&self.value));
+        Ok(der::sequence(&fields))
+    }
+
+    pub fn subject_key_identifier(key_id: &[u8]) -> Extension
+    {
+        Extension::new(OID_SUBJECT_KEY_ID, false, &der::octet_string(key_id))
+    }
+
+    pub fn authority_key_identifier(key_id: &[u8]) -> Extension
+    {
+        Extension::new(OID_AUTH_KEY_ID, false, &der::sequence(&[der::encode(0x80, key_id)]))
+    }
+
+    /// A single distribution point, reachable through any of the names
+    pub fn crl_distribution_points(names: &[GeneralName]) -> NSSResult<Extension>
+    {
+        let names = try!(names.iter().map(|name| name.to_der()).collect::<NSSResult<Vec<_>>>());
+        let full_name = der::encode(0xA0, &der::encode(0xA0, &names.concat()));
+        Ok(Extension::new(OID_CRL_DIST_POINTS, false, &der::sequence(&[der::sequence(&[full_name])])))
+    }
+
+    pub fn key_usage(usages: &[KeyUsage]) -> Extension
+    {
+        let bits = usages.iter().fold(0, |bits, usage| bits | usage.to_ffi());
+        Extension::new(OID_KEY_USAGE, true, &der::bit_string(&[bits as u8]))
+    }
+
+    pub fn extended_key_usage(usages: &[ExtendedKeyUsage]) -> NSSResult<Extension>
+    {
+        let oids = try!(usages.iter().map(|usage| der_oid(&usage.oid())).collect::<NSSResult<Vec<_>>>());
+        Ok(Extension::new(OID_EXT_KEY_USAGE, false, &der::sequence(&oids)))
+    }
+
+    pub fn basic_constraints(constraints: BasicConstraints) -> Extension
+    {
+        let mut fields = Vec::new();
+        if constraints.ca {
+            fields.push(der::boolean(true));
+            if let Some(path_len) = constraints.path_len {
+                fields.push(der::small_integer(path_len as u64));
+            }
+        }
+        Extension::new(OID_BASIC_CONSTRAINTS, true, &der::sequence(&fields))
+    }
+}
+
+static OID_SUBJECT_KEY_ID : &'static str = "2.5.29.14";
+static OID_KEY_USAGE : &'static str = "2.5.29.15";
+static OID_SUBJECT_ALT_NAME : &'static str = "2.5.29.17";
+static OID_BASIC_CONSTRAINTS : &'static str = "2.5.29.19";
+static OID_CRL_DIST_POINTS : &'static str = "2.5.29.31";
+static OID_AUTH_KEY_ID : &'static str = "2.5.29.35";
+static OID_EXT_KEY_USAGE : &'static str = "2.5.29.37";
+
+/// Builds a distinguished name, starting with the most significant attribute
+#[derive(Clone, Debug, Default)]
+pub struct NameBuilder
+{
+    attributes: Vec<(String, String)>,
+}
+
+impl NameBuilder
+{
+    pub fn new() -> NameBuilder
+    {
+        NameBuilder { attributes: Vec::new() }
+    }
+
+    pub fn country(self, value: &str) -> NameBuilder
+    {
+        self.attribute("C", value)
+    }
+
+    pub fn state(self, value: &str) -> NameBuilder
+    {
+        self.attribute("ST", value)
+    }
+
+    pub fn locality(self, value: &str) -> NameBuilder
+    {
+        self.attribute("L", value)
+    }
+
+    pub fn organization(self, value: &str) -> NameBuilder
+    {
+        self.attribute("O", value)
+    }
+
+    pub fn organizational_unit(self, value: &str) -> NameBuilder
+    {
+        self.attribute("OU", value)
+    }
+
+    pub fn common_name(self, value: &str) -> NameBuilder
+    {
+        self.attribute("CN", value)
+    }
+
+    pub fn email(self, value: &str) -> NameBuilder
+    {
+        self.attribute("E", value)
+    }
+
+    /// Adds an attribute by its NSS keyword (like "DC" or "UID") or by its dotted OID
+    pub fn attribute(mut self, key: &str, value: &str) -> NameBuilder
+    {
+        let key = match key.chars().next()
+        {
+            Some(c) if c.is_ascii_digit() => format!("OID.{}", key),
+            _ => key.to_string(),
+        };
+        self.attributes.push((key, value.to_string()));
+        self
+    }
+
+    fn to_ffi(&self) -> NSSResult<cert::Name>
+    {
+        let ascii = match CString::new(self.to_string())
+        {
+            Ok(ascii) => ascii,
+            Err(..) => return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS)),
+        };
+        unsafe { cert::Name::wrap(cert::CERT_AsciiToName(ascii.as_ptr())) }
+    }
+}
+
+impl fmt::Display for NameBuilder
+{
+    /// Formats the name like NSS does, least significant attribute first
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error>
+    {
+        for (i, (key, value)) in self.attributes.iter().rev().enumerate()
+        {
+            if i > 0 {
+                try!(formatter.write_str(","));
+            }
+            try!(write!(formatter, "{}=", key));
+            // Quoted, since NSS strips escaped trailing spaces
+            let quote = value.starts_with(' ') || value.starts_with('#') || value.ends_with(' ')
+                        || value.contains(|c| ",+=<>;".contains(c));
+            if quote {
+                try!(formatter.write_str("\""));
+            }
+            for c in value.chars()
+            {
+                if c == '"' || c == '\\' {
+                    try!(formatter.write_str("\\"));
+                }
+                try!(write!(formatter, "{}", c));
+            }
+            if quote {
+                try!(formatter.write_str("\""));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds the extensions through an NSS extension handle, finishing the handle even when adding fails
+unsafe fn add_extensions(handle: *mut c_void, extensions: &[Extension]) -> NSSResult<()>
+{
+    if handle.is_null() {
+        return Err(::ffi::nspr::get_error_code());
+    }
+
+    let mut added = Ok(());
+    for extension in extensions
+    {
+        added = oid_from_string(&extension.oid).and_then(|oid| {
+            let mut oid = sec::SECItem::from_buf(&oid);
+            let mut value = sec::SECItem::from_buf(&extension.value);
+            let critical = if extension.critical { PRBool::True } else { PRBool::False };
+            cert::CERT_AddExtensionByOID(handle, oid.get_mut(), value.get_mut(), critical, PRBool::True).to_result()
+        });
+        if added.is_err() {
+            break;
+        }
+    }
+
+    let finished = cert::CERT_FinishExtensions(handle).to_result();
+    try!(added);
+    finished
+}
+
+pub(crate) fn new_arena() -> NSSResult<cert::Arena>
+{
+    unsafe { cert::Arena::wrap(cert::PORT_NewArena(cert::DER_DEFAULT_CHUNKSIZE)) }
+}
+
+/// Signs the DER encoded data and wraps it in a SignedData structure
+fn sign_der(data: &[u8], key: &mut pkey::PrivateKey, hash: pkey::HashAlgorithm) -> NSSResult<Vec<u8>>
+{
+    let algorithm = try!(key.signature_algorithm(hash));
+    let mut arena = try!(new_arena());
+    let mut result = sec::SECItem::empty();
+    unsafe
+    {
+        try!(cert::SEC_DerSignData(arena.get_mut(), result.get_mut(), data.as_ptr(), data.len() as c_int,
+                                   key.get_mut(), algorithm).to_result());
+    }
+    Ok(result.copy_buf())
+}
+
+fn der_oid(oid: &str) -> NSSResult<Vec<u8>>
+{
+    Ok(der::encode(der::OID, &try!(oid_from_string(oid))))
+}
+
+pub(crate) fn from_prtime(time: cert::PRTime) -> SystemTime
+{
+    match time >= 0
+    {
+        true => UNIX_EPOCH + Duration::from_micros(time as u64),
+        false => UNIX_EPOCH - Duration::from_micros(time.wrapping_neg() as u64),
+    }
+}
+
+fn to_cert_list(certs: &[Certificate]) -> NSSResult<cert::CertList>
+{
+    let mut list = unsafe { try!(cert::CertList::wrap(cert::CERT_NewCertList())) };
+    for certificate in certs
+    {
+        // The list takes ownership of the reference on success
+        let mut reference = certificate.clone();
+        unsafe { try!(cert::CERT_AddCertToListTail(list.get_mut(), reference.cert.get_mut()).to_result()) };
+        mem::forget(reference);
+    }
+    Ok(list)
+}
+
+pub(crate) fn from_cert_list(list: &cert::CertList) -> NSSResult<Vec<Certificate>>
+{
+    let mut result = Vec::new();
+    unsafe
+    {
+        let head = &(*list.get()).list as *const cert::PRCList;
+        let mut node = (*head).next as *const cert::PRCList;
+        while node != head {
+            let entry = node as *const cert::CERTCertListNode;
+            result.push(try!(Certificate::from_raw(cert::CERT_DupCertificate((*entry).cert))));
+            node = (*node).next;
+        }
+    }
+    Ok(result)
+}
+
+pub(crate) fn to_prtime(time: SystemTime) -> cert::PRTime
+{
+    match time.duration_since(UNIX_EPOCH)
+    {
+        Ok(duration) => duration_to_micros(duration),
+        Err(e) => -duration_to_micros(e.duration()),
+    }
+}
+
+fn duration_to_micros(duration: Duration) -> cert::PRTime
+{
+    duration.as_secs() as cert::PRTime * 1_000_000 + duration.subsec_micros() as cert::PRTime
+}
+
+fn oid_from_string(oid: &str) -> NSSResult<Vec<u8>>
+{
+    let arcs = try!(oid.split('.')
+                    .map(|arc| arc.parse::<u64>().map_err(|_| NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS)))
+                    .collect::<NSSResult<Vec<u64>>>());
+    if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] >= 40) {
+        return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+    }
+
+    let mut der = Vec::new();
+    let first = arcs[0] * 40 + arcs[1];
+    for &arc in Some(&first).into_iter().chain(arcs[2..].iter())
+    {
+        let mut bytes = vec![(arc & 0x7F) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            bytes.push((rest & 0x7F) as u8 | 0x80);
+            rest >>= 7;
+        }
+        der.extend(bytes.iter().rev());
+    }
+    Ok(der)
+}
+
+/// Looks up the tag of an OID, registering it with NSS when it is not yet known
+fn oid_tag(oid: &str) -> NSSResult<cert::SECOidTag>
+{
+    let der = try!(oid_from_string(oid));
+    let item = sec::SECItem::from_buf(&der);
+    match unsafe { cert::SECOID_FindOIDTag(item.get()) }
+    {
+        cert::SEC_OID_UNKNOWN => {},
+        tag => return Ok(tag),
+    }
+
+    let desc = CString::new(oid).unwrap();
+    let data = cert::SECOidData
+    {
+        oid: *item.get(),
+        offset: cert::SEC_OID_UNKNOWN,
+        desc: desc.as_ptr(),
+        mechanism: cert::CKM_INVALID_MECHANISM,
+        supported_extension: 0,
+    };
+    match unsafe { cert::SECOID_AddEntry(&data) }
+    {
+        cert::SEC_OID_UNKNOWN => Err(::ffi::nspr::get_error_code()),
+        tag => Ok(tag),
+    }
+}
+
+fn oid_to_string(der: &[u8]) -> String
+{
+    let mut arcs = Vec::new();
+    let mut value = 0u64;
+    for &byte in der
+    {
+        value = (value << 7) | (byte & 0x7F) as u64;
+        if byte & 0x80 == 0 {
+            if arcs.is_empty() {
+                let first = ::std::cmp::min(value / 40, 2);
+                arcs.push(first);
+                arcs.push(value - first * 40);
+            }
+            else {
+                arcs.push(value);
+            }
+            value = 0;
+        }
+    }
+
+    arcs.iter().map(|arc| arc.to_string()).collect::<Vec<_>>().join(".")
+}
+
+unsafe fn take_string(string: *mut c_char) -> Option<String>
+{
+    match string.is_null()
+    {
+        true => None,
+        false => {
+            let result = String::from_utf8_lossy(CStr::from_ptr(string).to_bytes()).into_owned();
+            cert::PORT_Free(string as *mut c_void);
+            Some(result)
+        },
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    extern crate rustc_serialize as serialize;
+    use self::serialize::base64::FromBase64;
+    use self::serialize::hex::FromHex;
+    use super::{Certificate, GeneralName, KeyUsage, ExtendedKeyUsage, BasicConstraints, Extension, Name, NameBuilder};
+    use crypto::pkey;
+    use ffi::cert;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Self-signed RSA certificate with all extensions, for the key in the pkey tests
+    pub static LEAF_BASE64 : &'static [u8] = b"MIICmDCCAgGgAwIBAgIIASNFZ4mrze8wDQYJKoZIhvcNAQELBQAwNDELMAkGA1UEBhMCTkwxDzANBgNVBAoMBm5zcy1yczEUMBIGA1UEAwwLbnNzLXJzIHRlc3QwHhcNMjAwMTAxMDAwMDAwWhcNNDAwMTAxMDAwMDAwWjA0MQswCQYDVQQGEwJOTDEPMA0GA1UECgwGbnNzLXJzMRQwEgYDVQQDDAtuc3MtcnMgdGVzdDCBnzANBgkqhkiG9w0BAQEFAAOBjQAwgYkCgYEAv2btvTZ+jz47klTWbjmUaz0Ur1kT6uvCdpmKm0wDj77XXD9l9LNBSJbbrwhKL/rsxvGzCAWmIURHV1UHDusbxSwhc83a7X+C5ue29SS6YqoMqZeXHrv6gmXEKpEaDYyTF51tkILXFTOHcJtl88MJnUqUy0gi1xfFjiVcsnPIfXcCAwEAAaOBsjCBrzBlBgNVHREEXjBcggtleGFtcGxlLmNvbYINKi5leGFtcGxlLm9yZ4cEfwAAAYcQAAAAAAAAAAAAAAAAAAAAAYEQdGVzdEBleGFtcGxlLmNvbYYUaHR0cHM6Ly9leGFtcGxlLmNvbS8wDgYDVR0PAQH/BAQDAgWgMCgGA1UdJQQhMB8GCCsGAQUFBwMBBggrBgEFBQcDAgYJKwYBBAGGjR8BMAwGA1UdEwEB/wQCMAAwDQYJKoZIhvcNAQELBQADgYEArCHfNlTp06j1WH+CrUsg+8w07Ac0TguoiCjirRN6DelL9a8Zt8vQpUYFAYejrHPDm7jzIvfcUZtmskaY36YR+l+NY6wiNuVzVrM3Tu5jxM99GAcadnGhcwrzYVd4suAFlVX1Rl7WcF3rGc2fMG/PaksrLrP4WJH8RC+7V7ff8Zo=";
+    // Self-signed P-256 CA certificate with a path length constraint of 1
+    static CA_BASE64 : &'static [u8] = b"MIIBPTCB46ADAgECAgEBMAoGCCqGSM49BAMCMBQxEjAQBgNVBAMMCW5zcy1ycyBDQTAeFw0yMDAxMDEwMDAwMDBaFw00MDAxMDEwMDAwMDBaMBQxEjAQBgNVBAMMCW5zcy1ycyBDQTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABPnr5GQUfMEC2SMk0JnZJ8GlDkLVegihFv/sCimBnYxlcLr30SiIkCpqDB1nrdEDEjyFmrab+OlDHJOEkw1Lfu6jJjAkMBIGA1UdEwEB/wQIMAYBAf8CAQEwDgYDVR0PAQH/BAQDAgEGMAoGCCqGSM49BAMCA0kAMEYCIQC73T8eh9Q8X2JWA9dNpAufrEiLX0I0cSRqnPD2YUNkTQIhANTzISwJNplptszT5WRM2MhTje/caEy3QcdEUPwcZYHl";
+
+    #[test]
+    fn names()
+    {
+        let cert = Certificate::from_der(&LEAF_BASE64.from_base64().unwrap()).unwrap();
+
+        assert_eq!("CN=nss-rs test,O=nss-rs,C=NL", cert.subject().to_string());
+        assert_eq!("CN=nss-rs test,O=nss-rs,C=NL", cert.issuer().to_string());
+        assert_eq!(Some("nss-rs test".to_string()), cert.subject().common_name());
+        assert_eq!(cert.subject().to_der(), cert.issuer().to_der());
+        assert_eq!(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef], cert.serial_number());
+    }
+
+    #[test]
+    fn validity()
+    {
+        let cert = Certificate::from_der(&LEAF_BASE64.from_base64().unwrap()).unwrap();
+
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(1577836800), cert.not_before().unwrap());
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(2208988800), cert.not_after().unwrap());
+    }
+
+    #[test]
+    fn extensions()
+    {
+        let cert = Certificate::from_der(&LEAF_BASE64.from_base64().unwrap()).unwrap();
+
+        assert_eq!(vec![GeneralName::DNS("example.com".to_string()),
+                        GeneralName::DNS("*.example.org".to_string()),
+                        GeneralName::IP("127.0.0.1".parse().unwrap()),
+                        GeneralName::IP("::1".parse().unwrap()),
+                        GeneralName::Email("test@example.com".to_string()),
+                        GeneralName::URI("https://example.com/".to_string())],
+                   cert.subject_alt_names().unwrap());
+        assert_eq!(Some(vec![KeyUsage::DigitalSignature, KeyUsage::KeyEncipherment]), cert.key_usage());
+        assert_eq!(Some(vec![ExtendedKeyUsage::ServerAuth, ExtendedKeyUsage::ClientAuth,
+                             ExtendedKeyUsage::Other("1.3.6.1.4.1.99999.1".to_string())]),
+                   cert.extended_key_usage().unwrap());
+        assert_eq!(Some(BasicConstraints { ca: false, path_len: None }), cert.basic_constraints().unwrap());
+
+        let ca = Certificate::from_der(&CA_BASE64.from_base64().unwrap()).unwrap();
+        assert_eq!(Vec::<GeneralName>::new(), ca.subject_alt_names().unwrap());
+        assert_eq!(Some(vec![KeyUsage::KeyCertSign, KeyUsage::CRLSign]), ca.key_usage());
+        assert_eq!(None, ca.extended_key_usage().unwrap());
+        assert_eq!(Some(BasicConstraints { ca: true, path_len: Some(1) }), ca.basic_constraints().unwrap());
+    }
+
+    #[test]
+    fn fingerprint()
+    {
+        let cert = Certificate::from_der(&LEAF_BASE64.from_base64().unwrap()).unwrap();
+
+        assert_eq!("73a59a852435bd3e654e7e993d8a67fb1d2d33d8".from_hex().unwrap(),
+                   cert.fingerprint(pkey::HashAlgorithm::SHA1).unwrap());
+        assert_eq!("e055b4df0d3ee232c8302fcd5908fee9c6a45b8bed4ba281becf56aab12acdea".from_hex().unwrap(),
+                   cert.fingerprint(pkey::HashAlgorithm::SHA256).unwrap());
+    }
+
+    #[test]
+    fn public_key()
+    {
+        let mut cert = Certificate::from_der(&LEAF_BASE64.from_base64().unwrap()).unwrap();
+        let key = cert.public_key().unwrap();
+        assert_eq!(pkey::KeyType::RSA, key.key_type());
+        assert_eq!(1024, key.key_bits().unwrap());
+        assert_eq!(128, cert.rsa_public_key().unwrap().key_len().unwrap());
+
+        let mut ca = Certificate::from_der(&CA_BASE64.from_base64().unwrap()).unwrap();
+        let key = ca.public_key().unwrap();
+        assert_eq!(pkey::KeyType::EC, key.key_type());
+        assert_eq!(256, key.key_bits().unwrap());
+        assert!(key.into_rsa().is_err());
+        assert!(ca.rsa_public_key().is_err());
+    }
+
+    #[test]
+    fn pem()
+    {
+        let der = LEAF_BASE64.from_base64().unwrap();
+        let cert = Certificate::from_der(&der).unwrap();
+
+        let pem = cert.to_pem().unwrap();
+        assert!(pem.starts_with("-----BEGIN CERTIFICATE-----\nMIICmDCCAgGgAwIBAgIIASNFZ4mrze8wDQYJKoZIhvcNAQELBQAwNDELMAkGA1UE\n"));
+        assert!(pem.ends_with("\n-----END CERTIFICATE-----\n"));
+
+        let parsed = Certificate::from_pem(&pem).unwrap();
+        assert_eq!(der, parsed.to_der());
+        assert!(Certificate::from_der(b"not a certificate").is_err());
+    }
+
+    #[test]
+    fn name_builder()
+    {
+        let builder = NameBuilder::new().country("NL").organization("nss-rs, \"test\"").common_name(" spaced ");
+        assert_eq!("CN=\" spaced \",O=\"nss-rs, \\\"test\\\"\",C=NL", builder.to_string());
+
+        let name = builder.to_ffi().unwrap();
+        let name = Name { name: unsafe { &*name.get() }, der: &[] };
+        assert_eq!(Some(" spaced ".to_string()), name.common_name());
+        assert_eq!("CN=\" spaced \",O=\"nss-rs, \\\"test\\\"\",C=NL", name.to_string());
+        assert_eq!("OID.2.5.4.5=1234", NameBuilder::new().attribute("2.5.4.5", "1234").to_string());
+    }
+
+    #[test]
+    fn extension_encoding()
+    {
+        let leaf = Certificate::from_der(&LEAF_BASE64.from_base64().unwrap()).unwrap();
+        let ca = Certificate::from_der(&CA_BASE64.from_base64().unwrap()).unwrap();
+
+        let names = leaf.subject_alt_names().unwrap();
+        assert_eq!(leaf.find_extension(cert::SEC_OID_X509_SUBJECT_ALT_NAME).unwrap().unwrap(),
+                   Extension::subject_alt_name(&names).unwrap().value);
+        let usages = leaf.extended_key_usage().unwrap().unwrap();
+        assert_eq!(leaf.find_extension(cert::SEC_OID_X509_EXT_KEY_USAGE).unwrap().unwrap(),
+                   Extension::extended_key_usage(&usages).unwrap().value);
+        assert_eq!(ca.find_extension(cert::SEC_OID_X509_BASIC_CONSTRAINTS).unwrap().unwrap(),
+                   Extension::basic_constraints(ca.basic_constraints().unwrap().unwrap()).value);
+        assert_eq!(vec![0x30, 0x00], Extension::basic_constraints(BasicConstraints { ca: false, path_len: None }).value);
+
+        let key_usage = Extension::key_usage(&[KeyUsage::DigitalSignature, KeyUsage::KeyEncipherment]);
+        assert_eq!("2.5.29.15", key_usage.oid);
+        assert!(key_usage.critical);
+        assert_eq!(vec![0x03, 0x02, 0x05, 0xA0], key_usage.value);
+        assert!(Extension::subject_alt_name(&[GeneralName::Other(vec![])]).is_err());
+    }
+}
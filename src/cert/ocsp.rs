@@ -0,0 +1,312 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, sec};
+use ffi::nspr::PRBool;
+use super::Certificate;
+use std::ptr;
+use std::time::SystemTime;
+
+/// The status of a certificate according to an OCSP response
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CertStatus
+{
+    Good,
+    Revoked,
+    Unknown,
+}
+
+/// An unsigned OCSP request
+pub struct OcspRequest
+{
+    request: cert::OcspRequest,
+}
+
+impl OcspRequest
+{
+    /// Requests the status of the certificates, which should all have the same issuer.
+    ///
+    /// NSS needs to know the issuers, so they have to be loaded as `Certificate`s as well.
+    pub fn new(certificates: &[Certificate]) -> NSSResult<OcspRequest>
+    {
+        try!(::nss::init());
+
+        let mut list = try!(super::to_cert_list(certificates));
+        let time = super::to_prtime(SystemTime::now());
+        let request = unsafe
+        {
+            try!(cert::OcspRequest::wrap(cert::CERT_CreateOCSPRequest(list.get_mut(), time, PRBool::False, ptr::null_mut())))
+        };
+        Ok(OcspRequest { request: request })
+    }
+
+    /// The DER encoded request, as sent to the responder
+    pub fn to_der(&self) -> NSSResult<Vec<u8>>
+    {
+        let mut arena = try!(super::new_arena());
+        unsafe
+        {
+            let encoded = cert::CERT_EncodeOCSPRequest(arena.get_mut(), self.request.get() as *mut _, ptr::null_mut());
+            if encoded.is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+            Ok((*encoded).as_slice().to_vec())
+        }
+    }
+}
+
+/// A parsed OCSP response
+pub struct OcspResponse
+{
+    der: Vec<u8>,
+    response: cert::OcspResponse,
+}
+
+impl OcspResponse
+{
+    pub fn from_der(data: &[u8]) -> NSSResult<OcspResponse>
+    {
+        try!(::nss::init());
+
+        // The response is decoded from a copy in its own arena
+        let item = sec::SECItem::from_buf(data);
+        let response = unsafe { try!(cert::OcspResponse::wrap(cert::CERT_DecodeOCSPResponse(item.get()))) };
+        Ok(OcspResponse { der: data.to_vec(), response: response })
+    }
+
+    pub fn to_der(&self) -> Vec<u8>
+    {
+        self.der.clone()
+    }
+
+    /// Checks the responseStatus, failing with the matching error if the responder could not answer
+    pub fn response_status(&self) -> NSSResult<()>
+    {
+        unsafe { cert::CERT_GetOCSPResponseStatus(self.response.get() as *mut _).to_result() }
+    }
+
+    /// Checks the signature and returns the responder certificate
+    ///
+    /// The responder is either the issuer itself, or a certificate issued by it for OCSP signing.
+    /// NSS verifies the responder with the trust settings of `Certificate::set_trust`, not those of a `Verifier`.
+    pub fn verify(&self, issuer: &Certificate) -> NSSResult<Certificate>
+    {
+        let mut signer = ptr::null_mut();
+        unsafe
+        {
+            try!(cert::CERT_VerifyOCSPResponseSignature(self.response.get() as *mut _, cert::CERT_GetDefaultCertDB(), ptr::null_mut(),
+                                                        &mut signer, issuer.cert.get() as *mut _).to_result());
+        }
+        Certificate::from_raw(signer)
+    }
+
+    /// Verifies the response and looks up the status of the certificate at the given time
+    pub fn status(&self, certificate: &Certificate, issuer: &Certificate, time: SystemTime) -> NSSResult<CertStatus>
+    {
+        try!(self.response_status());
+        let signer = try!(self.verify(issuer));
+
+        let time = super::to_prtime(time);
+        let mut id = unsafe { try!(cert::OcspCertId::wrap(cert::CERT_CreateOCSPCertID(certificate.cert.get() as *mut _, time))) };
+        let status = unsafe
+        {
+            cert::CERT_GetOCSPStatusForCertID(cert::CERT_GetDefaultCertDB(), self.response.get() as *mut _, id.get_mut(),
+                                              signer.cert.get() as *mut _, time).to_result()
+        };
+        match status
+        {
+            Ok(..) => Ok(CertStatus::Good),
+            Err(NSSError::NSS(sec::SEC_ERROR_REVOKED_CERTIFICATE)) => Ok(CertStatus::Revoked),
+            Err(NSSError::NSS(sec::SEC_ERROR_OCSP_UNKNOWN_CERT)) => Ok(CertStatus::Unknown),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Adds the response to the OCSP cache for the certificate, as for a response stapled in a TLS handshake
+    ///
+    /// Only verified good responses are cached; chain verification with revocation checking uses them
+    /// instead of contacting the responder.
+    pub fn cache(&self, certificate: &Certificate) -> NSSResult<()>
+    {
+        let item = sec::SECItem::from_buf(&self.der);
+        let time = super::to_prtime(SystemTime::now());
+        unsafe
+        {
+            cert::CERT_CacheOCSPResponseFromSideChannel(cert::CERT_GetDefaultCertDB(), certificate.cert.get() as *mut _, time,
+                                                        item.get(), ptr::null_mut()).to_result()
+        }
+    }
+}
+
+/// Removes all responses from the OCSP cache
+pub fn clear_cache() -> NSSResult<()>
+{
+    try!(::nss::init());
+    unsafe { cert::CERT_ClearOCSPCache().to_result() }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use result::NSSError;
+    use cert::{der, BasicConstraints, Certificate, Extension, KeyUsage, NameBuilder};
+    use cert::builder::CertificateBuilder;
+    use cert::db::Trust;
+    use cert::verify::{Revocation, Usage, Verifier};
+    use crypto::pkey::{Curve, HashAlgorithm, PrivateKey};
+    use ffi::{cert, sec};
+    use std::ptr;
+    use std::time::{Duration, SystemTime};
+
+    /// Splits the first TLV off DER data, returning the tag, the content and the rest
+    fn read_tlv(data: &[u8]) -> (u8, &[u8], &[u8])
+    {
+        let (len, start) = match data[1]
+        {
+            len if len < 0x80 => (len as usize, 2),
+            len => {
+                let count = (len & 0x7F) as usize;
+                (data[2..2 + count].iter().fold(0, |len, &b| (len << 8) | b as usize), 2 + count)
+            },
+        };
+        (data[0], &data[start..start + len], &data[start + len..])
+    }
+
+    fn generalized_time(time: SystemTime) -> Vec<u8>
+    {
+        let mut arena = ::cert::new_arena().unwrap();
+        let mut item = sec::SECItem::empty();
+        unsafe { cert::DER_TimeToGeneralizedTimeArena(arena.get_mut(), item.get_mut(), ::cert::to_prtime(time)).to_result().unwrap() };
+        der::encode(0x18, item.get().as_slice())
+    }
+
+    /// A local responder answering requests for certificates of a single issuer
+    struct Responder
+    {
+        certificate: Certificate,
+        key: PrivateKey,
+        revoked: Vec<Vec<u8>>,
+    }
+
+    impl Responder
+    {
+        fn respond(&mut self, request: &[u8]) -> Vec<u8>
+        {
+            let now = SystemTime::now();
+            let (_, request, _) = read_tlv(request);
+            let (_, tbs_request, _) = read_tlv(request);
+            let (_, mut requests, _) = read_tlv(tbs_request);
+
+            let mut responses = Vec::new();
+            while !requests.is_empty()
+            {
+                let (_, single, rest) = read_tlv(requests);
+                let (_, fields, after) = read_tlv(single);
+                let cert_id = &single[..single.len() - after.len()];
+                let (_, _, fields) = read_tlv(fields);
+                let (_, _, fields) = read_tlv(fields);
+                let (_, _, fields) = read_tlv(fields);
+                let (_, serial, _) = read_tlv(fields);
+
+                let status = match self.revoked.iter().any(|revoked| &**revoked == serial)
+                {
+                    true => der::encode(0xA1, &generalized_time(now - Duration::from_secs(3600))),
+                    false => vec![0x80, 0x00],
+                };
+                responses.push(der::sequence(&[cert_id.to_vec(), status, generalized_time(now),
+                                                der::encode(0xA0, &generalized_time(now + Duration::from_secs(3600)))]));
+                requests = rest;
+            }
+
+            let response_data = der::sequence(&[der::encode(0xA1, &self.certificate.subject().to_der()), generalized_time(now),
+                                                der::sequence(&responses)]);
+            let basic = ::cert::sign_der(&response_data, &mut self.key, HashAlgorithm::SHA256).unwrap();
+            der::sequence(&[der::encode(der::ENUMERATED, &[0]),
+                            der::encode(0xA0, &der::sequence(&[::cert::der_oid("1.3.6.1.5.5.7.48.1.1").unwrap(), der::octet_string(&basic)]))])
+        }
+    }
+
+    struct Pki
+    {
+        root: Certificate,
+        leaf: Certificate,
+        revoked: Certificate,
+        responder: Responder,
+    }
+
+    fn pki(name: &str) -> Pki
+    {
+        let mut root_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let root = CertificateBuilder::new(NameBuilder::new().common_name(&format!("{} root", name)))
+                    .extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: None }))
+                    .extension(Extension::key_usage(&[KeyUsage::KeyCertSign, KeyUsage::CRLSign]))
+                    .self_signed(&mut root_key, HashAlgorithm::SHA256).unwrap();
+        let leaf_key = PrivateKey::gen_ec(Curve::P256).unwrap().public_key().unwrap();
+        let leaf = CertificateBuilder::new(NameBuilder::new().common_name(&format!("{} leaf", name)))
+                    .serial_number(&[0x01])
+                    .sign(&leaf_key, &root, &mut root_key, HashAlgorithm::SHA256).unwrap();
+        let revoked = CertificateBuilder::new(NameBuilder::new().common_name(&format!("{} revoked leaf", name)))
+                       .serial_number(&[0x02])
+                       .sign(&leaf_key, &root, &mut root_key, HashAlgorithm::SHA256).unwrap();
+
+        let responder = Responder { certificate: root.clone(), key: root_key, revoked: vec![vec![0x02]] };
+        Pki { root: root, leaf: leaf, revoked: revoked, responder: responder }
+    }
+
+    #[test]
+    fn request_and_response()
+    {
+        let mut pki = pki("nss-rs ocsp");
+        let now = SystemTime::now();
+        let request = OcspRequest::new(&[pki.leaf.clone(), pki.revoked.clone()]).unwrap();
+        let encoded = pki.responder.respond(&request.to_der().unwrap());
+        assert!(OcspResponse::from_der(&encoded).unwrap().verify(&pki.root).is_err());
+
+        // NSS remembers the outcome of verifying the signature in the response
+        pki.root.set_trust(&Trust::ca()).unwrap();
+        let response = OcspResponse::from_der(&encoded).unwrap();
+        assert!(response.response_status().is_ok());
+        assert_eq!(encoded, response.to_der());
+        assert_eq!(pki.root.to_der(), response.verify(&pki.root).unwrap().to_der());
+        assert_eq!(CertStatus::Good, response.status(&pki.leaf, &pki.root, now).unwrap());
+        assert_eq!(CertStatus::Revoked, response.status(&pki.revoked, &pki.root, now).unwrap());
+
+        let single = OcspRequest::new(&[pki.leaf.clone()]).unwrap();
+        let response = OcspResponse::from_der(&pki.responder.respond(&single.to_der().unwrap())).unwrap();
+        assert_eq!(CertStatus::Good, response.status(&pki.leaf, &pki.root, now).unwrap());
+        assert_eq!(CertStatus::Unknown, response.status(&pki.revoked, &pki.root, now).unwrap());
+
+        assert!(OcspRequest::new(&[pki.root.clone()]).is_ok());
+        assert!(OcspResponse::from_der(b"not a response").is_err());
+    }
+
+    #[test]
+    fn error_response()
+    {
+        let encoded = sec::SECItem::wrap(unsafe { cert::CERT_CreateEncodedOCSPErrorResponse(ptr::null_mut(), sec::SEC_ERROR_OCSP_TRY_SERVER_LATER) }).unwrap();
+        let response = OcspResponse::from_der(encoded.get().as_slice()).unwrap();
+        match response.response_status()
+        {
+            Err(NSSError::NSS(sec::SEC_ERROR_OCSP_TRY_SERVER_LATER)) => {},
+            _ => panic!("expected SEC_ERROR_OCSP_TRY_SERVER_LATER"),
+        }
+    }
+
+    #[test]
+    fn stapled_response()
+    {
+        let mut pki = pki("nss-rs ocsp stapling");
+        pki.root.set_trust(&Trust::ca()).unwrap();
+        let verifier = Verifier::new(Usage::EmailSigning).trust_anchor(&pki.root).revocation(Revocation::HardFail).network_fetch(false);
+        assert!(verifier.verify(&pki.leaf).is_err());
+
+        let request = OcspRequest::new(&[pki.leaf.clone(), pki.revoked.clone()]).unwrap();
+        let response = OcspResponse::from_der(&pki.responder.respond(&request.to_der().unwrap())).unwrap();
+        assert!(response.cache(&pki.revoked).is_err());
+        response.cache(&pki.leaf).unwrap();
+        assert!(verifier.verify(&pki.leaf).is_ok());
+        assert!(verifier.verify(&pki.revoked).is_err());
+
+        clear_cache().unwrap();
+        assert!(verifier.verify(&pki.leaf).is_err());
+    }
+}
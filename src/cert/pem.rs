@@ -0,0 +1,59 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, sec};
+use std::ffi::{CStr, CString};
+use libc::{c_uint, c_void};
+
+pub fn encode(label: &str, der: &[u8]) -> NSSResult<String>
+{
+    let body = unsafe
+    {
+        let ascii = cert::BTOA_DataToAscii(der.as_ptr(), der.len() as c_uint);
+        if ascii.is_null() {
+            return Err(::ffi::nspr::get_error_code());
+        }
+        let body = String::from_utf8_lossy(CStr::from_ptr(ascii).to_bytes()).replace("\r\n", "\n");
+        cert::PORT_Free(ascii as *mut c_void);
+        body
+    };
+
+    Ok(format!("-----BEGIN {}-----\n{}\n-----END {}-----\n", label, body, label))
+}
+
+pub fn decode(label: &str, pem: &str) -> NSSResult<Vec<u8>>
+{
+    let begin = format!("-----BEGIN {}-----", label);
+    let end = format!("-----END {}-----", label);
+
+    let start = match pem.find(&*begin)
+    {
+        Some(pos) => pos + begin.len(),
+        None => return Err(NSSError::NSS(sec::SEC_ERROR_BAD_DER)),
+    };
+    let stop = match pem[start..].find(&*end)
+    {
+        Some(pos) => start + pos,
+        None => return Err(NSSError::NSS(sec::SEC_ERROR_BAD_DER)),
+    };
+
+    let body: String = pem[start..stop].chars().filter(|c| !c.is_whitespace()).collect();
+    if body.is_empty() {
+        return Err(NSSError::NSS(sec::SEC_ERROR_BAD_DER));
+    }
+    let body = match CString::new(body)
+    {
+        Ok(body) => body,
+        Err(..) => return Err(NSSError::NSS(sec::SEC_ERROR_BAD_DER)),
+    };
+
+    unsafe
+    {
+        let mut len = 0;
+        let data = cert::ATOB_AsciiToData(body.as_ptr(), &mut len);
+        if data.is_null() {
+            return Err(::ffi::nspr::get_error_code());
+        }
+        let result = ::std::slice::from_raw_parts(data, len as usize).to_vec();
+        cert::PORT_Free(data as *mut c_void);
+        Ok(result)
+    }
+}
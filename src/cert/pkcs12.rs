@@ -0,0 +1,361 @@
+use result::NSSResult;
+use ffi::{cert, p12, sec};
+use ffi::nspr::PRBool;
+use crypto::pkey;
+use super::Certificate;
+use super::db::{self, Database};
+use std::ffi::CStr;
+use std::ptr;
+use libc::{c_char, c_uint, c_ulong, c_void};
+
+/// The password based encryption of a PKCS#12 bundle
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Encryption
+{
+    /// PBES2 with PBKDF2 and AES-256-CBC
+    AES_256_CBC,
+    AES_192_CBC,
+    AES_128_CBC,
+    /// The legacy pbeWithSHAAnd3-KeyTripleDES-CBC, for software without PBES2 support
+    SHA1_3DES_CBC,
+    /// The legacy pbeWithSHAAnd128BitRC2-CBC
+    SHA1_RC2_128_CBC,
+    /// The legacy pbeWithSHAAnd40BitRC2-CBC, which older software uses for the certificates
+    SHA1_RC2_40_CBC,
+}
+
+impl Encryption
+{
+    fn to_oid_tag(&self) -> cert::SECOidTag
+    {
+        match *self
+        {
+            Encryption::AES_256_CBC => cert::SEC_OID_AES_256_CBC,
+            Encryption::AES_192_CBC => cert::SEC_OID_AES_192_CBC,
+            Encryption::AES_128_CBC => cert::SEC_OID_AES_128_CBC,
+            Encryption::SHA1_3DES_CBC => p12::SEC_OID_PKCS12_V2_PBE_WITH_SHA1_AND_3KEY_TRIPLE_DES_CBC,
+            Encryption::SHA1_RC2_128_CBC => p12::SEC_OID_PKCS12_V2_PBE_WITH_SHA1_AND_128_BIT_RC2_CBC,
+            Encryption::SHA1_RC2_40_CBC => p12::SEC_OID_PKCS12_V2_PBE_WITH_SHA1_AND_40_BIT_RC2_CBC,
+        }
+    }
+}
+
+/// Exports a private key with its certificate chain as a password protected PKCS#12 bundle, like `pk12util -o`
+#[derive(Clone, Debug)]
+pub struct Pkcs12Builder
+{
+    key_encryption: Encryption,
+    certificate_encryption: Option<Encryption>,
+    mac: pkey::HashAlgorithm,
+    include_chain: bool,
+}
+
+impl Pkcs12Builder
+{
+    /// Encrypts both the key and the certificates with AES-256 and protects the bundle with an HMAC-SHA256
+    pub fn new() -> Pkcs12Builder
+    {
+        Pkcs12Builder {
+            key_encryption: Encryption::AES_256_CBC,
+            certificate_encryption: Some(Encryption::AES_256_CBC),
+            mac: pkey::HashAlgorithm::SHA256,
+            include_chain: true,
+        }
+    }
+
+    pub fn key_encryption(mut self, encryption: Encryption) -> Pkcs12Builder
+    {
+        self.key_encryption = encryption;
+        self
+    }
+
+    /// How to encrypt the certificates, or None to store them in the clear
+    pub fn certificate_encryption(mut self, encryption: Option<Encryption>) -> Pkcs12Builder
+    {
+        self.certificate_encryption = encryption;
+        self
+    }
+
+    /// The hash for the integrity check of the bundle
+    pub fn mac(mut self, hash: pkey::HashAlgorithm) -> Pkcs12Builder
+    {
+        self.mac = hash;
+        self
+    }
+
+    /// Whether to add the issuers of the certificate that NSS can find
+    pub fn include_chain(mut self, enabled: bool) -> Pkcs12Builder
+    {
+        self.include_chain = enabled;
+        self
+    }
+
+    /// Exports the certificate together with its private key, which must be stored in the database
+    pub fn export(&self, database: &mut Database, certificate: &Certificate, password: &str) -> NSSResult<Vec<u8>>
+    {
+        try!(init());
+
+        // The encoder converts the password to UCS-2 where the algorithm needs it
+        let mut password = sec::SECItem::from_buf(password.as_bytes());
+        let mut output = Vec::new();
+        unsafe
+        {
+            let mut context = try!(p12::ExportContext::wrap(p12::SEC_PKCS12CreateExportContext(ptr::null_mut(), ptr::null_mut(),
+                                                                                               database.slot.get_mut(),
+                                                                                               ptr::null_mut())));
+            try!(p12::SEC_PKCS12AddPasswordIntegrity(context.get_mut(), password.get_mut(), self.mac.to_oid_tag()).to_result());
+
+            let certificate_safe = match self.certificate_encryption
+            {
+                Some(encryption) => p12::SEC_PKCS12CreatePasswordPrivSafe(context.get_mut(), password.get_mut(), encryption.to_oid_tag()),
+                None => p12::SEC_PKCS12CreateUnencryptedSafe(context.get_mut()),
+            };
+            // The key is encrypted on its own, like pk12util does
+            let key_safe = p12::SEC_PKCS12CreateUnencryptedSafe(context.get_mut());
+            if certificate_safe.is_null() || key_safe.is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+
+            try!(p12::SEC_PKCS12AddCertOrChainAndKey(context.get_mut(), certificate_safe, ptr::null_mut(),
+                                                     certificate.cert.get() as *mut _, cert::CERT_GetDefaultCertDB(),
+                                                     key_safe, ptr::null_mut(), PRBool::True, password.get_mut(),
+                                                     self.key_encryption.to_oid_tag(),
+                                                     if self.include_chain { PRBool::True } else { PRBool::False }).to_result());
+            try!(p12::SEC_PKCS12Encode(context.get_mut(), append_output, &mut output as *mut Vec<u8> as *mut c_void).to_result());
+        }
+        Ok(output)
+    }
+}
+
+impl Default for Pkcs12Builder
+{
+    fn default() -> Pkcs12Builder
+    {
+        Pkcs12Builder::new()
+    }
+}
+
+/// An imported PKCS#12 bundle
+pub struct Pkcs12
+{
+    /// The private key, if the bundle has one
+    pub key: Option<pkey::PrivateKey>,
+    /// The certificate of the private key
+    pub certificate: Option<Certificate>,
+    /// The other certificates, with the issuers in order from the certificate up
+    pub chain: Vec<Certificate>,
+}
+
+/// Verifies a PKCS#12 bundle and stores its keys and certificates in the database, like `pk12util -i`
+///
+/// Issuer certificates without a nickname in the bundle are stored in the database NSS was initialized with, as pk12util does.
+pub fn import(database: &mut Database, data: &[u8], password: &str) -> NSSResult<Pkcs12>
+{
+    try!(init());
+
+    // The decoder wants the password as UCS-2 and converts it back for PBES2
+    let password = bmp_string(password);
+    let mut password = sec::SECItem::from_buf(&password);
+    let mut data = data.to_vec();
+    let mut certificates = Vec::new();
+    unsafe
+    {
+        let mut decoder = try!(p12::Decoder::wrap(p12::SEC_PKCS12DecoderStart(password.get_mut(), database.slot.get_mut(),
+                                                                              ptr::null_mut(), ptr::null_mut(), ptr::null_mut(),
+                                                                              ptr::null_mut(), ptr::null_mut(), ptr::null_mut())));
+        try!(p12::SEC_PKCS12DecoderUpdate(decoder.get_mut(), data.as_mut_ptr(), data.len() as c_ulong).to_result());
+        try!(p12::SEC_PKCS12DecoderVerify(decoder.get_mut()).to_result());
+        try!(p12::SEC_PKCS12DecoderValidateBags(decoder.get_mut(), nickname_collision).to_result());
+        try!(p12::SEC_PKCS12DecoderImportBags(decoder.get_mut()).to_result());
+
+        try!(p12::SEC_PKCS12DecoderIterateInit(decoder.get_mut()).to_result());
+        let mut item = ptr::null();
+        while let sec::SECStatus::Success = p12::SEC_PKCS12DecoderIterateNext(decoder.get_mut(), &mut item)
+        {
+            let item = &*item;
+            if item.typ == p12::SEC_OID_PKCS12_V1_CERT_BAG_ID && !item.der.is_null() {
+                let has_key = match item.has_key { PRBool::True => true, PRBool::False => false };
+                certificates.push((has_key, (*item.der).as_slice().to_vec()));
+            }
+        }
+    }
+
+    // The imported certificates are found again by their encoding
+    let mut chain = Vec::new();
+    let mut certificate = None;
+    for (has_key, der) in certificates
+    {
+        let found = try!(Certificate::from_der(&der));
+        match has_key && certificate.is_none()
+        {
+            true => certificate = Some(found),
+            false => chain.push(found),
+        }
+    }
+
+    let key = match certificate
+    {
+        Some(ref certificate) => {
+            chain = order_chain(certificate, chain);
+            try!(db::find_private_key(certificate))
+        },
+        None => None,
+    };
+    Ok(Pkcs12 { key: key, certificate: certificate, chain: chain })
+}
+
+/// Puts the issuers of the certificate first, from the certificate up to the root
+fn order_chain(certificate: &Certificate, mut others: Vec<Certificate>) -> Vec<Certificate>
+{
+    let mut chain: Vec<Certificate> = Vec::new();
+    loop
+    {
+        let issuer = match chain.last()
+        {
+            Some(last) if last.subject().to_der() == last.issuer().to_der() => None,
+            Some(last) => others.iter().position(|other| other.subject().to_der() == last.issuer().to_der()),
+            None => others.iter().position(|other| other.subject().to_der() == certificate.issuer().to_der()),
+        };
+        match issuer
+        {
+            Some(index) => chain.push(others.remove(index)),
+            None => break,
+        }
+    }
+    chain.extend(others);
+    chain
+}
+
+/// PKCS#12 passwords are big-endian UCS-2 with a terminating zero
+fn bmp_string(password: &str) -> Vec<u8>
+{
+    let mut result = Vec::with_capacity(password.len() * 2 + 2);
+    for unit in password.encode_utf16()
+    {
+        result.push((unit >> 8) as u8);
+        result.push(unit as u8);
+    }
+    result.extend_from_slice(&[0, 0]);
+    result
+}
+
+fn init() -> NSSResult<()>
+{
+    try!(::nss::init());
+
+    unsafe { p12::PORT_SetUCS2_ASCIIConversionFunction(convert_utf8) };
+    Ok(())
+}
+
+/// NSS has no conversion to UCS-2 for passwords by default, UTF-8 is what Rust strings hold
+extern "C" fn convert_utf8(to_unicode: PRBool, in_buf: *mut u8, in_buf_len: c_uint, out_buf: *mut u8, max_out_buf_len: c_uint,
+                           out_buf_len: *mut c_uint, _swap_bytes: PRBool) -> PRBool
+{
+    unsafe { p12::PORT_UCS2_UTF8Conversion(to_unicode, in_buf, in_buf_len, out_buf, max_out_buf_len, out_buf_len) }
+}
+
+extern "C" fn append_output(arg: *mut c_void, buf: *const c_char, len: c_ulong)
+{
+    let output = unsafe { &mut *(arg as *mut Vec<u8>) };
+    output.extend_from_slice(unsafe { ::std::slice::from_raw_parts(buf as *const u8, len as usize) });
+}
+
+/// Called for certificates without a nickname and for nicknames that are already taken, with the certificate as argument
+extern "C" fn nickname_collision(old_nickname: *mut sec::SECItemFFI, cancel: *mut PRBool, arg: *mut c_void) -> *mut sec::SECItemFFI
+{
+    unsafe
+    {
+        *cancel = PRBool::False;
+        if arg.is_null() {
+            return ptr::null_mut();
+        }
+
+        let nickname = p12::CERT_MakeCANickname(arg as *mut cert::CERTCertificate);
+        if nickname.is_null() {
+            return ptr::null_mut();
+        }
+        let new_nickname = CStr::from_ptr(nickname).to_bytes().to_vec();
+        cert::PORT_Free(nickname as *mut c_void);
+
+        // Handing back the same nickname would not resolve the collision
+        if !old_nickname.is_null() && (*old_nickname).as_slice() == &new_nickname[..] {
+            return ptr::null_mut();
+        }
+        match sec::SECItem::dup(&new_nickname)
+        {
+            Ok(item) => item.into_raw(),
+            Err(_) => ptr::null_mut(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::{BasicConstraints, Extension, KeyUsage, NameBuilder};
+    use cert::builder::CertificateBuilder;
+    use cert::db::test::TempDir;
+    use crypto::pkey::{Curve, HashAlgorithm, PrivateKey};
+
+    #[test]
+    fn export_and_import()
+    {
+        let export_dir = TempDir::new("p12-export");
+        let import_dir = TempDir::new("p12-import");
+
+        let mut root_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let root = CertificateBuilder::new(NameBuilder::new().common_name("nss-rs p12 root"))
+                    .extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: None }))
+                    .extension(Extension::key_usage(&[KeyUsage::KeyCertSign]))
+                    .self_signed(&mut root_key, HashAlgorithm::SHA256).unwrap();
+        let mut intermediate_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let intermediate = CertificateBuilder::new(NameBuilder::new().common_name("nss-rs p12 intermediate"))
+                            .extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: Some(0) }))
+                            .extension(Extension::key_usage(&[KeyUsage::KeyCertSign]))
+                            .sign(&intermediate_key.public_key().unwrap(), &root, &mut root_key, HashAlgorithm::SHA256).unwrap();
+        let mut leaf_key = PrivateKey::gen_rsa(1024).unwrap();
+        let leaf = CertificateBuilder::new(NameBuilder::new().common_name("p12.example.com"))
+                    .sign(&leaf_key.public_key().unwrap(), &intermediate, &mut intermediate_key, HashAlgorithm::SHA256).unwrap();
+
+        let (modern, legacy) = {
+            let mut db = export_dir.open("nss-rs p12 export");
+            db.import_private_key(&leaf_key.save().unwrap()).unwrap();
+            db.add(&leaf, "leaf").unwrap();
+
+            let modern = Pkcs12Builder::new().export(&mut db, &leaf, "p\u{e4}ssword").unwrap();
+            let legacy = Pkcs12Builder::new()
+                            .key_encryption(Encryption::SHA1_3DES_CBC)
+                            .certificate_encryption(Some(Encryption::SHA1_RC2_40_CBC))
+                            .mac(HashAlgorithm::SHA1)
+                            .include_chain(false)
+                            .export(&mut db, &leaf, "legacy").unwrap();
+            (modern, legacy)
+        };
+
+        let mut db = import_dir.open("nss-rs p12 import");
+        assert!(import(&mut db, &modern, "wrong").is_err());
+        assert!(db.certificates().unwrap().is_empty());
+
+        let imported = import(&mut db, &modern, "p\u{e4}ssword").unwrap();
+        assert_eq!(leaf.to_der(), imported.certificate.as_ref().unwrap().to_der());
+        assert_eq!(vec![intermediate.to_der(), root.to_der()], imported.chain.iter().map(|c| c.to_der()).collect::<Vec<_>>());
+        let mut key = imported.key.unwrap();
+        assert_eq!(leaf_key.public_key().unwrap().save().unwrap(), key.public_key().unwrap().save().unwrap());
+        // Only the certificate with the key goes to the database, the issuers to the one NSS was initialized with
+        assert_eq!(vec![leaf.to_der()], db.certificates().unwrap().iter().map(|c| c.to_der()).collect::<Vec<_>>());
+
+        let imported = import(&mut db, &legacy, "legacy").unwrap();
+        assert_eq!(leaf.to_der(), imported.certificate.unwrap().to_der());
+        assert!(imported.chain.is_empty());
+        assert!(imported.key.is_some());
+    }
+
+    #[test]
+    fn bmp_password()
+    {
+        assert_eq!(vec![0x00, 0x61, 0x00, 0xE4, 0xD8, 0x3D, 0xDE, 0x00, 0x00, 0x00], bmp_string("a\u{e4}\u{1f600}"));
+        assert_eq!(vec![0x00, 0x00], bmp_string(""));
+    }
+}
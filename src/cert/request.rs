@@ -0,0 +1,286 @@
+use result::NSSResult;
+use ffi::{cert, pk11, sec};
+use ffi::nspr::PRBool;
+use crypto::pkey;
+use super::{pem, Extension, GeneralName, Name, NameBuilder};
+use std::{mem, ptr};
+use libc::c_void;
+
+/// Builds a PKCS#10 certificate signing request
+#[derive(Clone, Debug)]
+pub struct CertificateRequestBuilder
+{
+    subject: NameBuilder,
+    names: Vec<GeneralName>,
+    extensions: Vec<Extension>,
+}
+
+impl CertificateRequestBuilder
+{
+    pub fn new(subject: NameBuilder) -> CertificateRequestBuilder
+    {
+        CertificateRequestBuilder { subject: subject, names: Vec::new(), extensions: Vec::new() }
+    }
+
+    /// Requests a subjectAltName extension with these names
+    pub fn subject_alt_names(mut self, names: &[GeneralName]) -> CertificateRequestBuilder
+    {
+        self.names.extend(names.iter().cloned());
+        self
+    }
+
+    pub fn extension(mut self, extension: Extension) -> CertificateRequestBuilder
+    {
+        self.extensions.push(extension);
+        self
+    }
+
+    pub fn sign(&self, key: &mut pkey::PrivateKey, hash: pkey::HashAlgorithm) -> NSSResult<CertificateRequest>
+    {
+        try!(::nss::init());
+
+        let mut extensions = Vec::new();
+        if !self.names.is_empty() {
+            extensions.push(try!(Extension::subject_alt_name(&self.names)));
+        }
+        extensions.extend(self.extensions.iter().cloned());
+
+        let mut name = try!(self.subject.to_ffi());
+        let public = try!(key.public_key());
+        let mut request = unsafe
+        {
+            let mut spki = try!(pk11::PublicKeyInfo::wrap(cert::SECKEY_CreateSubjectPublicKeyInfo(public.get())));
+            try!(cert::CertificateRequest::wrap(cert::CERT_CreateCertificateRequest(name.get_mut(), spki.get_mut(), ptr::null_mut())))
+        };
+
+        if !extensions.is_empty() {
+            unsafe
+            {
+                try!(super::add_extensions(cert::CERT_StartCertificateRequestAttributes(request.get_mut()), &extensions));
+                try!(cert::CERT_FinishCertificateRequestAttributes(request.get_mut()).to_result());
+            }
+        }
+
+        let mut arena = try!(super::new_arena());
+        let mut encoded = sec::SECItem::empty();
+        unsafe
+        {
+            let template = cert::NSS_Get_CERT_CertificateRequestTemplate(ptr::null_mut(), PRBool::False);
+            if cert::SEC_ASN1EncodeItem(arena.get_mut(), encoded.get_mut(), request.get() as *const c_void, template).is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+        }
+
+        let signed = try!(super::sign_der(encoded.get().as_slice(), key, hash));
+        CertificateRequest::from_der(&signed)
+    }
+}
+
+/// A parsed PKCS#10 certificate signing request
+pub struct CertificateRequest
+{
+    // The decoded structures point into der and the arena
+    der: Vec<u8>,
+    subject_der: Vec<u8>,
+    signed: Box<cert::CERTSignedData>,
+    request: Box<cert::CERTCertificateRequest>,
+    _arena: cert::Arena,
+}
+
+impl CertificateRequest
+{
+    pub fn from_der(data: &[u8]) -> NSSResult<CertificateRequest>
+    {
+        try!(::nss::init());
+
+        let der = data.to_vec();
+        let mut arena = try!(super::new_arena());
+        let mut signed: Box<cert::CERTSignedData> = Box::new(unsafe { mem::zeroed() });
+        let mut request: Box<cert::CERTCertificateRequest> = Box::new(unsafe { mem::zeroed() });
+        request.arena = arena.get_mut();
+
+        let subject_der = unsafe
+        {
+            let item = sec::SECItem::from_buf(&der);
+            try!(cert::SEC_QuickDERDecodeItem(arena.get_mut(), &mut *signed as *mut _ as *mut c_void,
+                                              cert::NSS_Get_CERT_SignedDataTemplate(ptr::null_mut(), PRBool::False),
+                                              item.get()).to_result());
+            try!(cert::SEC_QuickDERDecodeItem(arena.get_mut(), &mut *request as *mut _ as *mut c_void,
+                                              cert::NSS_Get_CERT_CertificateRequestTemplate(ptr::null_mut(), PRBool::False),
+                                              &signed.data).to_result());
+
+            let subject = cert::SEC_ASN1EncodeItem(arena.get_mut(), ptr::null_mut(), &request.subject as *const _ as *const c_void,
+                                                   cert::NSS_Get_CERT_NameTemplate(ptr::null_mut(), PRBool::False));
+            if subject.is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+            (*subject).as_slice().to_vec()
+        };
+
+        Ok(CertificateRequest { der: der, subject_der: subject_der, signed: signed, request: request, _arena: arena })
+    }
+
+    pub fn from_pem(data: &str) -> NSSResult<CertificateRequest>
+    {
+        let der = try!(pem::decode("CERTIFICATE REQUEST", data));
+        CertificateRequest::from_der(&der)
+    }
+
+    pub fn to_der(&self) -> Vec<u8>
+    {
+        self.der.clone()
+    }
+
+    pub fn to_pem(&self) -> NSSResult<String>
+    {
+        pem::encode("CERTIFICATE REQUEST", &self.der)
+    }
+
+    pub fn subject<'a>(&'a self) -> Name<'a>
+    {
+        Name { name: &self.request.subject, der: &self.subject_der }
+    }
+
+    pub fn public_key(&self) -> NSSResult<pkey::PublicKey>
+    {
+        let spki = &self.request.subject_public_key_info as *const _ as *const pk11::CERTSubjectPublicKeyInfo;
+        pkey::PublicKey::from_raw(unsafe { pk11::SECKEY_ExtractPublicKey(spki) })
+    }
+
+    /// The extensions requested through the extensionRequest attribute
+    pub fn extensions(&self) -> NSSResult<Vec<Extension>>
+    {
+        let mut exts = ptr::null_mut();
+        let mut result = Vec::new();
+        unsafe
+        {
+            // Only allocates in the arena, the request itself is left untouched
+            let request = &*self.request as *const _ as *mut cert::CERTCertificateRequest;
+            try!(cert::CERT_GetCertificateRequestExtensions(request, &mut exts).to_result());
+            if exts.is_null() {
+                return Ok(result);
+            }
+
+            let mut current = exts;
+            while !(*current).is_null()
+            {
+                let extension = &**current;
+                result.push(Extension {
+                    oid: super::oid_to_string(extension.id.as_slice()),
+                    critical: extension.critical.as_slice().iter().any(|&b| b != 0),
+                    value: extension.value.as_slice().to_vec(),
+                });
+                current = current.offset(1);
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn subject_alt_names(&self) -> NSSResult<Vec<GeneralName>>
+    {
+        let extensions = try!(self.extensions());
+        match extensions.iter().find(|extension| extension.oid == super::OID_SUBJECT_ALT_NAME)
+        {
+            Some(extension) => GeneralName::decode_list(&extension.value),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Checks that the request is signed by the key it contains
+    pub fn verify(&self) -> NSSResult<()>
+    {
+        unsafe
+        {
+            let signed = &*self.signed as *const _ as *mut cert::CERTSignedData;
+            let spki = &self.request.subject_public_key_info as *const _ as *mut cert::CERTSubjectPublicKeyInfo;
+            cert::CERT_VerifySignedDataWithPublicKeyInfo(signed, spki, ptr::null_mut()).to_result()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::{BasicConstraints, Extension, GeneralName, NameBuilder};
+    use crypto::pkey::{Curve, HashAlgorithm, KeyType, PrivateKey, RSAPrivateKey};
+    use result::NSSError;
+    use ffi::sec;
+
+    static CSR_PEM : &'static str = "-----BEGIN CERTIFICATE REQUEST-----
+MIIBMTCB2QIBADArMQ8wDQYDVQQKDAZuc3MtcnMxGDAWBgNVBAMMD2Nzci5leGFt
+cGxlLmNvbTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABPnr5GQUfMEC2SMk0JnZ
+J8GlDkLVegihFv/sCimBnYxlcLr30SiIkCpqDB1nrdEDEjyFmrab+OlDHJOEkw1L
+fu6gTDBKBgkqhkiG9w0BCQ4xPTA7MCsGA1UdEQQkMCKCD2Nzci5leGFtcGxlLmNv
+bYIPd3d3LmV4YW1wbGUuY29tMAwGA1UdEwEB/wQCMAAwCgYIKoZIzj0EAwIDRwAw
+RAIgXLW9ewOqopr5cYuO6uL9IcHk5WnRtxyQVCcOVXGE+9ACIHzu1YxE2+A7aD6n
+uXmnHGvtmiYkDKXWVlgaVcDvv9px
+-----END CERTIFICATE REQUEST-----
+";
+
+    fn subject() -> NameBuilder
+    {
+        NameBuilder::new().country("NL").organization("nss-rs").common_name("request.example.com")
+    }
+
+    #[test]
+    fn rsa()
+    {
+        let mut key = PrivateKey::from(RSAPrivateKey::gen(1024).unwrap());
+        let names = vec![GeneralName::DNS("request.example.com".to_string()),
+                         GeneralName::IP("127.0.0.1".parse().unwrap())];
+        let request = CertificateRequestBuilder::new(subject())
+                        .subject_alt_names(&names)
+                        .extension(Extension::basic_constraints(BasicConstraints { ca: false, path_len: None }))
+                        .sign(&mut key, HashAlgorithm::SHA256).unwrap();
+
+        assert!(request.verify().is_ok());
+        assert_eq!("CN=request.example.com,O=nss-rs,C=NL", request.subject().to_string());
+        assert_eq!(names, request.subject_alt_names().unwrap());
+        assert_eq!(KeyType::RSA, request.public_key().unwrap().key_type());
+        assert_eq!(key.public_key().unwrap().save().unwrap(), request.public_key().unwrap().save().unwrap());
+
+        let extensions = request.extensions().unwrap();
+        assert_eq!(2, extensions.len());
+        let constraints = extensions.iter().find(|extension| extension.oid == "2.5.29.19").unwrap();
+        assert!(constraints.critical);
+        assert_eq!(vec![0x30, 0x00], constraints.value);
+
+        let parsed = CertificateRequest::from_pem(&request.to_pem().unwrap()).unwrap();
+        assert_eq!(request.to_der(), parsed.to_der());
+
+        let mut tampered = request.to_der();
+        let pos = tampered.windows(6).position(|w| w == b"nss-rs").unwrap();
+        tampered[pos] = b'N';
+        assert!(CertificateRequest::from_der(&tampered).unwrap().verify().is_err());
+    }
+
+    #[test]
+    fn ec()
+    {
+        let mut key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let request = CertificateRequestBuilder::new(subject()).sign(&mut key, HashAlgorithm::SHA384).unwrap();
+
+        assert!(request.verify().is_ok());
+        assert_eq!(KeyType::EC, request.public_key().unwrap().key_type());
+        assert!(request.extensions().unwrap().is_empty());
+        assert!(request.subject_alt_names().unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse()
+    {
+        let request = CertificateRequest::from_pem(CSR_PEM).unwrap();
+        assert!(request.verify().is_ok());
+        assert_eq!(Some("csr.example.com".to_string()), request.subject().common_name());
+        assert_eq!(vec![GeneralName::DNS("csr.example.com".to_string()), GeneralName::DNS("www.example.com".to_string())],
+                   request.subject_alt_names().unwrap());
+        assert_eq!(256, request.public_key().unwrap().key_bits().unwrap());
+
+        match CertificateRequest::from_der(&[0x30, 0x03, 0x02, 0x01, 0x00])
+        {
+            Err(NSSError::NSS(sec::SEC_ERROR_BAD_DER)) => {},
+            _ => panic!("expected SEC_ERROR_BAD_DER"),
+        }
+    }
+}
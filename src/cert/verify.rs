@@ -0,0 +1,392 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, sec};
+use cert::Certificate;
+use std::time::SystemTime;
+use std::{fmt, ptr};
+use libc::c_void;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Usage
+{
+    TLSServer,
+    TLSClient,
+    CodeSigning,
+    EmailSigning,
+    EmailEncryption,
+    OCSPResponder,
+}
+
+impl Usage
+{
+    fn to_ffi(&self) -> cert::SECCertificateUsage
+    {
+        match *self
+        {
+            Usage::TLSServer => cert::CERTIFICATE_USAGE_SSL_SERVER,
+            Usage::TLSClient => cert::CERTIFICATE_USAGE_SSL_CLIENT,
+            Usage::CodeSigning => cert::CERTIFICATE_USAGE_OBJECT_SIGNER,
+            Usage::EmailSigning => cert::CERTIFICATE_USAGE_EMAIL_SIGNER,
+            Usage::EmailEncryption => cert::CERTIFICATE_USAGE_EMAIL_RECIPIENT,
+            Usage::OCSPResponder => cert::CERTIFICATE_USAGE_STATUS_RESPONDER,
+        }
+    }
+}
+
+/// How to check the revocation status of the certificates in the chain
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Revocation
+{
+    Disabled,
+    /// Use CRLs and OCSP responses when they are available
+    SoftFail,
+    /// Fail unless fresh revocation information is available for every certificate
+    ///
+    /// A certificate without any fails as `VerifyError::Revoked`.
+    HardFail,
+}
+
+/// The reason a certificate failed to verify
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum VerifyError
+{
+    Expired,
+    ExpiredIssuer,
+    UnknownIssuer,
+    UntrustedIssuer,
+    UntrustedCertificate,
+    BadSignature,
+    InvalidCA,
+    PathLenConstraint,
+    InadequateKeyUsage,
+    InadequateCertType,
+    NameConstraint,
+    PolicyValidation,
+    Revoked,
+    RevocationUnknown,
+    Other(NSSError),
+}
+
+impl VerifyError
+{
+    fn from_nss(error: NSSError) -> VerifyError
+    {
+        let code = match error
+        {
+            NSSError::NSS(code) => code,
+            other => return VerifyError::Other(other),
+        };
+
+        match code
+        {
+            sec::SEC_ERROR_EXPIRED_CERTIFICATE => VerifyError::Expired,
+            sec::SEC_ERROR_EXPIRED_ISSUER_CERTIFICATE => VerifyError::ExpiredIssuer,
+            sec::SEC_ERROR_UNKNOWN_ISSUER => VerifyError::UnknownIssuer,
+            sec::SEC_ERROR_UNTRUSTED_ISSUER => VerifyError::UntrustedIssuer,
+            sec::SEC_ERROR_UNTRUSTED_CERT => VerifyError::UntrustedCertificate,
+            sec::SEC_ERROR_BAD_SIGNATURE => VerifyError::BadSignature,
+            sec::SEC_ERROR_CA_CERT_INVALID => VerifyError::InvalidCA,
+            sec::SEC_ERROR_PATH_LEN_CONSTRAINT_INVALID => VerifyError::PathLenConstraint,
+            sec::SEC_ERROR_INADEQUATE_KEY_USAGE => VerifyError::InadequateKeyUsage,
+            sec::SEC_ERROR_INADEQUATE_CERT_TYPE => VerifyError::InadequateCertType,
+            sec::SEC_ERROR_CERT_NOT_IN_NAME_SPACE => VerifyError::NameConstraint,
+            sec::SEC_ERROR_POLICY_VALIDATION_FAILED => VerifyError::PolicyValidation,
+            sec::SEC_ERROR_REVOKED_CERTIFICATE
+            | sec::SEC_ERROR_REVOKED_CERTIFICATE_CRL
+            | sec::SEC_ERROR_REVOKED_CERTIFICATE_OCSP => VerifyError::Revoked,
+            sec::SEC_ERROR_OCSP_UNKNOWN_CERT
+            | sec::SEC_ERROR_CRL_NOT_FOUND => VerifyError::RevocationUnknown,
+            _ => VerifyError::Other(error),
+        }
+    }
+
+    pub fn to_nss_error(&self) -> NSSError
+    {
+        let code = match *self
+        {
+            VerifyError::Expired => sec::SEC_ERROR_EXPIRED_CERTIFICATE,
+            VerifyError::ExpiredIssuer => sec::SEC_ERROR_EXPIRED_ISSUER_CERTIFICATE,
+            VerifyError::UnknownIssuer => sec::SEC_ERROR_UNKNOWN_ISSUER,
+            VerifyError::UntrustedIssuer => sec::SEC_ERROR_UNTRUSTED_ISSUER,
+            VerifyError::UntrustedCertificate => sec::SEC_ERROR_UNTRUSTED_CERT,
+            VerifyError::BadSignature => sec::SEC_ERROR_BAD_SIGNATURE,
+            VerifyError::InvalidCA => sec::SEC_ERROR_CA_CERT_INVALID,
+            VerifyError::PathLenConstraint => sec::SEC_ERROR_PATH_LEN_CONSTRAINT_INVALID,
+            VerifyError::InadequateKeyUsage => sec::SEC_ERROR_INADEQUATE_KEY_USAGE,
+            VerifyError::InadequateCertType => sec::SEC_ERROR_INADEQUATE_CERT_TYPE,
+            VerifyError::NameConstraint => sec::SEC_ERROR_CERT_NOT_IN_NAME_SPACE,
+            VerifyError::PolicyValidation => sec::SEC_ERROR_POLICY_VALIDATION_FAILED,
+            VerifyError::Revoked => sec::SEC_ERROR_REVOKED_CERTIFICATE,
+            VerifyError::RevocationUnknown => sec::SEC_ERROR_CRL_NOT_FOUND,
+            VerifyError::Other(error) => return error,
+        };
+        NSSError::NSS(code)
+    }
+}
+
+impl fmt::Display for VerifyError
+{
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error>
+    {
+        fmt::Display::fmt(&self.to_nss_error(), formatter)
+    }
+}
+
+/// Builds and validates certificate chains with libpkix
+#[derive(Clone)]
+pub struct Verifier
+{
+    usage: Usage,
+    anchors: Vec<Certificate>,
+    intermediates: Vec<Certificate>,
+    time: Option<SystemTime>,
+    policies: Vec<String>,
+    revocation: Revocation,
+    network_fetch: bool,
+}
+
+impl Verifier
+{
+    pub fn new(usage: Usage) -> Verifier
+    {
+        Verifier
+        {
+            usage: usage,
+            anchors: Vec::new(),
+            intermediates: Vec::new(),
+            time: None,
+            policies: Vec::new(),
+            revocation: Revocation::Disabled,
+            network_fetch: false,
+        }
+    }
+
+    /// Trust the given certificate. Once any anchor is set, only the given anchors are trusted.
+    pub fn trust_anchor(mut self, anchor: &Certificate) -> Verifier
+    {
+        self.anchors.push(anchor.clone());
+        self
+    }
+
+    /// Make a certificate available for path building. Certificates are looked up by subject, so
+    /// any other certificate that is alive at the time of verification may be used as well.
+    pub fn intermediate(mut self, intermediate: &Certificate) -> Verifier
+    {
+        self.intermediates.push(intermediate.clone());
+        self
+    }
+
+    /// Verify at the given time instead of now
+    pub fn time(mut self, time: SystemTime) -> Verifier
+    {
+        self.time = Some(time);
+        self
+    }
+
+    /// Require the chain to be valid for the policy, as a dotted OID. The chain has to be
+    /// valid for at least one of the given policies.
+    pub fn policy(mut self, oid: &str) -> Verifier
+    {
+        self.policies.push(oid.to_string());
+        self
+    }
+
+    pub fn revocation(mut self, revocation: Revocation) -> Verifier
+    {
+        self.revocation = revocation;
+        self
+    }
+
+    /// Allow fetching of intermediates and revocation information over the network
+    pub fn network_fetch(mut self, network_fetch: bool) -> Verifier
+    {
+        self.network_fetch = network_fetch;
+        self
+    }
+
+    /// Verifies the certificate, returning the chain from the certificate up to the trust anchor
+    pub fn verify(&self, certificate: &Certificate) -> Result<Vec<Certificate>, VerifyError>
+    {
+        self.verify_chain(certificate).map_err(VerifyError::from_nss)
+    }
+
+    fn verify_chain(&self, certificate: &Certificate) -> NSSResult<Vec<Certificate>>
+    {
+        let anchors = try!(super::to_cert_list(&self.anchors));
+        let policies = try!(self.policies.iter().map(|oid| super::oid_tag(oid)).collect::<NSSResult<Vec<_>>>());
+        let mut methods = [self.revocation_method_flags(); 2];
+        let revocation = cert::CERTRevocationFlags
+        {
+            leaf_tests: self.revocation_tests(&mut methods),
+            chain_tests: self.revocation_tests(&mut methods),
+        };
+
+        let mut params_in = vec![
+            cert::CERTValInParam::pointer(cert::CERTValParamInType::RevocationFlags, &revocation as *const _ as *const c_void),
+            cert::CERTValInParam::scalar(cert::CERTValParamInType::UseAIACertFetch,
+                                         cert::CERTValParamScalar { b: self.network_fetch as i32 }),
+        ];
+        if !self.anchors.is_empty() {
+            params_in.push(cert::CERTValInParam::pointer(cert::CERTValParamInType::TrustAnchors, anchors.get() as *const c_void));
+            params_in.push(cert::CERTValInParam::scalar(cert::CERTValParamInType::UseOnlyTrustAnchors,
+                                                        cert::CERTValParamScalar { b: 1 }));
+        }
+        if let Some(time) = self.time {
+            params_in.push(cert::CERTValInParam::scalar(cert::CERTValParamInType::Date,
+                                                        cert::CERTValParamScalar { time: super::to_prtime(time) }));
+        }
+        if !policies.is_empty() {
+            params_in.push(cert::CERTValInParam::array(cert::CERTValParamInType::PolicyOID,
+                                                       policies.as_ptr() as *const c_void, policies.len()));
+        }
+        params_in.push(cert::CERTValInParam::scalar(cert::CERTValParamInType::End, cert::CERTValParamScalar { ul: 0 }));
+
+        let mut params_out = [
+            cert::CERTValOutParam::pointer(cert::CERTValParamOutType::CertList, ptr::null()),
+            cert::CERTValOutParam::pointer(cert::CERTValParamOutType::TrustAnchor, ptr::null()),
+            cert::CERTValOutParam::pointer(cert::CERTValParamOutType::End, ptr::null()),
+        ];
+
+        unsafe
+        {
+            try!(cert::CERT_PKIXVerifyCert(certificate.cert.get() as *mut _, self.usage.to_ffi(), params_in.as_ptr(),
+                                           params_out.as_mut_ptr(), ptr::null_mut()).to_result());
+            let list = try!(cert::CertList::wrap(params_out[0].pointer as *mut cert::CERTCertList));
+            let anchor = try!(Certificate::from_raw(params_out[1].pointer as *mut cert::CERTCertificate));
+
+            // The validated chain does not include the trust anchor
+            let mut chain = try!(super::from_cert_list(&list));
+            chain.push(anchor);
+            Ok(chain)
+        }
+    }
+
+    fn revocation_method_flags(&self) -> u64
+    {
+        let network = match self.network_fetch
+        {
+            true => 0,
+            false => cert::CERT_REV_M_FORBID_NETWORK_FETCHING,
+        };
+        match self.revocation
+        {
+            Revocation::Disabled => 0,
+            Revocation::SoftFail | Revocation::HardFail => cert::CERT_REV_M_TEST_USING_THIS_METHOD | network,
+        }
+    }
+
+    fn revocation_tests(&self, methods: &mut [u64; 2]) -> cert::CERTRevocationTests
+    {
+        let flags = match self.revocation
+        {
+            Revocation::Disabled => 0,
+            Revocation::SoftFail => cert::CERT_REV_MI_TEST_ALL_LOCAL_INFORMATION_FIRST,
+            Revocation::HardFail => cert::CERT_REV_MI_TEST_ALL_LOCAL_INFORMATION_FIRST
+                                    | cert::CERT_REV_MI_REQUIRE_SOME_FRESH_INFO_AVAILABLE,
+        };
+        cert::CERTRevocationTests
+        {
+            number_of_defined_methods: methods.len() as u32,
+            cert_rev_flags_per_method: methods.as_mut_ptr(),
+            number_of_preferred_methods: 0,
+            preferred_methods: ptr::null_mut(),
+            cert_rev_method_independent_flags: flags,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    extern crate rustc_serialize as serialize;
+    use self::serialize::base64::FromBase64;
+    use super::{Verifier, Usage, Revocation, VerifyError};
+    use cert::Certificate;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // P-256 hierarchy: root -> intermediate (path length 0) -> leaves, all asserting policy 1.3.6.1.4.1.99999.2
+    static ROOT_BASE64 : &'static [u8] = b"MIIBbzCCARSgAwIBAgIBATAKBggqhkjOPQQDAjAuMQ8wDQYDVQQKDAZuc3MtcnMxGzAZBgNVBAMMEm5zcy1ycyB2ZXJpZnkgcm9vdDAeFw0yMDAxMDEwMDAwMDBaFw00MDAxMDEwMDAwMDBaMC4xDzANBgNVBAoMBm5zcy1yczEbMBkGA1UEAwwSbnNzLXJzIHZlcmlmeSByb290MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEz8dGWJ5KFAeFs7+Uxyaa0bF60ln75xfCdq4LDnSYM6+e4l0CC1vpeb5Pk2ficTIs6KEAau8OQfYR57sZMJeO+KMjMCEwDwYDVR0TAQH/BAUwAwEB/zAOBgNVHQ8BAf8EBAMCAQYwCgYIKoZIzj0EAwIDSQAwRgIhANrPM1cipx3VWiydScAmJsgT8NVg6y+plE+KR8yXHSYGAiEAxuHgLTcbTDFu7rv4CDPbnuzzF6NdxsQNdmoPM/cyxLY=";
+    static INTERMEDIATE_BASE64 : &'static [u8] = b"MIIBkjCCATegAwIBAgIBAjAKBggqhkjOPQQDAjAuMQ8wDQYDVQQKDAZuc3MtcnMxGzAZBgNVBAMMEm5zcy1ycyB2ZXJpZnkgcm9vdDAeFw0yMDAxMDEwMDAwMDBaFw00MDAxMDEwMDAwMDBaMDYxDzANBgNVBAoMBm5zcy1yczEjMCEGA1UEAwwabnNzLXJzIHZlcmlmeSBpbnRlcm1lZGlhdGUwWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAATgu04RMnL374W2+8Z56P0fHgjXrWTSeTLvosQp8BRLnrJgyR+AmrvFzwG31s56a11uFfUZ77ByRqrkFwOGl5zToz4wPDASBgNVHRMBAf8ECDAGAQH/AgEAMA4GA1UdDwEB/wQEAwIBBjAWBgNVHSAEDzANMAsGCSsGAQQBho0fAjAKBggqhkjOPQQDAgNJADBGAiEAymIMiHo4DzAwuNVLUmO4nP5TggXokwL68I7o/LaRScYCIQCRz5xNAXluUNYg+06SzrJqKD8wWymWBLyFyq3B8+5DIg==";
+    static LEAF_BASE64 : &'static [u8] = b"MIIBvjCCAWWgAwIBAgIBAzAKBggqhkjOPQQDAjA2MQ8wDQYDVQQKDAZuc3MtcnMxIzAhBgNVBAMMGm5zcy1ycyB2ZXJpZnkgaW50ZXJtZWRpYXRlMB4XDTIwMDEwMTAwMDAwMFoXDTQwMDEwMTAwMDAwMFowLjEPMA0GA1UECgwGbnNzLXJzMRswGQYDVQQDDBJ2ZXJpZnkuZXhhbXBsZS5jb20wWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAASZ+Ij/ESs/XWAzxhFPcjLW83mYcTHV7Sl/P7HB/Cqr9l1ssUOAJxsjTzUx2T+LOi3kv6KwcGrCgvyYN4nfzm9no2wwajAMBgNVHRMBAf8EAjAAMA4GA1UdDwEB/wQEAwIHgDAdBgNVHREEFjAUghJ2ZXJpZnkuZXhhbXBsZS5jb20wEwYDVR0lBAwwCgYIKwYBBQUHAwEwFgYDVR0gBA8wDTALBgkrBgEEAYaNHwIwCgYIKoZIzj0EAwIDRwAwRAIgKFBDxhbTtKawETj5XUN4j/XZgK1Bq6GK12c1SczuW3QCICOjHe4kUBLWW32LZU2wfle+pYRSK5cqo4eT/j4J8zb9";
+    static EXPIRED_LEAF_BASE64 : &'static [u8] = b"MIIBwDCCAWagAwIBAgIBBDAKBggqhkjOPQQDAjA2MQ8wDQYDVQQKDAZuc3MtcnMxIzAhBgNVBAMMGm5zcy1ycyB2ZXJpZnkgaW50ZXJtZWRpYXRlMB4XDTIwMDEwMTAwMDAwMFoXDTIxMDEwMTAwMDAwMFowLzEPMA0GA1UECgwGbnNzLXJzMRwwGgYDVQQDDBNleHBpcmVkLmV4YW1wbGUuY29tMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEmfiI/xErP11gM8YRT3Iy1vN5mHEx1e0pfz+xwfwqq/ZdbLFDgCcbI081Mdk/izot5L+isHBqwoL8mDeJ385vZ6NsMGowDAYDVR0TAQH/BAIwADAOBgNVHQ8BAf8EBAMCB4AwHQYDVR0RBBYwFIISdmVyaWZ5LmV4YW1wbGUuY29tMBMGA1UdJQQMMAoGCCsGAQUFBwMBMBYGA1UdIAQPMA0wCwYJKwYBBAGGjR8CMAoGCCqGSM49BAMCA0gAMEUCIQC+G/+cqx55rqNGoERozfCIYuk8/SmyhOFIVucdsZYoyAIgMOpO5+7Wso9IC8G2gJSDjKZcJg5BO7JV7K+W6VIqKLg=";
+    static CLIENT_LEAF_BASE64 : &'static [u8] = b"MIIBvzCCAWWgAwIBAgIBBTAKBggqhkjOPQQDAjA2MQ8wDQYDVQQKDAZuc3MtcnMxIzAhBgNVBAMMGm5zcy1ycyB2ZXJpZnkgaW50ZXJtZWRpYXRlMB4XDTIwMDEwMTAwMDAwMFoXDTQwMDEwMTAwMDAwMFowLjEPMA0GA1UECgwGbnNzLXJzMRswGQYDVQQDDBJjbGllbnQuZXhhbXBsZS5jb20wWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAASZ+Ij/ESs/XWAzxhFPcjLW83mYcTHV7Sl/P7HB/Cqr9l1ssUOAJxsjTzUx2T+LOi3kv6KwcGrCgvyYN4nfzm9no2wwajAMBgNVHRMBAf8EAjAAMA4GA1UdDwEB/wQEAwIHgDAdBgNVHREEFjAUghJ2ZXJpZnkuZXhhbXBsZS5jb20wEwYDVR0lBAwwCgYIKwYBBQUHAwIwFgYDVR0gBA8wDTALBgkrBgEEAYaNHwIwCgYIKoZIzj0EAwIDSAAwRQIhAP2F2K9y1EtEvaAntBZE+LqGLYZg6+NMYL6JXraAsYBGAiByKRp6evyMbwG0YjcbNH4N6kaD8oy2Hd44olG8ML3UGw==";
+    static OTHER_ROOT_BASE64 : &'static [u8] = b"MIIBejCCASCgAwIBAgIBBjAKBggqhkjOPQQDAjA0MQ8wDQYDVQQKDAZuc3MtcnMxITAfBgNVBAMMGG5zcy1ycyB2ZXJpZnkgb3RoZXIgcm9vdDAeFw0yMDAxMDEwMDAwMDBaFw00MDAxMDEwMDAwMDBaMDQxDzANBgNVBAoMBm5zcy1yczEhMB8GA1UEAwwYbnNzLXJzIHZlcmlmeSBvdGhlciByb290MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEZ/VpCKHSGdjgKnGc0kc4bUszTjPq6QiAVCAmcc4bqQ48QSt3QdSH25T77p2zadEemnAwbdnC73GBI0ddc36JAKMjMCEwDwYDVR0TAQH/BAUwAwEB/zAOBgNVHQ8BAf8EBAMCAQYwCgYIKoZIzj0EAwIDSAAwRQIgJGgAoyMd7cjQTN610Uul70G/OtAJjR2RJ65toDTCW78CIQDTDglPDLToXoHTlhfTJy5iwr+Z5p0/lk4v2fJ0nb8GQw==";
+    static SUB_CA_BASE64 : &'static [u8] = b"MIIBkDCCATagAwIBAgIBBzAKBggqhkjOPQQDAjA2MQ8wDQYDVQQKDAZuc3MtcnMxIzAhBgNVBAMMGm5zcy1ycyB2ZXJpZnkgaW50ZXJtZWRpYXRlMB4XDTIwMDEwMTAwMDAwMFoXDTQwMDEwMTAwMDAwMFowMDEPMA0GA1UECgwGbnNzLXJzMR0wGwYDVQQDDBRuc3MtcnMgdmVyaWZ5IHN1YiBDQTBZMBMGByqGSM49AgEGCCqGSM49AwEHA0IABNTPKZtSkEpncKgxPm2x/hL/9ZvcmrvDqklrvGFiq2zC/ElM56UqTIPk2KETXykkKP/tRUbCLXUe+TRlROUXafmjOzA5MA8GA1UdEwEB/wQFMAMBAf8wDgYDVR0PAQH/BAQDAgEGMBYGA1UdIAQPMA0wCwYJKwYBBAGGjR8CMAoGCCqGSM49BAMCA0gAMEUCIQDz85JF0qNubmeRU0BG29HciVEw0Y68WMQnziXoZ78ZqQIgBmVF/B1KkELpZxJkLC1HDP6DCjb+MAfp8EwL5VOFtKw=";
+    static SUB_LEAF_BASE64 : &'static [u8] = b"MIIBtjCCAVygAwIBAgIBCDAKBggqhkjOPQQDAjAwMQ8wDQYDVQQKDAZuc3MtcnMxHTAbBgNVBAMMFG5zcy1ycyB2ZXJpZnkgc3ViIENBMB4XDTIwMDEwMTAwMDAwMFoXDTQwMDEwMTAwMDAwMFowKzEPMA0GA1UECgwGbnNzLXJzMRgwFgYDVQQDDA9zdWIuZXhhbXBsZS5jb20wWTATBgcqhkjOPQIBBggqhkjOPQMBBwNCAASZ+Ij/ESs/XWAzxhFPcjLW83mYcTHV7Sl/P7HB/Cqr9l1ssUOAJxsjTzUx2T+LOi3kv6KwcGrCgvyYN4nfzm9no2wwajAMBgNVHRMBAf8EAjAAMA4GA1UdDwEB/wQEAwIHgDAdBgNVHREEFjAUghJ2ZXJpZnkuZXhhbXBsZS5jb20wEwYDVR0lBAwwCgYIKwYBBQUHAwEwFgYDVR0gBA8wDTALBgkrBgEEAYaNHwIwCgYIKoZIzj0EAwIDSAAwRQIgdCjvX1RLb3CW21Pf+KPmegemg8dso2kb1H51zKqX0LUCIQCOgo9XcHe+9WQ91ckn1Mi6B/4JZVOlVR//5drRMVBBSQ==";
+
+    fn load(data: &[u8]) -> Certificate
+    {
+        Certificate::from_der(&data.from_base64().unwrap()).unwrap()
+    }
+
+    fn verifier() -> Verifier
+    {
+        Verifier::new(Usage::TLSServer)
+            .trust_anchor(&load(ROOT_BASE64))
+            .intermediate(&load(INTERMEDIATE_BASE64))
+            .time(UNIX_EPOCH + Duration::from_secs(1893456000)) // 2030-01-01
+    }
+
+    #[test]
+    fn valid_chain()
+    {
+        let leaf = load(LEAF_BASE64);
+        let chain = verifier().verify(&leaf).unwrap();
+
+        let names: Vec<_> = chain.iter().map(|cert| cert.subject().common_name().unwrap()).collect();
+        assert_eq!(vec!["verify.example.com", "nss-rs verify intermediate", "nss-rs verify root"], names);
+        assert_eq!(leaf.to_der(), chain[0].to_der());
+    }
+
+    #[test]
+    fn failures()
+    {
+        let verifier = verifier();
+
+        assert_eq!(Err(VerifyError::Expired), verifier.verify(&load(EXPIRED_LEAF_BASE64)).map(|_| ()));
+        assert_eq!(Err(VerifyError::InadequateCertType), verifier.verify(&load(CLIENT_LEAF_BASE64)).map(|_| ()));
+
+        // The path length constraint leaves no valid path, which libpkix reports as an unknown issuer
+        let sub_ca = load(SUB_CA_BASE64);
+        assert_eq!(Err(VerifyError::UnknownIssuer),
+                   verifier.clone().intermediate(&sub_ca).verify(&load(SUB_LEAF_BASE64)).map(|_| ()));
+    }
+
+    #[test]
+    fn untrusted()
+    {
+        let other = Verifier::new(Usage::TLSServer).trust_anchor(&load(OTHER_ROOT_BASE64));
+        assert_eq!(Err(VerifyError::UntrustedIssuer), other.verify(&load(LEAF_BASE64)).map(|_| ()));
+    }
+
+    #[test]
+    fn usage()
+    {
+        let client = load(CLIENT_LEAF_BASE64);
+        let verifier = verifier();
+
+        assert!(Verifier { usage: Usage::TLSClient, .. verifier.clone() }.verify(&client).is_ok());
+        assert!(Verifier { usage: Usage::TLSServer, .. verifier.clone() }.verify(&client).is_err());
+    }
+
+    #[test]
+    fn policy()
+    {
+        let leaf = load(LEAF_BASE64);
+
+        assert!(verifier().policy("1.3.6.1.4.1.99999.2").verify(&leaf).is_ok());
+        assert!(verifier().policy("1.3.6.1.4.1.99999.3").policy("1.3.6.1.4.1.99999.2").verify(&leaf).is_ok());
+        assert_eq!(Err(VerifyError::PolicyValidation),
+                   verifier().policy("1.3.6.1.4.1.99999.3").verify(&leaf).map(|_| ()));
+        assert!(verifier().policy("not an oid").verify(&leaf).is_err());
+    }
+
+    #[test]
+    fn revocation()
+    {
+        let leaf = load(LEAF_BASE64);
+
+        assert!(verifier().revocation(Revocation::SoftFail).verify(&leaf).is_ok());
+        // Nothing says whether the leaf is revoked, which libpkix reports like a revocation
+        assert_eq!(Err(VerifyError::Revoked), verifier().revocation(Revocation::HardFail).verify(&leaf).map(|_| ()));
+    }
+}
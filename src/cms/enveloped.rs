@@ -0,0 +1,342 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, cms, sec};
+use ffi::nspr::PRBool;
+use cert::Certificate;
+use crypto::pkey::KeyType;
+use std::{mem, ptr};
+use libc::{c_char, c_ulong, c_void};
+
+/// The content encryption of an EnvelopedData message
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ContentEncryption
+{
+    AES_128_CBC,
+    AES_192_CBC,
+    AES_256_CBC,
+}
+
+impl ContentEncryption
+{
+    fn to_ffi(&self) -> (cert::SECOidTag, i32)
+    {
+        match *self
+        {
+            ContentEncryption::AES_128_CBC => (cert::SEC_OID_AES_128_CBC, 128),
+            ContentEncryption::AES_192_CBC => (cert::SEC_OID_AES_192_CBC, 192),
+            ContentEncryption::AES_256_CBC => (cert::SEC_OID_AES_256_CBC, 256),
+        }
+    }
+}
+
+/// Builds a CMS EnvelopedData message, like `cmsutil -E`
+#[derive(Clone)]
+pub struct EnvelopedDataBuilder
+{
+    recipients: Vec<Certificate>,
+    encryption: ContentEncryption,
+}
+
+impl EnvelopedDataBuilder
+{
+    /// Encrypts with AES-256-CBC by default
+    pub fn new() -> EnvelopedDataBuilder
+    {
+        EnvelopedDataBuilder {
+            recipients: Vec::new(),
+            encryption: ContentEncryption::AES_256_CBC,
+        }
+    }
+
+    /// Adds a recipient, who can decrypt the message with the private key of the certificate
+    ///
+    /// Only RSA keys are supported, with PKCS #1 v1.5 key transport.
+    pub fn recipient(mut self, certificate: &Certificate) -> EnvelopedDataBuilder
+    {
+        self.recipients.push(certificate.clone());
+        self
+    }
+
+    pub fn content_encryption(mut self, encryption: ContentEncryption) -> EnvelopedDataBuilder
+    {
+        self.encryption = encryption;
+        self
+    }
+
+    pub fn encrypt(&self, content: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        let mut encryptor = try!(self.encryptor());
+        let mut output = try!(encryptor.update(content));
+        output.extend(try!(encryptor.finalize()));
+        Ok(output)
+    }
+
+    /// Starts a message whose content is passed in pieces
+    pub fn encryptor(&self) -> NSSResult<Encryptor>
+    {
+        try!(::nss::init());
+
+        if self.recipients.is_empty() {
+            return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+        }
+        for recipient in &self.recipients
+        {
+            if try!(recipient.clone().public_key()).key_type() != KeyType::RSA {
+                return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ALGORITHM));
+            }
+        }
+
+        let mut message = unsafe { try!(cms::Message::wrap(cms::NSS_CMSMessage_Create(ptr::null_mut()))) };
+        let mut output = Box::new(Vec::new());
+        let context = unsafe
+        {
+            let (algorithm, key_size) = self.encryption.to_ffi();
+            let enveloped = cms::NSS_CMSEnvelopedData_Create(message.get_mut(), algorithm, key_size);
+            if enveloped.is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+            let info = cms::NSS_CMSMessage_GetContentInfo(message.get_mut());
+            if let sec::SECStatus::Failure = cms::NSS_CMSContentInfo_SetContent_EnvelopedData(message.get_mut(), info, enveloped) {
+                let error = ::ffi::nspr::get_error_code();
+                cms::NSS_CMSEnvelopedData_Destroy(enveloped);
+                return Err(error);
+            }
+
+            for recipient in &self.recipients
+            {
+                let recipient_info = cms::NSS_CMSRecipientInfo_Create(message.get_mut(), recipient.get() as *mut _);
+                if recipient_info.is_null() {
+                    return Err(::ffi::nspr::get_error_code());
+                }
+                if let sec::SECStatus::Failure = cms::NSS_CMSEnvelopedData_AddRecipient(enveloped, recipient_info) {
+                    let error = ::ffi::nspr::get_error_code();
+                    cms::NSS_CMSRecipientInfo_Destroy(recipient_info);
+                    return Err(error);
+                }
+            }
+            try!(cms::NSS_CMSContentInfo_SetContent_Data(message.get_mut(), cms::NSS_CMSEnvelopedData_GetContentInfo(enveloped),
+                                                         ptr::null_mut(), PRBool::False).to_result());
+
+            let output_ptr = &mut *output as *mut Vec<u8> as *mut c_void;
+            cms::NSS_CMSEncoder_Start(message.get_mut(), super::append_output, output_ptr, ptr::null_mut(), ptr::null_mut(),
+                                      ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(),
+                                      ptr::null_mut(), ptr::null_mut())
+        };
+        if context.is_null() {
+            return Err(::ffi::nspr::get_error_code());
+        }
+
+        Ok(Encryptor { context: context, output: output, _message: message })
+    }
+}
+
+impl Default for EnvelopedDataBuilder
+{
+    fn default() -> EnvelopedDataBuilder
+    {
+        EnvelopedDataBuilder::new()
+    }
+}
+
+/// Encrypts content piece by piece, returning the encoded message as it becomes available
+pub struct Encryptor
+{
+    // Null once finalized
+    context: *mut cms::NSSCMSEncoderContext,
+    // The encoder appends here, so it is boxed to keep its address
+    #[allow(clippy::box_collection)]
+    output: Box<Vec<u8>>,
+    _message: cms::Message,
+}
+
+impl Encryptor
+{
+    pub fn update(&mut self, content: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        if self.context.is_null() {
+            return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+        }
+        unsafe { try!(cms::NSS_CMSEncoder_Update(self.context, content.as_ptr() as *const c_char, content.len() as c_ulong).to_result()) };
+        Ok(mem::take(&mut *self.output))
+    }
+
+    /// Ends the content and returns the rest of the message
+    pub fn finalize(&mut self) -> NSSResult<Vec<u8>>
+    {
+        if self.context.is_null() {
+            return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+        }
+        // Finishing frees the context, also when it fails
+        let context = mem::replace(&mut self.context, ptr::null_mut());
+        unsafe { try!(cms::NSS_CMSEncoder_Finish(context).to_result()) };
+        Ok(mem::take(&mut *self.output))
+    }
+}
+
+impl Drop for Encryptor
+{
+    fn drop(&mut self)
+    {
+        if !self.context.is_null() {
+            unsafe { let _ = cms::NSS_CMSEncoder_Cancel(self.context); }
+        }
+    }
+}
+
+/// Decrypts an EnvelopedData message piece by piece, returning the content as it becomes available
+///
+/// NSS looks for the certificate and private key of a recipient in all open databases, which must be logged in to.
+/// The content is not authenticated and only known to come from an EnvelopedData message once `finalize` succeeds.
+pub struct Decryptor
+{
+    // Null once finalized
+    context: *mut cms::NSSCMSDecoderContext,
+    // The decoder appends here, so it is boxed to keep its address
+    #[allow(clippy::box_collection)]
+    output: Box<Vec<u8>>,
+}
+
+impl Decryptor
+{
+    pub fn new() -> NSSResult<Decryptor>
+    {
+        try!(::nss::init());
+
+        let mut output = Box::new(Vec::new());
+        let context = unsafe
+        {
+            cms::NSS_CMSDecoder_Start(ptr::null_mut(), super::append_output, &mut *output as *mut Vec<u8> as *mut c_void,
+                                      ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut())
+        };
+        if context.is_null() {
+            return Err(::ffi::nspr::get_error_code());
+        }
+
+        Ok(Decryptor { context: context, output: output })
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        if self.context.is_null() {
+            return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+        }
+        unsafe { try!(cms::NSS_CMSDecoder_Update(self.context, data.as_ptr() as *const c_char, data.len() as c_ulong).to_result()) };
+        Ok(mem::take(&mut *self.output))
+    }
+
+    /// Ends the message and returns the rest of the content
+    pub fn finalize(&mut self) -> NSSResult<Vec<u8>>
+    {
+        if self.context.is_null() {
+            return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+        }
+        // Finishing frees the context, also when it fails
+        let context = mem::replace(&mut self.context, ptr::null_mut());
+        let mut message = unsafe { try!(cms::Message::wrap(cms::NSS_CMSDecoder_Finish(context))) };
+        try!(super::outer_content(&mut message, cms::SEC_OID_PKCS7_ENVELOPED_DATA));
+        Ok(mem::take(&mut *self.output))
+    }
+}
+
+impl Drop for Decryptor
+{
+    fn drop(&mut self)
+    {
+        if !self.context.is_null() {
+            unsafe { cms::NSS_CMSDecoder_Cancel(self.context) };
+        }
+    }
+}
+
+/// Decrypts a complete EnvelopedData message
+pub fn decrypt(data: &[u8]) -> NSSResult<Vec<u8>>
+{
+    let mut decryptor = try!(Decryptor::new());
+    let mut content = try!(decryptor.update(data));
+    content.extend(try!(decryptor.finalize()));
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::{Certificate, NameBuilder};
+    use cert::builder::CertificateBuilder;
+    use cert::db::test::TempDir;
+    use crypto::pkey::{Curve, HashAlgorithm, PrivateKey};
+
+    fn certificate(name: &str, key: &mut PrivateKey) -> Certificate
+    {
+        CertificateBuilder::new(NameBuilder::new().common_name(name)).self_signed(key, HashAlgorithm::SHA256).unwrap()
+    }
+
+    #[test]
+    fn encrypt_and_decrypt()
+    {
+        let dir = TempDir::new("cms-enveloped");
+
+        let mut key = PrivateKey::gen_rsa(2048).unwrap();
+        let recipient = certificate("nss-rs cms recipient", &mut key);
+        let mut other_key = PrivateKey::gen_rsa(2048).unwrap();
+        let other = certificate("nss-rs cms other", &mut other_key);
+
+        let mut db = dir.open("nss-rs cms enveloped");
+        db.import_private_key(&key.save().unwrap()).unwrap();
+        db.add(&recipient, "recipient").unwrap();
+
+        let content = b"backup";
+        let message = EnvelopedDataBuilder::new()
+                        .recipient(&other)
+                        .recipient(&recipient)
+                        .content_encryption(ContentEncryption::AES_128_CBC)
+                        .encrypt(content).unwrap();
+        assert_eq!(content.to_vec(), decrypt(&message).unwrap());
+
+        let message = EnvelopedDataBuilder::new().recipient(&other).encrypt(content).unwrap();
+        assert!(decrypt(&message).is_err());
+    }
+
+    #[test]
+    fn streaming()
+    {
+        let dir = TempDir::new("cms-streaming");
+
+        let mut key = PrivateKey::gen_rsa(2048).unwrap();
+        let recipient = certificate("nss-rs cms streaming", &mut key);
+        let mut db = dir.open("nss-rs cms streaming");
+        db.import_private_key(&key.save().unwrap()).unwrap();
+        db.add(&recipient, "recipient").unwrap();
+
+        let content: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+        let mut encryptor = EnvelopedDataBuilder::new().recipient(&recipient).encryptor().unwrap();
+        let mut message = Vec::new();
+        for chunk in content.chunks(10000)
+        {
+            message.extend(encryptor.update(chunk).unwrap());
+        }
+        message.extend(encryptor.finalize().unwrap());
+        assert!(encryptor.finalize().is_err());
+
+        let mut decryptor = Decryptor::new().unwrap();
+        let mut decrypted = Vec::new();
+        for chunk in message.chunks(7777)
+        {
+            decrypted.extend(decryptor.update(chunk).unwrap());
+        }
+        assert!(!decrypted.is_empty());
+        decrypted.extend(decryptor.finalize().unwrap());
+        assert_eq!(content, decrypted);
+    }
+
+    #[test]
+    fn ec_recipient_unsupported()
+    {
+        let mut key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let recipient = certificate("nss-rs cms ec", &mut key);
+        let mut rsa_key = PrivateKey::gen_rsa(2048).unwrap();
+        let other = certificate("nss-rs cms rsa", &mut rsa_key);
+        let builder = EnvelopedDataBuilder::new().recipient(&other).recipient(&recipient);
+        assert_eq!(Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ALGORITHM)), builder.encrypt(b"backup"));
+    }
+}
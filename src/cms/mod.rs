@@ -0,0 +1,67 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, cms, sec};
+use std::ptr;
+use libc::{c_char, c_ulong, c_void};
+
+pub mod enveloped;
+pub mod signed;
+
+fn decode(data: &[u8]) -> NSSResult<cms::Message>
+{
+    try!(::nss::init());
+
+    let mut der = sec::SECItem::from_buf(data);
+    unsafe
+    {
+        cms::Message::wrap(cms::NSS_CMSMessage_CreateFromDER(der.get_mut(), ptr::null_mut(), ptr::null_mut(), ptr::null_mut(),
+                                                             ptr::null_mut(), ptr::null_mut(), ptr::null_mut()))
+    }
+}
+
+/// The outer content of the message, which the message owns
+fn outer_content(message: &mut cms::Message, tag: cert::SECOidTag) -> NSSResult<*mut c_void>
+{
+    unsafe
+    {
+        let info = cms::NSS_CMSMessage_ContentLevel(message.get_mut(), 0);
+        if info.is_null() || cms::NSS_CMSContentInfo_GetContentTypeTag(info) != tag {
+            return Err(NSSError::NSS(sec::SEC_ERROR_BAD_DER));
+        }
+        match cms::NSS_CMSContentInfo_GetContent(info)
+        {
+            content if content.is_null() => Err(NSSError::NSS(sec::SEC_ERROR_BAD_DER)),
+            content => Ok(content),
+        }
+    }
+}
+
+/// The innermost content, if it is not detached
+///
+/// NSS decodes detached content as empty, so empty content counts as detached as well.
+fn inner_content(message: &cms::Message) -> Option<Vec<u8>>
+{
+    let content = unsafe { cms::NSS_CMSMessage_GetContent(message.get() as *mut _) };
+    if content.is_null() || unsafe { (*content).as_slice().is_empty() } {
+        return None;
+    }
+    Some(unsafe { (*content).as_slice().to_vec() })
+}
+
+fn encode(message: &mut cms::Message, content: &[u8]) -> NSSResult<Vec<u8>>
+{
+    let mut arena = try!(::cert::new_arena());
+    let mut input = sec::SECItem::from_buf(content);
+    let mut output = sec::SECItem::empty();
+    unsafe { try!(cms::NSS_CMSDEREncode(message.get_mut(), input.get_mut(), output.get_mut(), arena.get_mut()).to_result()) };
+    Ok(output.copy_buf())
+}
+
+/// Collects the output of the streaming encoder and decoder
+extern "C" fn append_output(arg: *mut c_void, buf: *const c_char, len: c_ulong)
+{
+    if len == 0 {
+        return;
+    }
+    let output = unsafe { &mut *(arg as *mut Vec<u8>) };
+    output.extend_from_slice(unsafe { ::std::slice::from_raw_parts(buf as *const u8, len as usize) });
+}
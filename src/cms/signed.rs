@@ -0,0 +1,402 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, cms, sec};
+use ffi::nspr::PRBool;
+use cert::Certificate;
+use cert::verify::{Verifier, VerifyError};
+use crypto::pkey::{HashAlgorithm, KeyType};
+use std::ptr;
+use std::time::SystemTime;
+
+/// The certificates to include in the message for a signer
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CertificateChain
+{
+    None,
+    Certificate,
+    /// The certificate and its issuers, except the root
+    Chain,
+    ChainWithRoot,
+}
+
+impl CertificateChain
+{
+    fn to_ffi(&self) -> cms::NSSCMSCertChainMode
+    {
+        match *self
+        {
+            CertificateChain::None => cms::NSSCMSCM_NONE,
+            CertificateChain::Certificate => cms::NSSCMSCM_CERT_ONLY,
+            CertificateChain::Chain => cms::NSSCMSCM_CERT_CHAIN,
+            CertificateChain::ChainWithRoot => cms::NSSCMSCM_CERT_CHAIN_WITH_ROOT,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct SignerOptions
+{
+    certificate: Certificate,
+    hash: HashAlgorithm,
+    chain: CertificateChain,
+}
+
+/// Builds a CMS SignedData message, like `cmsutil -S`
+#[derive(Clone)]
+pub struct SignedDataBuilder
+{
+    signers: Vec<SignerOptions>,
+    certificates: Vec<Certificate>,
+    detached: bool,
+    signed_attributes: bool,
+    signing_time: Option<SystemTime>,
+}
+
+impl SignedDataBuilder
+{
+    pub fn new() -> SignedDataBuilder
+    {
+        SignedDataBuilder {
+            signers: Vec::new(),
+            certificates: Vec::new(),
+            detached: false,
+            signed_attributes: true,
+            signing_time: None,
+        }
+    }
+
+    /// Adds a signer, whose private key must be stored in a database
+    pub fn signer(mut self, certificate: &Certificate, hash: HashAlgorithm, chain: CertificateChain) -> SignedDataBuilder
+    {
+        self.signers.push(SignerOptions { certificate: certificate.clone(), hash: hash, chain: chain });
+        self
+    }
+
+    /// Includes another certificate in the message, which NSS only supports if no signer uses `CertificateChain::None`
+    pub fn certificate(mut self, certificate: &Certificate) -> SignedDataBuilder
+    {
+        self.certificates.push(certificate.clone());
+        self
+    }
+
+    /// Leaves the content out of the message, for a detached signature
+    pub fn detached(mut self, detached: bool) -> SignedDataBuilder
+    {
+        self.detached = detached;
+        self
+    }
+
+    /// Whether to sign the content type, digest and signing time along with the content, which is the default
+    ///
+    /// NSS can only leave them out for RSA signers.
+    pub fn signed_attributes(mut self, enabled: bool) -> SignedDataBuilder
+    {
+        self.signed_attributes = enabled;
+        self
+    }
+
+    /// The signing time in the signed attributes, instead of the current time
+    pub fn signing_time(mut self, time: SystemTime) -> SignedDataBuilder
+    {
+        self.signing_time = Some(time);
+        self
+    }
+
+    pub fn sign(&self, content: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        try!(::nss::init());
+
+        // NSS crashes on extra certificates next to a signer without any
+        let without_certificates = self.signers.iter().any(|s| s.chain == CertificateChain::None);
+        if self.signers.is_empty() || (without_certificates && !self.certificates.is_empty()) {
+            return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+        }
+        // Without signed attributes NSS produces EC signatures in the wrong format
+        if !self.signed_attributes {
+            for signer in &self.signers
+            {
+                if try!(signer.certificate.clone().public_key()).key_type() != KeyType::RSA {
+                    return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ALGORITHM));
+                }
+            }
+        }
+
+        let signing_time = ::cert::to_prtime(self.signing_time.unwrap_or_else(SystemTime::now));
+        let mut message = unsafe { try!(cms::Message::wrap(cms::NSS_CMSMessage_Create(ptr::null_mut()))) };
+        unsafe
+        {
+            let signed = cms::NSS_CMSSignedData_Create(message.get_mut());
+            if signed.is_null() {
+                return Err(::ffi::nspr::get_error_code());
+            }
+            let info = cms::NSS_CMSMessage_GetContentInfo(message.get_mut());
+            if let sec::SECStatus::Failure = cms::NSS_CMSContentInfo_SetContent_SignedData(message.get_mut(), info, signed) {
+                let error = ::ffi::nspr::get_error_code();
+                cms::NSS_CMSSignedData_Destroy(signed);
+                return Err(error);
+            }
+
+            let detached = if self.detached { PRBool::True } else { PRBool::False };
+            try!(cms::NSS_CMSContentInfo_SetContent_Data(message.get_mut(), cms::NSS_CMSSignedData_GetContentInfo(signed),
+                                                         ptr::null_mut(), detached).to_result());
+
+            for signer in &self.signers
+            {
+                // The signer info lives in the arena of the message
+                let signer_info = cms::NSS_CMSSignerInfo_Create(message.get_mut(), signer.certificate.get() as *mut _,
+                                                                signer.hash.to_oid_tag());
+                if signer_info.is_null() {
+                    return Err(::ffi::nspr::get_error_code());
+                }
+                try!(cms::NSS_CMSSignerInfo_IncludeCerts(signer_info, signer.chain.to_ffi(), cms::CERT_USAGE_EMAIL_SIGNER).to_result());
+                if self.signed_attributes {
+                    try!(cms::NSS_CMSSignerInfo_AddSigningTime(signer_info, signing_time).to_result());
+                }
+                try!(cms::NSS_CMSSignedData_AddSignerInfo(signed, signer_info).to_result());
+            }
+            for certificate in &self.certificates
+            {
+                try!(cms::NSS_CMSSignedData_AddCertificate(signed, certificate.get() as *mut _).to_result());
+            }
+        }
+
+        super::encode(&mut message, content)
+    }
+}
+
+impl Default for SignedDataBuilder
+{
+    fn default() -> SignedDataBuilder
+    {
+        SignedDataBuilder::new()
+    }
+}
+
+/// A signer whose signature and certificate verified
+pub struct Signer
+{
+    pub certificate: Certificate,
+    /// The verified chain, from the certificate up to the trust anchor
+    pub chain: Vec<Certificate>,
+    /// The signing time from the signed attributes, if there is one
+    pub signing_time: Option<SystemTime>,
+}
+
+/// A decoded CMS SignedData message
+pub struct SignedData
+{
+    message: cms::Message,
+    // Owned by the message
+    signed: *mut cms::NSSCMSSignedData,
+}
+
+impl SignedData
+{
+    pub fn from_der(data: &[u8]) -> NSSResult<SignedData>
+    {
+        let mut message = try!(super::decode(data));
+        let signed = try!(super::outer_content(&mut message, cms::SEC_OID_PKCS7_SIGNED_DATA));
+        Ok(SignedData { message: message, signed: signed as *mut cms::NSSCMSSignedData })
+    }
+
+    /// The signed content, or None for a detached signature or empty content
+    pub fn content(&self) -> Option<Vec<u8>>
+    {
+        super::inner_content(&self.message)
+    }
+
+    pub fn signer_count(&self) -> usize
+    {
+        unsafe { cms::NSS_CMSSignedData_SignerInfoCount(self.signed) as usize }
+    }
+
+    /// Verifies every signature on the content in the message, and the signer certificates with the verifier
+    pub fn verify(&self, verifier: &Verifier) -> Result<Vec<Signer>, VerifyError>
+    {
+        match self.content()
+        {
+            Some(content) => self.verify_content(&content, verifier),
+            None => Err(VerifyError::Other(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS))),
+        }
+    }
+
+    /// Verifies a detached signature on the content
+    pub fn verify_detached(&self, content: &[u8], verifier: &Verifier) -> Result<Vec<Signer>, VerifyError>
+    {
+        self.verify_content(content, verifier)
+    }
+
+    fn verify_content(&self, content: &[u8], verifier: &Verifier) -> Result<Vec<Signer>, VerifyError>
+    {
+        // A message without signers vouches for nothing
+        if self.signer_count() == 0 {
+            return Err(VerifyError::BadSignature);
+        }
+
+        let mut signers = Vec::new();
+        unsafe
+        {
+            // Makes the certificates in the message available to find the signers and their issuers
+            let certdb = cert::CERT_GetDefaultCertDB();
+            try!(cms::NSS_CMSSignedData_ImportCerts(self.signed, certdb, cms::CERT_USAGE_EMAIL_SIGNER, PRBool::False).to_result()
+                    .map_err(VerifyError::Other));
+
+            let content_type = cms::NSS_CMSContentInfo_GetContentTypeTag(cms::NSS_CMSSignedData_GetContentInfo(self.signed));
+            let mut content_type = match cert::SECOID_FindOIDByTag(content_type)
+            {
+                oid if oid.is_null() => return Err(VerifyError::Other(::ffi::nspr::get_error_code())),
+                oid => (*oid).oid,
+            };
+
+            for index in 0..self.signer_count()
+            {
+                let signer_info = cms::NSS_CMSSignedData_GetSignerInfo(self.signed, index as i32);
+                // The certificate belongs to the signer info
+                let found = cms::NSS_CMSSignerInfo_GetSigningCertificate(signer_info, certdb);
+                if found.is_null() {
+                    return Err(VerifyError::Other(::ffi::nspr::get_error_code()));
+                }
+                let certificate = try!(Certificate::from_raw(cert::CERT_DupCertificate(found)).map_err(VerifyError::Other));
+
+                let digest = try!(digest(&(*signer_info).digest_alg, content).map_err(VerifyError::Other));
+                let mut digest = sec::SECItem::from_buf(&digest);
+                if let sec::SECStatus::Failure = cms::NSS_CMSSignerInfo_Verify(signer_info, digest.get_mut(), &mut content_type) {
+                    return Err(match cms::NSS_CMSSignerInfo_GetVerificationStatus(signer_info)
+                    {
+                        cms::NSSCMSVS_BAD_SIGNATURE | cms::NSSCMSVS_DIGEST_MISMATCH => VerifyError::BadSignature,
+                        _ => VerifyError::Other(::ffi::nspr::get_error_code()),
+                    });
+                }
+
+                let chain = try!(verifier.verify(&certificate));
+                let mut time = 0;
+                let signing_time = match cms::NSS_CMSSignerInfo_GetSigningTime(signer_info, &mut time)
+                {
+                    sec::SECStatus::Success => Some(::cert::from_prtime(time)),
+                    _ => None,
+                };
+                signers.push(Signer { certificate: certificate, chain: chain, signing_time: signing_time });
+            }
+        }
+        Ok(signers)
+    }
+}
+
+fn digest(algorithm: &cert::SECAlgorithmID, content: &[u8]) -> NSSResult<Vec<u8>>
+{
+    let tag = unsafe { cert::SECOID_FindOIDTag(&algorithm.algorithm) };
+    let hash = match HashAlgorithm::from_oid_tag(tag)
+    {
+        Some(hash) => hash,
+        None => return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ALGORITHM)),
+    };
+    let mut out = vec![0; hash.output_len()];
+    unsafe { try!(cert::PK11_HashBuf(tag, out.as_mut_ptr(), content.as_ptr(), content.len() as i32).to_result()) };
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::{BasicConstraints, Certificate, ExtendedKeyUsage, Extension, KeyUsage, NameBuilder};
+    use cert::builder::CertificateBuilder;
+    use cert::db::test::TempDir;
+    use cert::verify::{Usage, Verifier, VerifyError};
+    use crypto::pkey::{Curve, HashAlgorithm, PrivateKey};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn signer(name: &str, key: &mut PrivateKey, root: &Certificate, root_key: &mut PrivateKey) -> Certificate
+    {
+        CertificateBuilder::new(NameBuilder::new().common_name(name))
+            .extension(Extension::key_usage(&[KeyUsage::DigitalSignature]))
+            .extension(Extension::extended_key_usage(&[ExtendedKeyUsage::EmailProtection]).unwrap())
+            .sign(&key.public_key().unwrap(), root, root_key, HashAlgorithm::SHA256).unwrap()
+    }
+
+    #[test]
+    fn sign_and_verify()
+    {
+        let dir = TempDir::new("cms");
+
+        let mut root_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let root = CertificateBuilder::new(NameBuilder::new().common_name("nss-rs cms root"))
+                    .extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: None }))
+                    .extension(Extension::key_usage(&[KeyUsage::KeyCertSign]))
+                    .self_signed(&mut root_key, HashAlgorithm::SHA256).unwrap();
+        let mut rsa_key = PrivateKey::gen_rsa(2048).unwrap();
+        let rsa = signer("nss-rs cms rsa", &mut rsa_key, &root, &mut root_key);
+        let mut ec_key = PrivateKey::gen_ec(Curve::P384).unwrap();
+        let ec = signer("nss-rs cms ec", &mut ec_key, &root, &mut root_key);
+
+        let mut db = dir.open("nss-rs cms");
+        db.import_private_key(&rsa_key.save().unwrap()).unwrap();
+        db.add(&rsa, "rsa").unwrap();
+        db.import_private_key(&ec_key.save().unwrap()).unwrap();
+        db.add(&ec, "ec").unwrap();
+
+        let content = b"update manifest";
+        let signing_time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let attached = SignedDataBuilder::new()
+                        .signer(&rsa, HashAlgorithm::SHA256, CertificateChain::Certificate)
+                        .signer(&ec, HashAlgorithm::SHA384, CertificateChain::Chain)
+                        .certificate(&root)
+                        .signing_time(signing_time)
+                        .sign(content).unwrap();
+        let detached = SignedDataBuilder::new()
+                        .signer(&rsa, HashAlgorithm::SHA256, CertificateChain::None)
+                        .detached(true)
+                        .signed_attributes(false)
+                        .sign(content).unwrap();
+
+        let verifier = Verifier::new(Usage::EmailSigning).trust_anchor(&root);
+        let message = SignedData::from_der(&attached).unwrap();
+        assert_eq!(Some(content.to_vec()), message.content());
+        assert_eq!(2, message.signer_count());
+        let signers = message.verify(&verifier).unwrap();
+        // The signer infos are a DER set, so their order is not kept
+        let mut certificates: Vec<_> = signers.iter().map(|s| s.certificate.to_der()).collect();
+        certificates.sort();
+        let mut expected = vec![rsa.to_der(), ec.to_der()];
+        expected.sort();
+        assert_eq!(expected, certificates);
+        for signer in &signers
+        {
+            assert_eq!(root.to_der(), signer.chain.last().unwrap().to_der());
+            assert_eq!(Some(signing_time), signer.signing_time);
+        }
+
+        let message = SignedData::from_der(&detached).unwrap();
+        assert_eq!(None, message.content());
+        assert!(message.verify(&verifier).is_err());
+        let signers = message.verify_detached(content, &verifier).unwrap();
+        assert_eq!(rsa.to_der(), signers[0].certificate.to_der());
+        assert_eq!(None, signers[0].signing_time);
+        assert_eq!(Some(VerifyError::BadSignature), message.verify_detached(b"other manifest", &verifier).err());
+
+        assert!(SignedDataBuilder::new().signer(&ec, HashAlgorithm::SHA256, CertificateChain::None)
+                    .signed_attributes(false).sign(content).is_err());
+
+        let mut tampered = attached.clone();
+        let pos = tampered.windows(content.len()).position(|w| w == content).unwrap();
+        tampered[pos] = b'U';
+        assert_eq!(Some(VerifyError::BadSignature), SignedData::from_der(&tampered).unwrap().verify(&verifier).err());
+
+        let mut other_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let other_root = CertificateBuilder::new(NameBuilder::new().common_name("nss-rs cms other root"))
+                            .self_signed(&mut other_key, HashAlgorithm::SHA256).unwrap();
+        let untrusting = Verifier::new(Usage::EmailSigning).trust_anchor(&other_root);
+        assert!(SignedData::from_der(&attached).unwrap().verify(&untrusting).is_err());
+    }
+
+    #[test]
+    fn without_signers()
+    {
+        assert!(SignedDataBuilder::new().sign(b"content").is_err());
+        let mut key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let certificate = CertificateBuilder::new(NameBuilder::new().common_name("nss-rs cms"))
+                            .self_signed(&mut key, HashAlgorithm::SHA256).unwrap();
+        assert!(SignedDataBuilder::new().signer(&certificate, HashAlgorithm::SHA256, CertificateChain::None)
+                    .certificate(&certificate).sign(b"content").is_err());
+        assert!(SignedData::from_der(&[0x30, 0x03, 0x02, 0x01, 0x00]).is_err());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod symm;
 pub mod pkey;
+pub mod hpke;
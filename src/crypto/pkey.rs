@@ -1,5 +1,6 @@
-use result::NSSResult;
-use ffi::{pk11, sec};
+use result::{NSSResult, NSSError};
+use ffi::{pk11, sec, cert};
+use crypto::symm;
 use ffi::nspr::PRBool;
 use std::ptr;
 use libc::{c_uint, c_int, c_void};
@@ -16,10 +17,109 @@ pub enum RSAPadding
     OAEP_MGF1_SHA512,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HashAlgorithm
+{
+    SHA1,
+    SHA224,
+    SHA256,
+    SHA384,
+    SHA512,
+}
+
+impl HashAlgorithm
+{
+    pub(crate) fn to_oid_tag(&self) -> cert::SECOidTag
+    {
+        match *self
+        {
+            HashAlgorithm::SHA1 => cert::SEC_OID_SHA1,
+            HashAlgorithm::SHA224 => cert::SEC_OID_SHA224,
+            HashAlgorithm::SHA256 => cert::SEC_OID_SHA256,
+            HashAlgorithm::SHA384 => cert::SEC_OID_SHA384,
+            HashAlgorithm::SHA512 => cert::SEC_OID_SHA512,
+        }
+    }
+
+    pub(crate) fn from_oid_tag(tag: cert::SECOidTag) -> Option<HashAlgorithm>
+    {
+        match tag
+        {
+            cert::SEC_OID_SHA1 => Some(HashAlgorithm::SHA1),
+            cert::SEC_OID_SHA224 => Some(HashAlgorithm::SHA224),
+            cert::SEC_OID_SHA256 => Some(HashAlgorithm::SHA256),
+            cert::SEC_OID_SHA384 => Some(HashAlgorithm::SHA384),
+            cert::SEC_OID_SHA512 => Some(HashAlgorithm::SHA512),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn output_len(&self) -> usize
+    {
+        pk11::hash_len_from_ckm(self.to_ckm()).unwrap()
+    }
+
+    fn to_ckm(&self) -> pk11::CK_MECHANISM_TYPE
+    {
+        match *self
+        {
+            HashAlgorithm::SHA1 => pk11::CKM_SHA_1,
+            HashAlgorithm::SHA224 => pk11::CKM_SHA_224,
+            HashAlgorithm::SHA256 => pk11::CKM_SHA_256,
+            HashAlgorithm::SHA384 => pk11::CKM_SHA_384,
+            HashAlgorithm::SHA512 => pk11::CKM_SHA_512,
+        }
+    }
+}
+
+/// Parameters for OAEP padding, with an independent MGF1 hash and an optional label
+#[derive(Clone)]
+pub struct OAEPParams
+{
+    hash: HashAlgorithm,
+    mgf1_hash: HashAlgorithm,
+    label: Vec<u8>,
+}
+
+impl OAEPParams
+{
+    /// Uses `hash` for both the label hash and MGF1, with an empty label
+    pub fn new(hash: HashAlgorithm) -> OAEPParams
+    {
+        OAEPParams
+        {
+            hash: hash,
+            mgf1_hash: hash,
+            label: Vec::new(),
+        }
+    }
+
+    pub fn mgf1_hash(mut self, hash: HashAlgorithm) -> OAEPParams
+    {
+        self.mgf1_hash = hash;
+        self
+    }
+
+    pub fn label(mut self, label: &[u8]) -> OAEPParams
+    {
+        self.label = label.to_vec();
+        self
+    }
+
+    // The returned parameter points into self.label
+    fn get_param(&self) -> NSSResult<RSAPaddingParam>
+    {
+        let hash_len = try!(pk11::hash_len_from_ckm(self.hash.to_ckm()));
+        let param = try!(pk11::CK_RSA_PKCS_OAEP_PARAMS::new(self.hash.to_ckm(), self.mgf1_hash.to_ckm(), &self.label));
+        Ok(RSAPaddingParam::OAEP(param, hash_len))
+    }
+}
+
 enum RSAPaddingParam
 {
-    NullParam,
-    OAEPParam(pk11::CK_RSA_PKCS_OAEP_PARAMS),
+    Raw,
+    PKCS1,
+    OAEP(pk11::CK_RSA_PKCS_OAEP_PARAMS, usize),
 }
 
 impl RSAPaddingParam
@@ -28,65 +128,228 @@ impl RSAPaddingParam
     {
         match *self
         {
-            RSAPaddingParam::NullParam => sec::SECItem::empty(),
-            RSAPaddingParam::OAEPParam(ref param) => sec::SECItem::from_struct(param),
+            RSAPaddingParam::Raw
+            | RSAPaddingParam::PKCS1 => sec::SECItem::empty(),
+            RSAPaddingParam::OAEP(ref param, _) => sec::SECItem::from_struct(param),
         }
     }
-}
 
-impl RSAPadding
-{
     fn to_ckm(&self) -> pk11::CK_MECHANISM_TYPE
     {
         match *self
         {
-            RSAPadding::PKCS1v15 => pk11::CKM_RSA_PKCS,
-            RSAPadding::OAEP_MGF1_SHA1
-            | RSAPadding::OAEP_MGF1_SHA224
-            | RSAPadding::OAEP_MGF1_SHA256
-            | RSAPadding::OAEP_MGF1_SHA384
-            | RSAPadding::OAEP_MGF1_SHA512 => pk11::CKM_RSA_PKCS_OAEP,
+            RSAPaddingParam::Raw => pk11::CKM_RSA_X_509,
+            RSAPaddingParam::PKCS1 => pk11::CKM_RSA_PKCS,
+            RSAPaddingParam::OAEP(..) => pk11::CKM_RSA_PKCS_OAEP,
         }
     }
 
-    fn get_param(&self) -> RSAPaddingParam
+    // Number of bytes of the modulus taken up by the padding
+    fn overhead(&self) -> usize
     {
         match *self
         {
-            RSAPadding::PKCS1v15 => RSAPaddingParam::NullParam,
-            RSAPadding::OAEP_MGF1_SHA1 => RSAPaddingParam::OAEPParam(pk11::CK_RSA_PKCS_OAEP_PARAMS::from_algorithm(pk11::CKM_SHA_1)),
-            RSAPadding::OAEP_MGF1_SHA224 => RSAPaddingParam::OAEPParam(pk11::CK_RSA_PKCS_OAEP_PARAMS::from_algorithm(pk11::CKM_SHA_224)),
-            RSAPadding::OAEP_MGF1_SHA256 => RSAPaddingParam::OAEPParam(pk11::CK_RSA_PKCS_OAEP_PARAMS::from_algorithm(pk11::CKM_SHA_256)),
-            RSAPadding::OAEP_MGF1_SHA384 => RSAPaddingParam::OAEPParam(pk11::CK_RSA_PKCS_OAEP_PARAMS::from_algorithm(pk11::CKM_SHA_384)),
-            RSAPadding::OAEP_MGF1_SHA512 => RSAPaddingParam::OAEPParam(pk11::CK_RSA_PKCS_OAEP_PARAMS::from_algorithm(pk11::CKM_SHA_512)),
+            RSAPaddingParam::Raw => 0,
+            RSAPaddingParam::PKCS1 => 11,
+            RSAPaddingParam::OAEP(_, hash_len) => 2 * hash_len + 2,
         }
     }
 }
 
-pub struct RSAPrivateKey
+impl RSAPadding
+{
+    fn get_param(&self) -> NSSResult<RSAPaddingParam>
+    {
+        let hash = match *self
+        {
+            RSAPadding::PKCS1v15 => return Ok(RSAPaddingParam::PKCS1),
+            RSAPadding::OAEP_MGF1_SHA1 => pk11::CKM_SHA_1,
+            RSAPadding::OAEP_MGF1_SHA224 => pk11::CKM_SHA_224,
+            RSAPadding::OAEP_MGF1_SHA256 => pk11::CKM_SHA_256,
+            RSAPadding::OAEP_MGF1_SHA384 => pk11::CKM_SHA_384,
+            RSAPadding::OAEP_MGF1_SHA512 => pk11::CKM_SHA_512,
+        };
+        let hash_len = try!(pk11::hash_len_from_ckm(hash));
+        Ok(RSAPaddingParam::OAEP(try!(pk11::CK_RSA_PKCS_OAEP_PARAMS::from_algorithm(hash)), hash_len))
+    }
+}
+
+static OID_PRIME256V1 : &'static [u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+static OID_SECP384R1 : &'static [u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22];
+static OID_SECP521R1 : &'static [u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x23];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Curve
+{
+    P256,
+    P384,
+    P521,
+}
+
+impl Curve
+{
+    fn oid(&self) -> &'static [u8]
+    {
+        match *self
+        {
+            Curve::P256 => OID_PRIME256V1,
+            Curve::P384 => OID_SECP384R1,
+            Curve::P521 => OID_SECP521R1,
+        }
+    }
+}
+
+fn import_private_key(data: &[u8]) -> NSSResult<pk11::PrivateKey>
+{
+    try!(::nss::init());
+
+    let mut der = sec::SECItem::from_buf(data);
+    let mut slot = try!(pk11::SlotInfo::get_internal());
+    let mut key = ptr::null_mut();
+
+    unsafe
+    {
+        try!(pk11::PK11_ImportDERPrivateKeyInfoAndReturnKey(slot.get_mut(), der.get_mut(), ptr::null_mut(),
+                                                            ptr::null_mut(), PRBool::False, PRBool::False, pk11::KU_ALL,
+                                                            &mut key, ptr::null_mut()).to_result());
+        pk11::PrivateKey::wrap(key)
+    }
+}
+
+/// A private key of any type, for signing
+pub struct PrivateKey
 {
     key: pk11::PrivateKey,
-    pubkey: Option<RSAPublicKey>
 }
 
-impl RSAPrivateKey
+impl PrivateKey
 {
-    pub fn load(data: &[u8]) -> NSSResult<RSAPrivateKey>
+    /// Loads a PKCS#8 encoded private key
+    pub fn load(data: &[u8]) -> NSSResult<PrivateKey>
+    {
+        let key = try!(import_private_key(data));
+        Ok(PrivateKey { key: key })
+    }
+
+    pub fn gen_rsa(key_size_bits: u32) -> NSSResult<PrivateKey>
+    {
+        let key = try!(RSAPrivateKey::gen(key_size_bits));
+        Ok(PrivateKey::from(key))
+    }
+
+    pub fn gen_ec(curve: Curve) -> NSSResult<PrivateKey>
     {
         try!(::nss::init());
 
-        let mut der = sec::SECItem::from_buf(data);
         let mut slot = try!(pk11::SlotInfo::get_internal());
-        let mut key = ptr::null_mut();
+        let mut params = sec::SECItem::from_buf(curve.oid());
+        let mut pubkey = ptr::null_mut();
 
-        let pkey = unsafe
+        let key = unsafe
         {
-            try!(pk11::PK11_ImportDERPrivateKeyInfoAndReturnKey(slot.get_mut(), der.get_mut(), ptr::null_mut(),
-                                                                ptr::null_mut(), PRBool::False, PRBool::False, pk11::KU_ALL,
-                                                                &mut key, ptr::null_mut()).to_result());
-            try!(pk11::PrivateKey::wrap(key))
+            try!(pk11::PrivateKey::wrap(
+                    pk11::PK11_GenerateKeyPair(slot.get_mut(), pk11::CKM_EC_KEY_PAIR_GEN, params.get_mut() as *mut _ as *mut c_void,
+                                               &mut pubkey, PRBool::False, PRBool::False, ptr::null_mut())
+                    )
+                )
+        };
+        // Only frees the public key, it can be derived from the private key again
+        try!(PublicKey::from_raw(pubkey));
+
+        Ok(PrivateKey { key: key })
+    }
+
+    pub fn save(&mut self) -> NSSResult<Vec<u8>>
+    {
+        let secitem = unsafe
+        {
+            try!(sec::SECItem::wrap(pk11::PK11_ExportDERPrivateKeyInfo(self.key.get_mut(), ptr::null_mut())))
         };
+        Ok(secitem.copy_buf())
+    }
+
+    pub fn key_type(&self) -> KeyType
+    {
+        match unsafe { pk11::SECKEY_GetPrivateKeyType(self.key.get()) }
+        {
+            pk11::KeyType::RSA => KeyType::RSA,
+            pk11::KeyType::DSA => KeyType::DSA,
+            pk11::KeyType::DH => KeyType::DH,
+            pk11::KeyType::EC => KeyType::EC,
+            _ => KeyType::Other,
+        }
+    }
+
+    pub fn public_key(&mut self) -> NSSResult<PublicKey>
+    {
+        PublicKey::from_raw(unsafe { pk11::SECKEY_ConvertToPublicKey(self.key.get_mut()) })
+    }
+
+    pub fn into_rsa(self) -> NSSResult<RSAPrivateKey>
+    {
+        match self.key_type()
+        {
+            KeyType::RSA => Ok(RSAPrivateKey { key: self.key, pubkey: None }),
+            _ => Err(NSSError::NSS(sec::SEC_ERROR_INVALID_KEY)),
+        }
+    }
+
+    /// The signature algorithm NSS uses for this key with the given hash
+    pub(crate) fn signature_algorithm(&self, hash: HashAlgorithm) -> NSSResult<cert::SECOidTag>
+    {
+        let key_type = unsafe { pk11::SECKEY_GetPrivateKeyType(self.key.get()) };
+        match unsafe { cert::SEC_GetSignatureAlgorithmOidTag(key_type, hash.to_oid_tag()) }
+        {
+            cert::SEC_OID_UNKNOWN => Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ALGORITHM)),
+            tag => Ok(tag),
+        }
+    }
+
+    pub(crate) fn from_raw(raw: *mut pk11::SECKEYPrivateKey) -> NSSResult<PrivateKey>
+    {
+        let key = try!(pk11::PrivateKey::wrap(raw));
+        Ok(PrivateKey { key: key })
+    }
+
+    pub(crate) fn get(&self) -> *const pk11::SECKEYPrivateKey
+    {
+        self.key.get()
+    }
+
+    pub(crate) fn get_mut(&mut self) -> *mut pk11::SECKEYPrivateKey
+    {
+        self.key.get_mut()
+    }
+}
+
+impl Clone for PrivateKey
+{
+    fn clone(&self) -> PrivateKey
+    {
+        let key = unsafe { pk11::SECKEY_CopyPrivateKey(self.key.get()) };
+        PrivateKey::from_raw(key).unwrap()
+    }
+}
+
+impl From<RSAPrivateKey> for PrivateKey
+{
+    fn from(key: RSAPrivateKey) -> PrivateKey
+    {
+        PrivateKey { key: key.key }
+    }
+}
+
+pub struct RSAPrivateKey
+{
+    key: pk11::PrivateKey,
+    pubkey: Option<RSAPublicKey>
+}
 
+impl RSAPrivateKey
+{
+    pub fn load(data: &[u8]) -> NSSResult<RSAPrivateKey>
+    {
+        let pkey = try!(import_private_key(data));
         Ok(RSAPrivateKey { key: pkey, pubkey: None })
     }
 
@@ -122,13 +385,34 @@ impl RSAPrivateKey
         Ok(result)
     }
 
-    pub fn key_len(&mut self) -> usize
+    pub fn key_len(&mut self) -> NSSResult<usize>
     {
-        match self.get_public()
-        {
-            Err(..) => 0,
-            Ok(public) => public.key_len(),
-        }
+        let public = try!(self.get_public());
+        public.key_len()
+    }
+
+    pub fn modulus_bits(&mut self) -> NSSResult<usize>
+    {
+        let public = try!(self.get_public());
+        public.modulus_bits()
+    }
+
+    pub fn ciphertext_len(&mut self) -> NSSResult<usize>
+    {
+        let public = try!(self.get_public());
+        public.ciphertext_len()
+    }
+
+    pub fn max_plaintext_len(&mut self, padding: RSAPadding) -> NSSResult<usize>
+    {
+        let public = try!(self.get_public());
+        public.max_plaintext_len(padding)
+    }
+
+    pub fn max_plaintext_len_oaep(&mut self, params: &OAEPParams) -> NSSResult<usize>
+    {
+        let public = try!(self.get_public());
+        public.max_plaintext_len_oaep(params)
     }
 
     pub fn encrypt(&mut self, padding: RSAPadding, data: &[u8]) -> NSSResult<Vec<u8>>
@@ -137,17 +421,72 @@ impl RSAPrivateKey
         public.encrypt(padding, data)
     }
 
+    pub fn encrypt_oaep(&mut self, params: &OAEPParams, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        let public = try!(self.get_public());
+        public.encrypt_oaep(params, data)
+    }
+
     pub fn decrypt(&mut self, padding: RSAPadding, data: &[u8]) -> NSSResult<Vec<u8>>
     {
-        let mut out = Vec::with_capacity(self.key_len());
+        let params = try!(padding.get_param());
+        self.decrypt_param(&params, data)
+    }
+
+    pub fn decrypt_oaep(&mut self, params: &OAEPParams, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        let params = try!(params.get_param());
+        self.decrypt_param(&params, data)
+    }
+
+    /// Raw RSA private key operation (`CKM_RSA_X_509`), without any padding
+    pub fn decrypt_raw(&mut self, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        self.decrypt_param(&RSAPaddingParam::Raw, data)
+    }
+
+    pub fn unwrap_key(&mut self, padding: RSAPadding, wrapped: &[u8], kind: symm::Kind) -> NSSResult<symm::Key>
+    {
+        let params = try!(padding.get_param());
+        self.unwrap_key_param(&params, wrapped, kind)
+    }
+
+    pub fn unwrap_key_oaep(&mut self, params: &OAEPParams, wrapped: &[u8], kind: symm::Kind) -> NSSResult<symm::Key>
+    {
+        let params = try!(params.get_param());
+        self.unwrap_key_param(&params, wrapped, kind)
+    }
+
+    fn unwrap_key_param(&mut self, params: &RSAPaddingParam, wrapped: &[u8], kind: symm::Kind) -> NSSResult<symm::Key>
+    {
+        let mut secitem = params.to_secitem();
+        let mut wrapped_item = sec::SECItem::from_buf(wrapped);
+
+        let key = unsafe
+        {
+            try!(pk11::SymKey::wrap(pk11::PK11_PubUnwrapSymKeyWithMechanism(self.key.get_mut(), params.to_ckm(), secitem.get_mut(),
+                                                                            wrapped_item.get_mut(), kind.to_ffi(),
+                                                                            pk11::CKA_FLAGS_ONLY, 0)))
+        };
+
+        Ok(symm::Key::from_raw(key))
+    }
+
+    fn decrypt_param(&mut self, params: &RSAPaddingParam, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        let key_len = try!(self.key_len());
+        if data.len() > key_len {
+            return Err(NSSError::InputTooLong { len: data.len(), max: key_len });
+        }
+
+        let mut out = Vec::with_capacity(key_len);
         let mut outlen = 0;
 
-        let params = padding.get_param();
         let mut secitem = params.to_secitem();
 
         unsafe
         {
-            try!(pk11::PK11_PrivDecrypt(self.key.get_mut(), padding.to_ckm(), secitem.get_mut(), out.as_mut_ptr(),
+            try!(pk11::PK11_PrivDecrypt(self.key.get_mut(), params.to_ckm(), secitem.get_mut(), out.as_mut_ptr(),
                                         &mut outlen, out.capacity() as c_uint, data.as_ptr(), data.len() as c_uint).to_result());
             out.set_len(outlen as usize);
         }
@@ -174,6 +513,92 @@ impl RSAPrivateKey
     }
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum KeyType
+{
+    RSA,
+    DSA,
+    DH,
+    EC,
+    Other,
+}
+
+/// A public key of any type, as found in a SubjectPublicKeyInfo
+pub struct PublicKey
+{
+    key: pk11::PublicKey,
+}
+
+impl PublicKey
+{
+    pub fn load(data: &[u8]) -> NSSResult<PublicKey>
+    {
+        try!(::nss::init());
+
+        let der = sec::SECItem::from_buf(data);
+
+        let key = unsafe
+        {
+            let spki = try!(pk11::PublicKeyInfo::wrap(pk11::SECKEY_DecodeDERSubjectPublicKeyInfo(der.get())));
+            try!(pk11::PublicKey::wrap(pk11::SECKEY_ExtractPublicKey(spki.get())))
+        };
+
+        Ok(PublicKey { key: key })
+    }
+
+    pub(crate) fn from_raw(raw: *mut pk11::SECKEYPublicKey) -> NSSResult<PublicKey>
+    {
+        let key = try!(pk11::PublicKey::wrap(raw));
+        Ok(PublicKey { key: key })
+    }
+
+    pub(crate) fn get(&self) -> *const pk11::SECKEYPublicKey
+    {
+        self.key.get()
+    }
+
+    pub fn save(&self) -> NSSResult<Vec<u8>>
+    {
+        let secitem = unsafe
+        {
+            try!(sec::SECItem::wrap(pk11::SECKEY_EncodeDERSubjectPublicKeyInfo(self.key.get())))
+        };
+
+        Ok(secitem.copy_buf())
+    }
+
+    pub fn key_type(&self) -> KeyType
+    {
+        match unsafe { pk11::SECKEY_GetPublicKeyType(self.key.get()) }
+        {
+            pk11::KeyType::RSA => KeyType::RSA,
+            pk11::KeyType::DSA => KeyType::DSA,
+            pk11::KeyType::DH => KeyType::DH,
+            pk11::KeyType::EC => KeyType::EC,
+            _ => KeyType::Other,
+        }
+    }
+
+    /// Size of the key in bits
+    pub fn key_bits(&self) -> NSSResult<usize>
+    {
+        match unsafe { pk11::SECKEY_PublicKeyStrengthInBits(self.key.get()) }
+        {
+            0 => Err(::ffi::nspr::get_error_code()),
+            bits => Ok(bits as usize),
+        }
+    }
+
+    pub fn into_rsa(self) -> NSSResult<RSAPublicKey>
+    {
+        match self.key_type()
+        {
+            KeyType::RSA => Ok(RSAPublicKey { key: self.key }),
+            _ => Err(NSSError::NSS(sec::SEC_ERROR_INVALID_KEY)),
+        }
+    }
+}
+
 pub struct RSAPublicKey
 {
     key: pk11::PublicKey,
@@ -212,26 +637,117 @@ impl RSAPublicKey
         Ok(result)
     }
 
-    pub fn key_len(&self) -> usize
+    /// Length of the modulus in bytes
+    pub fn key_len(&self) -> NSSResult<usize>
     {
-        unsafe
+        match unsafe { pk11::SECKEY_PublicKeyStrength(self.key.get()) }
+        {
+            0 => Err(::ffi::nspr::get_error_code()),
+            len => Ok(len as usize),
+        }
+    }
+
+    pub fn modulus_bits(&self) -> NSSResult<usize>
+    {
+        match unsafe { pk11::SECKEY_PublicKeyStrengthInBits(self.key.get()) }
+        {
+            0 => Err(::ffi::nspr::get_error_code()),
+            bits => Ok(bits as usize),
+        }
+    }
+
+    pub fn ciphertext_len(&self) -> NSSResult<usize>
+    {
+        self.key_len()
+    }
+
+    pub fn max_plaintext_len(&self, padding: RSAPadding) -> NSSResult<usize>
+    {
+        let params = try!(padding.get_param());
+        self.max_plaintext_len_param(&params)
+    }
+
+    pub fn max_plaintext_len_oaep(&self, params: &OAEPParams) -> NSSResult<usize>
+    {
+        let params = try!(params.get_param());
+        self.max_plaintext_len_param(&params)
+    }
+
+    fn max_plaintext_len_param(&self, params: &RSAPaddingParam) -> NSSResult<usize>
+    {
+        let key_len = try!(self.key_len());
+        match key_len.checked_sub(params.overhead())
         {
-            pk11::SECKEY_PublicKeyStrength(self.key.get()) as usize
+            Some(len) => Ok(len),
+            None => Err(NSSError::NSS(sec::SEC_ERROR_INVALID_KEY)),
         }
     }
 
     pub fn encrypt(&mut self, padding: RSAPadding, data: &[u8]) -> NSSResult<Vec<u8>>
     {
-        let mut out = Vec::with_capacity(self.key_len());
+        let params = try!(padding.get_param());
+        self.encrypt_param(&params, data)
+    }
+
+    pub fn encrypt_oaep(&mut self, params: &OAEPParams, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        let params = try!(params.get_param());
+        self.encrypt_param(&params, data)
+    }
+
+    /// Raw RSA public key operation (`CKM_RSA_X_509`), without any padding
+    pub fn encrypt_raw(&mut self, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        self.encrypt_param(&RSAPaddingParam::Raw, data)
+    }
+
+    /// Encrypts the value of a symmetric key, without it leaving the token
+    pub fn wrap_key(&mut self, padding: RSAPadding, key: &mut symm::Key) -> NSSResult<Vec<u8>>
+    {
+        let params = try!(padding.get_param());
+        self.wrap_key_param(&params, key)
+    }
+
+    pub fn wrap_key_oaep(&mut self, params: &OAEPParams, key: &mut symm::Key) -> NSSResult<Vec<u8>>
+    {
+        let params = try!(params.get_param());
+        self.wrap_key_param(&params, key)
+    }
+
+    fn wrap_key_param(&mut self, params: &RSAPaddingParam, key: &mut symm::Key) -> NSSResult<Vec<u8>>
+    {
+        let mut out = vec![0; try!(self.ciphertext_len())];
+        let mut secitem = params.to_secitem();
+
+        let outlen = {
+            let mut wrapped = sec::SECItem::from_mut_buf(&mut out);
+            unsafe
+            {
+                try!(pk11::PK11_PubWrapSymKeyWithMechanism(self.key.get_mut(), params.to_ckm(), secitem.get_mut(),
+                                                           key.get_mut(), wrapped.get_mut()).to_result());
+            }
+            wrapped.len()
+        };
+        out.truncate(outlen);
+
+        Ok(out)
+    }
+
+    fn encrypt_param(&mut self, params: &RSAPaddingParam, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        let max_len = try!(self.max_plaintext_len_param(params));
+        if data.len() > max_len {
+            return Err(NSSError::InputTooLong { len: data.len(), max: max_len });
+        }
+
+        let mut out = Vec::with_capacity(try!(self.ciphertext_len()));
         let mut outlen = 0;
 
-        let params = padding.get_param();
         let mut secitem = params.to_secitem();
 
         unsafe
         {
-
-            try!(pk11::PK11_PubEncrypt(self.key.get_mut(), padding.to_ckm(), secitem.get_mut(), out.as_mut_ptr(),
+            try!(pk11::PK11_PubEncrypt(self.key.get_mut(), params.to_ckm(), secitem.get_mut(), out.as_mut_ptr(),
                                        &mut outlen, out.capacity() as c_uint, data.as_ptr(), data.len() as c_uint,
                                        ptr::null_mut()).to_result());
             out.set_len(outlen as usize);
@@ -263,6 +779,137 @@ mod test
         assert_eq!(b"Encrypt Me!", &*message);
     }
 
+    static PRIV_1024_BASE64 : &'static [u8] = b"MIICdgIBADANBgkqhkiG9w0BAQEFAASCAmAwggJcAgEAAoGBAL9m7b02fo8+O5JU1m45lGs9FK9ZE+rrwnaZiptMA4++11w/ZfSzQUiW268ISi/67MbxswgFpiFER1dVBw7rG8UsIXPN2u1/gubntvUkumKqDKmXlx67+oJlxCqRGg2MkxedbZCC1xUzh3CbZfPDCZ1KlMtIItcXxY4lXLJzyH13AgMBAAECgYAsysCuPBeIHC9CwnngF7eFFSlHE9iWbI/4w1vtle9rb2Tk4K1Sg7SucSd+IbmC2v0YXa3hHblQJbNwW6SoOBsqNIVptYpxP2Tju1HmNNlb/GnL1E5XxSZorSQyzMfZ35yV7vkeJCSE9t6AwktN9O69mfdXhOpSeHI8NiEeP972CQJBAO/vrYFia6qAAh5jfz91kq6ieQGOqLeVx2m2PDiF5F39ooXB1Md7jp1j5vidrCwnozTK2W/A2O+PyPJHbZxTdHsCQQDMN2mcVnn00sUAg8pxa1Kl8MEAJ9+Zsoh7rlJ6ySmkQJrF3Tjhr+8FDA8FtncoGu/GSZPmeSM/81OXCz9iNiA1AkBAKCKI2jxX1uq6HXjFCzcxoxggJnlOvaoYxjXMF2E9ELkIcw/mV0+HHlOAXwjOtbqaPzNtAm//fr8IqbjReC9JAkAxL9OPJUEAl9d9VUDoaoogj8lcqT3w/hBjgcw56MPP3QOqTqtJqJ9wXsQrgxRWI47G+qfV5i14VLo4ciwVEqZVAkEAqX5BsPURRxixKEboZ4qERO761CDzjd35EKe78Co/pnNZvYbxshLlQSgacByqeYRz+7bEIexCm6iorZn08gcbjA==";
+
+    #[test]
+    fn decrypt_oaep_label()
+    {
+        // openssl pkeyutl -pkeyopt rsa_oaep_md:sha256 -pkeyopt rsa_mgf1_md:sha1 -pkeyopt rsa_oaep_label:6e73732d7273
+        static ENC_MESSAGE : &'static [u8] = b"ndepm3PH0s5/oYRPQf5v+RndfQ3512ok6HWu6IcYFVw7heJmd4ngmtEBiSgIHffNi9S1v8d/o8YAf+8Gz4ODiOazyt/NALt54kknKfKKaBzWUIgzfMSSe06l3kPbYNQK78MKZg5bW0rSBwMKhnqqFhYdvwqNuO5PSZMNlzglW28=";
+        let encrypted = ENC_MESSAGE.from_base64().unwrap();
+
+        let priv_der = PRIV_1024_BASE64.from_base64().unwrap();
+        let mut privkey = super::RSAPrivateKey::load(&priv_der).unwrap();
+
+        let params = super::OAEPParams::new(super::HashAlgorithm::SHA256)
+                        .mgf1_hash(super::HashAlgorithm::SHA1)
+                        .label(b"nss-rs");
+        let message = privkey.decrypt_oaep(&params, &encrypted).unwrap();
+        assert_eq!(b"Encrypt Me!", &*message);
+
+        let wrong_label = params.clone().label(b"other");
+        assert!(privkey.decrypt_oaep(&wrong_label, &encrypted).is_err());
+
+        let wrong_mgf = params.mgf1_hash(super::HashAlgorithm::SHA256);
+        assert!(privkey.decrypt_oaep(&wrong_mgf, &encrypted).is_err());
+    }
+
+    #[test]
+    fn oaep_roundtrip()
+    {
+        let priv_der = PRIV_1024_BASE64.from_base64().unwrap();
+        let mut privkey = super::RSAPrivateKey::load(&priv_der).unwrap();
+
+        let params = super::OAEPParams::new(super::HashAlgorithm::SHA384)
+                        .mgf1_hash(super::HashAlgorithm::SHA224)
+                        .label(b"label");
+        let encrypted = privkey.encrypt_oaep(&params, b"Encrypt Me!").unwrap();
+        let message = privkey.decrypt_oaep(&params, &encrypted).unwrap();
+        assert_eq!(b"Encrypt Me!", &*message);
+    }
+
+    #[test]
+    fn oaep_invalid_hash()
+    {
+        assert!(super::pk11::CK_RSA_PKCS_OAEP_PARAMS::new(super::pk11::CKM_SHA_256, super::pk11::CKM_AES_ECB, &[]).is_err());
+        assert!(super::pk11::CK_RSA_PKCS_OAEP_PARAMS::new(super::pk11::CKM_RSA_PKCS, super::pk11::CKM_SHA_1, &[]).is_err());
+    }
+
+    #[test]
+    fn raw_rsa()
+    {
+        static ENC_MESSAGE : &'static [u8] = b"aUKDCQ2kWblHltztGee2SpaT3KJ66ueSx8+2lmZq0PozXxEokzat369D11DMt7SJ3vufWhg+2CnMpHstv+27aQ==";
+        let expected = ENC_MESSAGE.from_base64().unwrap();
+
+        let priv_der = PRIV_BASE64.from_base64().unwrap();
+        let mut privkey = super::RSAPrivateKey::load(&priv_der).unwrap();
+
+        let encrypted = privkey.get_public().unwrap().encrypt_raw(b"Encrypt Me!").unwrap();
+        assert_eq!(expected, encrypted);
+
+        let message = privkey.decrypt_raw(&encrypted).unwrap();
+        assert_eq!(64, message.len());
+        assert!(message[..53].iter().all(|&b| b == 0));
+        assert_eq!(b"Encrypt Me!", &message[53..]);
+    }
+
+    #[test]
+    fn wrap_unwrap_key()
+    {
+        use super::super::symm;
+
+        let priv_der = PRIV_1024_BASE64.from_base64().unwrap();
+        let mut privkey = super::RSAPrivateKey::load(&priv_der).unwrap();
+        let mut pubkey = privkey.extract_public().unwrap();
+
+        let mut key = symm::Key::generate(symm::Kind::AES_CBC_PAD, 32).unwrap();
+        let iv = [0; 16];
+        let encrypted = symm::Crypter::with_key(symm::Kind::AES_CBC_PAD, symm::Mode::Encrypt, &mut key, &iv).unwrap()
+                            .finalize(b"Encrypt Me!").unwrap();
+
+        let params = super::OAEPParams::new(super::HashAlgorithm::SHA256).label(b"nss-rs");
+        let wrapped = pubkey.wrap_key_oaep(&params, &mut key).unwrap();
+        assert_eq!(128, wrapped.len());
+
+        let mut unwrapped = privkey.unwrap_key_oaep(&params, &wrapped, symm::Kind::AES_CBC_PAD).unwrap();
+        assert_eq!(32, unwrapped.key_len());
+        let decrypted = symm::Crypter::with_key(symm::Kind::AES_CBC_PAD, symm::Mode::Decrypt, &mut unwrapped, &iv).unwrap()
+                            .finalize(&encrypted).unwrap();
+        assert_eq!(b"Encrypt Me!", &*decrypted);
+
+        let wrapped = pubkey.wrap_key(super::RSAPadding::PKCS1v15, &mut key).unwrap();
+        let mut unwrapped = privkey.unwrap_key(super::RSAPadding::PKCS1v15, &wrapped, symm::Kind::AES_CBC_PAD).unwrap();
+        assert_eq!(key.extract().unwrap(), unwrapped.extract().unwrap());
+    }
+
+    #[test]
+    fn lengths()
+    {
+        let priv_der = PRIV_BASE64.from_base64().unwrap();
+        let mut privkey = super::RSAPrivateKey::load(&priv_der).unwrap();
+
+        assert_eq!(64, privkey.key_len().unwrap());
+        assert_eq!(512, privkey.modulus_bits().unwrap());
+        assert_eq!(64, privkey.ciphertext_len().unwrap());
+        assert_eq!(53, privkey.max_plaintext_len(super::RSAPadding::PKCS1v15).unwrap());
+        assert_eq!(22, privkey.max_plaintext_len(super::RSAPadding::OAEP_MGF1_SHA1).unwrap());
+        assert_eq!(6, privkey.max_plaintext_len(super::RSAPadding::OAEP_MGF1_SHA224).unwrap());
+        assert!(privkey.max_plaintext_len(super::RSAPadding::OAEP_MGF1_SHA256).is_err());
+
+        let params = super::OAEPParams::new(super::HashAlgorithm::SHA1).mgf1_hash(super::HashAlgorithm::SHA512);
+        assert_eq!(22, privkey.max_plaintext_len_oaep(&params).unwrap());
+    }
+
+    #[test]
+    fn input_too_long()
+    {
+        let priv_der = PRIV_BASE64.from_base64().unwrap();
+        let mut privkey = super::RSAPrivateKey::load(&priv_der).unwrap();
+
+        let data = [0x2a; 65];
+        assert_eq!(Err(super::NSSError::InputTooLong { len: 23, max: 22 }),
+                   privkey.encrypt(super::RSAPadding::OAEP_MGF1_SHA1, &data[..23]));
+        assert_eq!(Err(super::NSSError::InputTooLong { len: 54, max: 53 }),
+                   privkey.encrypt(super::RSAPadding::PKCS1v15, &data[..54]));
+        assert_eq!(Err(super::NSSError::InputTooLong { len: 65, max: 64 }),
+                   privkey.get_public().unwrap().encrypt_raw(&data));
+        assert_eq!(Err(super::NSSError::InputTooLong { len: 65, max: 64 }),
+                   privkey.decrypt(super::RSAPadding::PKCS1v15, &data));
+
+        assert!(privkey.encrypt(super::RSAPadding::OAEP_MGF1_SHA1, &data[..22]).is_ok());
+        assert!(privkey.encrypt(super::RSAPadding::OAEP_MGF1_SHA256, b"").is_err());
+    }
+
     #[test]
     fn priv_pub()
     {
@@ -275,4 +922,26 @@ mod test
         let derivedpub_der = pubkey.save().unwrap();
         assert_eq!(pub_der, derivedpub_der);
     }
+
+    #[test]
+    fn private_key()
+    {
+        let priv_der = PRIV_BASE64.from_base64().unwrap();
+        let pub_der = PUB_BASE64.from_base64().unwrap();
+
+        let mut privkey = super::PrivateKey::load(&priv_der).unwrap();
+        assert_eq!(super::KeyType::RSA, privkey.key_type());
+        assert_eq!(pub_der, privkey.public_key().unwrap().save().unwrap());
+        assert!(privkey.into_rsa().is_ok());
+
+        let mut eckey = super::PrivateKey::gen_ec(super::Curve::P384).unwrap();
+        assert_eq!(super::KeyType::EC, eckey.key_type());
+        assert_eq!(384, eckey.public_key().unwrap().key_bits().unwrap());
+        let reloaded = super::PrivateKey::load(&eckey.save().unwrap()).unwrap();
+        assert_eq!(super::KeyType::EC, reloaded.key_type());
+        assert!(reloaded.into_rsa().is_err());
+
+        let rsakey = super::PrivateKey::gen_rsa(1024).unwrap();
+        assert_eq!(super::KeyType::RSA, rsakey.key_type());
+    }
 }
@@ -1,6 +1,8 @@
 use result::NSSResult;
 use ffi::{pk11, sec};
+use ffi::nspr::PRBool;
 use std::ptr;
+use libc::{c_int, c_uint};
 
 #[derive(Copy, Clone)]
 pub enum Mode
@@ -33,9 +35,9 @@ pub enum Kind
     DES_CBC_PAD,
 }
 
-impl Kind 
+impl Kind
 {
-    fn to_ffi(&self) -> pk11::CK_MECHANISM_TYPE
+    pub(crate) fn to_ffi(&self) -> pk11::CK_MECHANISM_TYPE
     {
         match *self
         {
@@ -49,6 +51,177 @@ impl Kind
     }
 }
 
+/// Key wrapping algorithms, for wrapping keys under a key encryption key
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone)]
+pub enum KeyWrap
+{
+    /// RFC 3394, for keys that are a multiple of 8 bytes
+    AES_KW,
+    /// RFC 5649, for keys of any length
+    AES_KWP,
+}
+
+impl KeyWrap
+{
+    fn to_ffi(&self) -> pk11::CK_MECHANISM_TYPE
+    {
+        match *self
+        {
+            KeyWrap::AES_KW => pk11::CKM_AES_KEY_WRAP,
+            KeyWrap::AES_KWP => pk11::CKM_AES_KEY_WRAP_KWP,
+        }
+    }
+}
+
+// Wrapping adds an 8 byte integrity check value and at most 7 bytes of padding
+const KEY_WRAP_OVERHEAD : usize = 16;
+
+const KEY_FLAGS : pk11::CK_FLAGS = pk11::CKF_ENCRYPT | pk11::CKF_DECRYPT | pk11::CKF_WRAP | pk11::CKF_UNWRAP;
+
+/// A symmetric key object, which may live inside a token without its value being extractable
+pub struct Key
+{
+    key: pk11::SymKey,
+}
+
+impl Key
+{
+    pub fn generate(kind: Kind, key_len: usize) -> NSSResult<Key>
+    {
+        try!(::nss::init());
+
+        let mech = kind.to_ffi();
+        let mut slot = try!(pk11::SlotInfo::get_best(mech));
+
+        let key = unsafe
+        {
+            try!(pk11::SymKey::wrap(pk11::PK11_KeyGen(slot.get_mut(), mech, ptr::null_mut(), key_len as c_int, ptr::null_mut())))
+        };
+
+        Ok(Key { key: key })
+    }
+
+    pub fn import(kind: Kind, key: &[u8]) -> NSSResult<Key>
+    {
+        try!(::nss::init());
+
+        let mech = kind.to_ffi();
+        let mut key_item = sec::SECItem::from_buf(key);
+        let mut slot = try!(pk11::SlotInfo::get_best(mech));
+
+        let key = unsafe
+        {
+            try!(pk11::SymKey::wrap(pk11::PK11_ImportSymKeyWithFlags(slot.get_mut(), mech, pk11::PK11Origin::OriginUnwrap,
+                                                                     pk11::CKA_FLAGS_ONLY, key_item.get_mut(), KEY_FLAGS,
+                                                                     PRBool::False, ptr::null_mut())))
+        };
+
+        Ok(Key { key: key })
+    }
+
+    pub(crate) fn from_raw(key: pk11::SymKey) -> Key
+    {
+        Key { key: key }
+    }
+
+    pub fn key_len(&mut self) -> usize
+    {
+        unsafe { pk11::PK11_GetKeyLength(self.key.get_mut()) as usize }
+    }
+
+    /// Returns the key value, which fails for keys that are marked sensitive by their token
+    pub fn extract(&mut self) -> NSSResult<Vec<u8>>
+    {
+        unsafe
+        {
+            try!(pk11::PK11_ExtractKeyValue(self.key.get_mut()).to_result());
+            // The key data is owned by the key itself
+            let data = pk11::PK11_GetKeyData(self.key.get_mut());
+            Ok((*data).as_slice().to_vec())
+        }
+    }
+
+    /// Wraps another key under this key, without the other key leaving the token
+    pub fn wrap_key(&mut self, kind: KeyWrap, key: &mut Key) -> NSSResult<Vec<u8>>
+    {
+        let mut out = vec![0; key.key_len() + KEY_WRAP_OVERHEAD];
+
+        let outlen = {
+            let mut wrapped = sec::SECItem::from_mut_buf(&mut out);
+            unsafe
+            {
+                try!(pk11::PK11_WrapSymKey(kind.to_ffi(), ptr::null_mut(), self.key.get_mut(), key.get_mut(),
+                                           wrapped.get_mut()).to_result());
+            }
+            wrapped.len()
+        };
+        out.truncate(outlen);
+
+        Ok(out)
+    }
+
+    /// Unwraps a key that was wrapped under this key, for use with `target`
+    pub fn unwrap_key(&mut self, kind: KeyWrap, wrapped: &[u8], target: Kind) -> NSSResult<Key>
+    {
+        let mut wrapped_item = sec::SECItem::from_buf(wrapped);
+
+        let key = unsafe
+        {
+            try!(pk11::SymKey::wrap(pk11::PK11_UnwrapSymKeyWithFlags(self.key.get_mut(), kind.to_ffi(), ptr::null_mut(),
+                                                                     wrapped_item.get_mut(), target.to_ffi(),
+                                                                     pk11::CKA_FLAGS_ONLY, 0, KEY_FLAGS)))
+        };
+
+        Ok(Key { key: key })
+    }
+
+    /// Wraps key material given as bytes under this key
+    pub fn wrap(&mut self, kind: KeyWrap, data: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        let mut out = Vec::with_capacity(data.len() + KEY_WRAP_OVERHEAD);
+        let mut outlen = 0;
+
+        unsafe
+        {
+            try!(pk11::PK11_Encrypt(self.key.get_mut(), kind.to_ffi(), ptr::null_mut(), out.as_mut_ptr(), &mut outlen,
+                                    out.capacity() as c_uint, data.as_ptr(), data.len() as c_uint).to_result());
+            out.set_len(outlen as usize);
+        }
+
+        Ok(out)
+    }
+
+    pub fn unwrap(&mut self, kind: KeyWrap, wrapped: &[u8]) -> NSSResult<Vec<u8>>
+    {
+        let mut out = Vec::with_capacity(wrapped.len());
+        let mut outlen = 0;
+
+        unsafe
+        {
+            try!(pk11::PK11_Decrypt(self.key.get_mut(), kind.to_ffi(), ptr::null_mut(), out.as_mut_ptr(), &mut outlen,
+                                    out.capacity() as c_uint, wrapped.as_ptr(), wrapped.len() as c_uint).to_result());
+            out.set_len(outlen as usize);
+        }
+
+        Ok(out)
+    }
+
+    pub(crate) fn get_mut(&mut self) -> *mut pk11::PK11SymKey
+    {
+        self.key.get_mut()
+    }
+}
+
+impl Clone for Key
+{
+    fn clone(&self) -> Key
+    {
+        let key = unsafe { pk11::PK11_ReferenceSymKey(self.key.get() as *mut _) };
+        Key { key: pk11::SymKey::wrap(key).unwrap() }
+    }
+}
+
 pub struct Crypter
 {
     context: pk11::Context,
@@ -58,26 +231,19 @@ impl Crypter
 {
     pub fn new(kind: Kind, mode: Mode, key: &[u8], iv: &[u8]) -> NSSResult<Crypter>
     {
-        try!(::nss::init());
+        let mut key = try!(Key::import(kind, key));
+        Crypter::with_key(kind, mode, &mut key, iv)
+    }
 
+    pub fn with_key(kind: Kind, mode: Mode, key: &mut Key, iv: &[u8]) -> NSSResult<Crypter>
+    {
         let mech = kind.to_ffi();
-        let mut key_item = sec::SECItem::from_buf(key);
         let mut iv_item = sec::SECItem::from_buf(iv);
 
-        let mut slot = try!(pk11::SlotInfo::get_best(mech));
-
         let context = unsafe
         {
-            let mut sym_key =
-                try!(
-                    pk11::SymKey::wrap(
-                        pk11::PK11_ImportSymKey(slot.get_mut(), mech,
-                                                pk11::PK11Origin::OriginUnwrap, mode.to_ffi(),
-                                                key_item.get_mut(), ptr::null_mut())
-                    )
-                );
             let mut sec_param = try!(sec::SECItem::wrap(pk11::PK11_ParamFromIV(mech, iv_item.get_mut())));
-            try!(pk11::Context::wrap(pk11::PK11_CreateContextBySymKey(mech, mode.to_ffi(), sym_key.get_mut(), sec_param.get_mut())))
+            try!(pk11::Context::wrap(pk11::PK11_CreateContextBySymKey(mech, mode.to_ffi(), key.get_mut(), sec_param.get_mut())))
         };
 
         Ok(Crypter {
@@ -129,7 +295,9 @@ impl Crypter
 #[cfg(test)]
 mod test
 {
-    use super::Crypter;
+    extern crate rustc_serialize as serialize;
+    use self::serialize::hex::FromHex;
+    use super::{Crypter, Key, KeyWrap, Kind};
 
     fn test_fips(key: &[u8], plain: &[u8], result: &[u8])
     {
@@ -175,4 +343,75 @@ mod test
 
         test_fips(key, plain, result);
     }
+
+    fn test_key_wrap(kind: KeyWrap, kek: &str, key: &str, wrapped: &str)
+    {
+        let kek = kek.from_hex().unwrap();
+        let key = key.from_hex().unwrap();
+        let wrapped = wrapped.from_hex().unwrap();
+
+        let mut kek = Key::import(Kind::AES_ECB, &kek).unwrap();
+        assert_eq!(wrapped, kek.wrap(kind, &key).unwrap());
+        assert_eq!(key, kek.unwrap(kind, &wrapped).unwrap());
+
+        let mut corrupted = wrapped.clone();
+        corrupted[0] ^= 1;
+        assert!(kek.unwrap(kind, &corrupted).is_err());
+    }
+
+    #[test]
+    fn rfc3394_128_128()
+    {
+        test_key_wrap(KeyWrap::AES_KW, "000102030405060708090A0B0C0D0E0F", "00112233445566778899AABBCCDDEEFF",
+                      "1FA68B0A8112B447AEF34BD8FB5A7B829D3E862371D2CFE5");
+    }
+
+    #[test]
+    fn rfc3394_256_256()
+    {
+        test_key_wrap(KeyWrap::AES_KW, "000102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F",
+                      "00112233445566778899AABBCCDDEEFF000102030405060708090A0B0C0D0E0F",
+                      "28C9F404C4B810F4CBCCB35CFB87F8263F5786E2D80ED326CBC7F0E71A99F43BFB988B9B7A02DD21");
+    }
+
+    #[test]
+    fn rfc5649_20_bytes()
+    {
+        test_key_wrap(KeyWrap::AES_KWP, "5840df6e29b02af1ab493b705bf16ea1ae8338f4dcc176a8",
+                      "c37b7e6492584340bed12207808941155068f738",
+                      "138bdeaa9b8fa7fc61f97742e72248ee5ae6ae5360d1ae6a5f54f373fa543b6a");
+    }
+
+    #[test]
+    fn rfc5649_7_bytes()
+    {
+        test_key_wrap(KeyWrap::AES_KWP, "5840df6e29b02af1ab493b705bf16ea1ae8338f4dcc176a8", "466f7250617369",
+                      "afbeb0f07dfbf5419200f2ccb50bb24f");
+    }
+
+    #[test]
+    fn wrap_key_object()
+    {
+        let kek = "000102030405060708090A0B0C0D0E0F".from_hex().unwrap();
+        let key = "00112233445566778899AABBCCDDEEFF".from_hex().unwrap();
+        let wrapped = "1FA68B0A8112B447AEF34BD8FB5A7B829D3E862371D2CFE5".from_hex().unwrap();
+
+        let mut kek = Key::import(Kind::AES_ECB, &kek).unwrap();
+        let mut data_key = Key::import(Kind::AES_CBC_PAD, &key).unwrap();
+        assert_eq!(wrapped, kek.wrap_key(KeyWrap::AES_KW, &mut data_key).unwrap());
+
+        let mut unwrapped = kek.unwrap_key(KeyWrap::AES_KW, &wrapped, Kind::AES_CBC_PAD).unwrap();
+        assert_eq!(16, unwrapped.key_len());
+        let iv = [0; 16];
+        let encrypted = Crypter::with_key(Kind::AES_CBC_PAD, super::Mode::Encrypt, &mut unwrapped, &iv).unwrap()
+                            .finalize(b"Encrypt Me!").unwrap();
+        let decrypted = Crypter::new(Kind::AES_CBC_PAD, super::Mode::Decrypt, &key, &iv).unwrap()
+                            .finalize(&encrypted).unwrap();
+        assert_eq!(b"Encrypt Me!", &*decrypted);
+
+        let mut generated = Key::generate(Kind::AES_CBC, 24).unwrap();
+        let wrapped = kek.wrap_key(KeyWrap::AES_KWP, &mut generated).unwrap();
+        let mut unwrapped = kek.unwrap_key(KeyWrap::AES_KWP, &wrapped, Kind::AES_CBC).unwrap();
+        assert_eq!(generated.extract().unwrap(), unwrapped.extract().unwrap());
+    }
 }
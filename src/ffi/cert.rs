@@ -0,0 +1,584 @@
+use ffi::sec::{SECStatus, SECItemFFI};
+use ffi::nspr::PRBool;
+use ffi::pk11;
+use ffi::pk11::{SECKEYPublicKey, SECKEYPrivateKey};
+use libc::{c_void, c_char, c_int, c_uint, c_ulong};
+use std::ptr;
+
+pub type PRTime = i64;
+pub type SECOidTag = c_int;
+
+pub const SEC_OID_SHA1   : SECOidTag = 4;
+pub const SEC_OID_SHA224 : SECOidTag = 309;
+pub const SEC_OID_SHA256 : SECOidTag = 191;
+pub const SEC_OID_SHA384 : SECOidTag = 192;
+pub const SEC_OID_SHA512 : SECOidTag = 193;
+
+pub const SEC_OID_AES_128_CBC : SECOidTag = 184;
+pub const SEC_OID_AES_192_CBC : SECOidTag = 186;
+pub const SEC_OID_AES_256_CBC : SECOidTag = 188;
+
+pub const SEC_OID_X509_SUBJECT_ALT_NAME   : SECOidTag = 83;
+pub const SEC_OID_X509_BASIC_CONSTRAINTS  : SECOidTag = 85;
+pub const SEC_OID_X509_CRL_DIST_POINTS    : SECOidTag = 87;
+pub const SEC_OID_X509_AUTH_KEY_ID        : SECOidTag = 91;
+pub const SEC_OID_X509_EXT_KEY_USAGE      : SECOidTag = 92;
+
+pub const SEC_OID_EXT_KEY_USAGE_SERVER_AUTH   : SECOidTag = 146;
+pub const SEC_OID_EXT_KEY_USAGE_CLIENT_AUTH   : SECOidTag = 147;
+pub const SEC_OID_EXT_KEY_USAGE_CODE_SIGN     : SECOidTag = 148;
+pub const SEC_OID_EXT_KEY_USAGE_EMAIL_PROTECT : SECOidTag = 149;
+pub const SEC_OID_EXT_KEY_USAGE_TIME_STAMP    : SECOidTag = 150;
+pub const SEC_OID_OCSP_RESPONDER              : SECOidTag = 151;
+// SECOidTag
+
+pub const KU_DIGITAL_SIGNATURE : c_uint = 0x80;
+pub const KU_NON_REPUDIATION   : c_uint = 0x40;
+pub const KU_KEY_ENCIPHERMENT  : c_uint = 0x20;
+pub const KU_DATA_ENCIPHERMENT : c_uint = 0x10;
+pub const KU_KEY_AGREEMENT     : c_uint = 0x08;
+pub const KU_KEY_CERT_SIGN     : c_uint = 0x04;
+pub const KU_CRL_SIGN          : c_uint = 0x02;
+pub const KU_ENCIPHER_ONLY     : c_uint = 0x01;
+
+pub const CERT_UNLIMITED_PATH_CONSTRAINT : c_int = -2;
+
+pub type SECCertificateUsage = i64;
+
+pub const CERTIFICATE_USAGE_SSL_CLIENT       : SECCertificateUsage = 0x0001;
+pub const CERTIFICATE_USAGE_SSL_SERVER       : SECCertificateUsage = 0x0002;
+pub const CERTIFICATE_USAGE_EMAIL_SIGNER     : SECCertificateUsage = 0x0010;
+pub const CERTIFICATE_USAGE_EMAIL_RECIPIENT  : SECCertificateUsage = 0x0020;
+pub const CERTIFICATE_USAGE_OBJECT_SIGNER    : SECCertificateUsage = 0x0040;
+pub const CERTIFICATE_USAGE_STATUS_RESPONDER : SECCertificateUsage = 0x0400;
+// SECCertificateUsage
+
+pub const CERT_REV_M_TEST_USING_THIS_METHOD  : u64 = 1;
+pub const CERT_REV_M_FORBID_NETWORK_FETCHING : u64 = 2;
+pub const CERT_REV_MI_TEST_ALL_LOCAL_INFORMATION_FIRST : u64 = 1;
+pub const CERT_REV_MI_REQUIRE_SOME_FRESH_INFO_AVAILABLE : u64 = 2;
+
+#[repr(C)]
+#[allow(dead_code)] // List all available options
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum CERTGeneralNameType
+{
+    OtherName = 1,
+    RFC822Name = 2,
+    DNSName = 3,
+    X400Address = 4,
+    DirectoryName = 5,
+    EDIPartyName = 6,
+    URI = 7,
+    IPAddress = 8,
+    RegisterID = 9,
+}
+
+#[repr(C)]
+#[allow(dead_code)] // List all available options
+#[derive(Clone, Copy)]
+pub enum CERTValParamInType
+{
+    End = 0,
+    NbioContext = 1,
+    NbioAbort = 2,
+    CertList = 3,
+    PolicyOID = 4,
+    PolicyFlags = 5,
+    KeyUsage = 6,
+    ExtendedKeyUsage = 7,
+    Date = 8,
+    RevocationFlags = 9,
+    CertStores = 10,
+    TrustAnchors = 11,
+    UseAIACertFetch = 12,
+    ChainVerifyCallback = 13,
+    UseOnlyTrustAnchors = 14,
+}
+
+#[repr(C)]
+#[allow(dead_code)] // List all available options
+#[derive(Clone, Copy)]
+pub enum CERTValParamOutType
+{
+    End = 0,
+    NbioContext = 1,
+    TrustAnchor = 2,
+    CertList = 3,
+    PolicyOID = 4,
+    ErrorLog = 5,
+    Usages = 6,
+    KeyUsage = 7,
+    ExtendedKeyUsage = 8,
+}
+
+#[repr(C)]
+#[allow(dead_code)] // List all available options
+#[derive(Clone, Copy)]
+pub enum CERTRevocationMethodIndex
+{
+    CRL = 0,
+    OCSP = 1,
+}
+
+// Opaque structures, with pointer references only
+#[repr(C)] pub struct PLArenaPool(c_void);
+#[repr(C)] pub struct CERTCertDBHandle(c_void);
+
+#[repr(C)]
+pub struct SECAlgorithmID
+{
+    pub algorithm: SECItemFFI,
+    pub parameters: SECItemFFI,
+}
+
+#[repr(C)]
+pub struct CERTSignedData
+{
+    pub data: SECItemFFI,
+    pub signature_algorithm: SECAlgorithmID,
+    pub signature: SECItemFFI,
+}
+
+#[repr(C)]
+pub struct CERTName
+{
+    pub arena: *mut PLArenaPool,
+    pub rdns: *mut *mut c_void,
+}
+
+#[repr(C)]
+pub struct CERTValidity
+{
+    pub arena: *mut PLArenaPool,
+    pub not_before: SECItemFFI,
+    pub not_after: SECItemFFI,
+}
+
+#[repr(C)]
+pub struct CERTSubjectPublicKeyInfo
+{
+    pub arena: *mut PLArenaPool,
+    pub algorithm: SECAlgorithmID,
+    pub subject_public_key: SECItemFFI,
+}
+
+// Only the leading, stable part of the structure is declared; it is never allocated from Rust
+#[repr(C)]
+pub struct CERTCertificate
+{
+    pub arena: *mut PLArenaPool,
+    pub subject_name: *mut c_char,
+    pub issuer_name: *mut c_char,
+    pub signature_wrap: CERTSignedData,
+    pub der_cert: SECItemFFI,
+    pub der_issuer: SECItemFFI,
+    pub der_subject: SECItemFFI,
+    pub der_public_key: SECItemFFI,
+    pub cert_key: SECItemFFI,
+    pub version: SECItemFFI,
+    pub serial_number: SECItemFFI,
+    pub signature: SECAlgorithmID,
+    pub issuer: CERTName,
+    pub validity: CERTValidity,
+    pub subject: CERTName,
+    pub subject_public_key_info: CERTSubjectPublicKeyInfo,
+    pub issuer_id: SECItemFFI,
+    pub subject_id: SECItemFFI,
+    pub extensions: *mut *mut c_void,
+    pub email_addr: *mut c_char,
+    pub dbhandle: *mut CERTCertDBHandle,
+    pub subject_key_id: SECItemFFI,
+    pub key_id_generated: PRBool,
+    pub key_usage: c_uint,
+    pub raw_key_usage: c_uint,
+    pub key_usage_present: PRBool,
+    pub ns_cert_type: u32,
+    pub keep_session: PRBool,
+    pub time_ok: PRBool,
+    pub domain_ok: *mut c_void,
+    pub isperm: PRBool,
+    pub istemp: PRBool,
+    pub nickname: *mut c_char,
+    pub dbnickname: *mut c_char,
+    pub nss_certificate: *mut c_void,
+    pub trust: *mut c_void,
+}
+
+#[repr(C)]
+pub struct PRCList
+{
+    pub next: *mut PRCList,
+    pub prev: *mut PRCList,
+}
+
+#[repr(C)]
+pub struct CERTGeneralName
+{
+    pub typ: CERTGeneralNameType,
+    // A union; other names store their value followed by the type OID, all others just the value
+    pub name: SECItemFFI,
+    pub oid: SECItemFFI,
+    pub der_directory_name: SECItemFFI,
+    pub l: PRCList,
+}
+
+#[repr(C)]
+pub struct CERTOidSequence
+{
+    pub arena: *mut PLArenaPool,
+    pub oids: *mut *mut SECItemFFI,
+}
+
+#[repr(C)]
+pub struct CERTBasicConstraints
+{
+    pub is_ca: PRBool,
+    pub path_len_constraint: c_int,
+}
+
+#[repr(C)]
+pub struct CERTCertListNode
+{
+    pub links: PRCList,
+    pub cert: *mut CERTCertificate,
+    pub app_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct CERTCertList
+{
+    pub list: PRCList,
+    pub arena: *mut PLArenaPool,
+}
+
+#[repr(C)]
+pub struct CERTCertificateList
+{
+    pub certs: *mut SECItemFFI,
+    pub len: c_int,
+    pub arena: *mut PLArenaPool,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union CERTValParamScalar
+{
+    pub b: c_int,
+    pub ul: u64,
+    pub time: PRTime,
+}
+
+// The in and out parameters are tagged unions, the type determines which member is used
+#[repr(C)]
+pub struct CERTValParam<T>
+{
+    pub typ: T,
+    pub scalar: CERTValParamScalar,
+    pub pointer: *const c_void,
+    pub array: *const c_void,
+    pub array_size: c_int,
+}
+
+pub type CERTValInParam = CERTValParam<CERTValParamInType>;
+pub type CERTValOutParam = CERTValParam<CERTValParamOutType>;
+
+impl<T> CERTValParam<T>
+{
+    pub fn scalar(typ: T, scalar: CERTValParamScalar) -> CERTValParam<T>
+    {
+        CERTValParam { typ: typ, scalar: scalar, pointer: ptr::null(), array: ptr::null(), array_size: 0 }
+    }
+
+    pub fn pointer(typ: T, pointer: *const c_void) -> CERTValParam<T>
+    {
+        CERTValParam { typ: typ, scalar: CERTValParamScalar { ul: 0 }, pointer: pointer, array: ptr::null(), array_size: 0 }
+    }
+
+    pub fn array(typ: T, array: *const c_void, size: usize) -> CERTValParam<T>
+    {
+        CERTValParam { typ: typ, scalar: CERTValParamScalar { ul: 0 }, pointer: ptr::null(), array: array, array_size: size as c_int }
+    }
+}
+
+#[repr(C)]
+pub struct CERTCertificateRequest
+{
+    pub arena: *mut PLArenaPool,
+    pub version: SECItemFFI,
+    pub subject: CERTName,
+    pub subject_public_key_info: CERTSubjectPublicKeyInfo,
+    pub attributes: *mut *mut c_void,
+}
+
+#[repr(C)]
+pub struct CERTCertExtension
+{
+    pub id: SECItemFFI,
+    pub critical: SECItemFFI,
+    pub value: SECItemFFI,
+}
+
+#[repr(C)]
+pub struct CERTAuthKeyID
+{
+    pub key_id: SECItemFFI,
+    pub auth_cert_issuer: *mut CERTGeneralName,
+    pub auth_cert_serial_number: SECItemFFI,
+    pub der_auth_cert_issuer: *mut *mut SECItemFFI,
+}
+
+pub const DIST_POINT_GENERAL_NAME : c_int = 1;
+
+// Only the leading part of the structure is declared, the distribution point is a union of pointer size
+#[repr(C)]
+pub struct CRLDistributionPoint
+{
+    pub dist_point_type: c_int,
+    pub full_name: *mut CERTGeneralName,
+}
+
+#[repr(C)]
+pub struct CERTCrlDistributionPoints
+{
+    pub dist_points: *mut *mut CRLDistributionPoint,
+}
+
+#[repr(C)]
+pub struct CERTCrlEntry
+{
+    pub serial_number: SECItemFFI,
+    pub revocation_date: SECItemFFI,
+    pub extensions: *mut *mut CERTCertExtension,
+}
+
+#[repr(C)]
+pub struct CERTCrl
+{
+    pub arena: *mut PLArenaPool,
+    pub version: SECItemFFI,
+    pub signature_alg: SECAlgorithmID,
+    pub der_name: SECItemFFI,
+    pub name: CERTName,
+    pub last_update: SECItemFFI,
+    pub next_update: SECItemFFI,
+    pub entries: *mut *mut CERTCrlEntry,
+    pub extensions: *mut *mut CERTCertExtension,
+}
+
+// Only the leading part of the structure is declared; it is never allocated from Rust
+#[repr(C)]
+pub struct CERTSignedCrl
+{
+    pub arena: *mut PLArenaPool,
+    pub crl: CERTCrl,
+    pub reserved1: *mut c_void,
+    pub reserved2: PRBool,
+    pub isperm: PRBool,
+    pub istemp: PRBool,
+    pub reference_count: c_int,
+    pub dbhandle: *mut CERTCertDBHandle,
+    pub signature_wrap: CERTSignedData,
+    pub url: *mut c_char,
+    pub der_crl: *mut SECItemFFI,
+}
+
+pub const SEC_CRL_TYPE : c_int = 1;
+
+#[repr(C)] pub struct SEC_ASN1Template(c_void);
+
+#[repr(C)]
+pub struct CERTRevocationTests
+{
+    pub number_of_defined_methods: u32,
+    pub cert_rev_flags_per_method: *mut u64,
+    pub number_of_preferred_methods: u32,
+    pub preferred_methods: *mut CERTRevocationMethodIndex,
+    pub cert_rev_method_independent_flags: u64,
+}
+
+#[repr(C)]
+pub struct CERTRevocationFlags
+{
+    pub leaf_tests: CERTRevocationTests,
+    pub chain_tests: CERTRevocationTests,
+}
+
+#[repr(C)]
+pub struct SECOidData
+{
+    pub oid: SECItemFFI,
+    pub offset: SECOidTag,
+    pub desc: *const c_char,
+    pub mechanism: c_ulong,
+    pub supported_extension: c_int,
+}
+
+pub const SEC_OID_UNKNOWN : SECOidTag = 0;
+pub const CKM_INVALID_MECHANISM : c_ulong = 0xFFFF_FFFF;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct CERTCertTrust
+{
+    pub ssl_flags: c_uint,
+    pub email_flags: c_uint,
+    pub object_signing_flags: c_uint,
+}
+
+pub const CERTDB_TERMINAL_RECORD : c_uint = 1 << 0;
+pub const CERTDB_TRUSTED : c_uint = 1 << 1;
+pub const CERTDB_SEND_WARN : c_uint = 1 << 2;
+pub const CERTDB_VALID_CA : c_uint = 1 << 3;
+pub const CERTDB_TRUSTED_CA : c_uint = 1 << 4;
+pub const CERTDB_USER : c_uint = 1 << 6;
+pub const CERTDB_TRUSTED_CLIENT_CA : c_uint = 1 << 7;
+pub const CERTDB_INVISIBLE_CA : c_uint = 1 << 8;
+pub const CERTDB_GOVT_APPROVED_CA : c_uint = 1 << 9;
+
+#[repr(C)] pub struct CERTOCSPRequest(c_void);
+#[repr(C)] pub struct CERTOCSPResponse(c_void);
+#[repr(C)] pub struct CERTOCSPCertID(c_void);
+
+unsafe fn arena_destructor(arena: *mut PLArenaPool) { PORT_FreeArena(arena, PRBool::False); }
+unsafe fn crl_destructor(crl: *mut CERTSignedCrl) { let _ = SEC_DestroyCrl(crl); }
+unsafe fn ocsp_cert_id_destructor(id: *mut CERTOCSPCertID) { let _ = CERT_DestroyOCSPCertID(id); }
+
+create_wrapper!(Certificate, CERTCertificate, CERT_DestroyCertificate);
+create_wrapper!(Arena, PLArenaPool, arena_destructor);
+create_wrapper!(OidSequence, CERTOidSequence, CERT_DestroyOidSequence);
+create_wrapper!(CertList, CERTCertList, CERT_DestroyCertList);
+create_wrapper!(Name, CERTName, CERT_DestroyName);
+create_wrapper!(CertificateRequest, CERTCertificateRequest, CERT_DestroyCertificateRequest);
+create_wrapper!(Validity, CERTValidity, CERT_DestroyValidity);
+create_wrapper!(SignedCrl, CERTSignedCrl, crl_destructor);
+create_wrapper!(OcspRequest, CERTOCSPRequest, CERT_DestroyOCSPRequest);
+create_wrapper!(OcspResponse, CERTOCSPResponse, CERT_DestroyOCSPResponse);
+create_wrapper!(OcspCertId, CERTOCSPCertID, ocsp_cert_id_destructor);
+
+pub const DER_DEFAULT_CHUNKSIZE : c_uint = 2048;
+
+#[link(name="nss3")]
+extern "C"
+{
+    pub fn CERT_GetDefaultCertDB() -> *mut CERTCertDBHandle;
+    pub fn CERT_NewTempCertificate(handle: *mut CERTCertDBHandle, der_cert: *mut SECItemFFI, nickname: *mut c_char,
+                                   is_perm: PRBool, copy_der: PRBool) -> *mut CERTCertificate;
+    pub fn CERT_DupCertificate(cert: *mut CERTCertificate) -> *mut CERTCertificate;
+    pub fn CERT_VerifyCertName(cert: *const CERTCertificate, hostname: *const c_char) -> SECStatus;
+    fn CERT_DestroyCertificate(cert: *mut CERTCertificate);
+    pub fn CERT_NameToAscii(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetCommonName(name: *const CERTName) -> *mut c_char;
+    pub fn CERT_GetCertTimes(cert: *const CERTCertificate, not_before: *mut PRTime, not_after: *mut PRTime) -> SECStatus;
+    pub fn CERT_FindCertExtension(cert: *const CERTCertificate, tag: c_int, value: *mut SECItemFFI) -> SECStatus;
+    pub fn CERT_DecodeAltNameExtension(arena: *mut PLArenaPool, encoded: *mut SECItemFFI) -> *mut CERTGeneralName;
+    pub fn CERT_GetNextGeneralName(current: *mut CERTGeneralName) -> *mut CERTGeneralName;
+    pub fn CERT_DecodeOidSequence(seq_item: *const SECItemFFI) -> *mut CERTOidSequence;
+    fn CERT_DestroyOidSequence(seq: *mut CERTOidSequence);
+    pub fn CERT_DecodeBasicConstraintValue(value: *mut CERTBasicConstraints, encoded: *const SECItemFFI) -> SECStatus;
+    pub fn CERT_ExtractPublicKey(cert: *mut CERTCertificate) -> *mut SECKEYPublicKey;
+    pub fn SECOID_FindOIDTag(oid: *const SECItemFFI) -> SECOidTag;
+    pub fn SECOID_AddEntry(src: *const SECOidData) -> SECOidTag;
+    pub fn SECOID_FindOIDByTag(tag: SECOidTag) -> *mut SECOidData;
+    pub fn CERT_NewCertList() -> *mut CERTCertList;
+    fn CERT_DestroyCertList(certs: *mut CERTCertList);
+    pub fn CERT_AddCertToListTail(certs: *mut CERTCertList, cert: *mut CERTCertificate) -> SECStatus;
+    pub fn CERT_PKIXVerifyCert(cert: *mut CERTCertificate, usages: SECCertificateUsage, params_in: *const CERTValInParam,
+                               params_out: *mut CERTValOutParam, wincx: *mut c_void) -> SECStatus;
+    pub fn CERT_AsciiToName(string: *const c_char) -> *mut CERTName;
+    fn CERT_DestroyName(name: *mut CERTName);
+    pub fn CERT_CreateCertificateRequest(name: *mut CERTName, spki: *mut pk11::CERTSubjectPublicKeyInfo,
+                                         attributes: *mut *mut SECItemFFI) -> *mut CERTCertificateRequest;
+    fn CERT_DestroyCertificateRequest(req: *mut CERTCertificateRequest);
+    pub fn CERT_StartCertificateRequestAttributes(req: *mut CERTCertificateRequest) -> *mut c_void;
+    pub fn CERT_FinishCertificateRequestAttributes(req: *mut CERTCertificateRequest) -> SECStatus;
+    pub fn CERT_GetCertificateRequestExtensions(req: *mut CERTCertificateRequest, exts: *mut *mut *mut CERTCertExtension) -> SECStatus;
+    pub fn CERT_AddExtensionByOID(exthandle: *mut c_void, oid: *mut SECItemFFI, value: *mut SECItemFFI, critical: PRBool,
+                                  copy_data: PRBool) -> SECStatus;
+    pub fn CERT_FinishExtensions(exthandle: *mut c_void) -> SECStatus;
+    pub fn CERT_CreateValidity(not_before: PRTime, not_after: PRTime) -> *mut CERTValidity;
+    fn CERT_DestroyValidity(validity: *mut CERTValidity);
+    pub fn CERT_CreateCertificate(serial_number: c_ulong, issuer: *mut CERTName, validity: *mut CERTValidity,
+                                  req: *mut CERTCertificateRequest) -> *mut CERTCertificate;
+    pub fn CERT_StartCertExtensions(cert: *mut CERTCertificate) -> *mut c_void;
+    pub fn CERT_FindSubjectKeyIDExtension(cert: *mut CERTCertificate, ret_item: *mut SECItemFFI) -> SECStatus;
+    pub fn CERT_DecodeAuthKeyID(arena: *mut PLArenaPool, encoded: *const SECItemFFI) -> *mut CERTAuthKeyID;
+    pub fn CERT_DecodeCRLDistributionPoints(arena: *mut PLArenaPool, der: *mut SECItemFFI) -> *mut CERTCrlDistributionPoints;
+    pub fn CERT_DecodeDERCrl(arena: *mut PLArenaPool, der_signed_crl: *mut SECItemFFI, typ: c_int) -> *mut CERTSignedCrl;
+    fn SEC_DestroyCrl(crl: *mut CERTSignedCrl) -> SECStatus;
+    pub fn PK11_ImportCRL(slot: *mut pk11::PK11SlotInfo, der_crl: *mut SECItemFFI, url: *mut c_char, typ: c_int,
+                          wincx: *mut c_void, import_options: i32, arena: *mut PLArenaPool, decode_options: i32) -> *mut CERTSignedCrl;
+    pub fn CERT_CacheCRL(handle: *mut CERTCertDBHandle, new_crl: *mut SECItemFFI) -> SECStatus;
+    pub fn CERT_UncacheCRL(handle: *mut CERTCertDBHandle, old_crl: *mut SECItemFFI) -> SECStatus;
+    pub fn CERT_FindCRLEntryReasonExten(entry: *mut CERTCrlEntry, value: *mut c_int) -> SECStatus;
+    pub fn CERT_FindCRLNumberExten(arena: *mut PLArenaPool, crl: *mut CERTCrl, value: *mut SECItemFFI) -> SECStatus;
+    pub fn CERT_ChangeCertTrust(handle: *mut CERTCertDBHandle, cert: *mut CERTCertificate, trust: *mut CERTCertTrust) -> SECStatus;
+    pub fn CERT_GetCertTrust(cert: *const CERTCertificate, trust: *mut CERTCertTrust) -> SECStatus;
+    pub fn CERT_DecodeTrustString(trust: *mut CERTCertTrust, trusts: *const c_char) -> SECStatus;
+    pub fn CERT_CreateSubjectCertList(certs: *mut CERTCertList, handle: *mut CERTCertDBHandle, name: *const SECItemFFI,
+                                      sorttime: PRTime, valid_only: PRBool) -> *mut CERTCertList;
+    pub fn SEC_DeletePermCertificate(cert: *mut CERTCertificate) -> SECStatus;
+    pub fn PK11_ImportCert(slot: *mut pk11::PK11SlotInfo, cert: *mut CERTCertificate, key: pk11::CK_OBJECT_HANDLE,
+                           nickname: *const c_char, include_trust: PRBool) -> SECStatus;
+    pub fn PK11_ListCertsInSlot(slot: *mut pk11::PK11SlotInfo) -> *mut CERTCertList;
+    pub fn PK11_FindCertFromNickname(nickname: *const c_char, wincx: *mut c_void) -> *mut CERTCertificate;
+    pub fn PK11_FindCertsFromEmailAddress(email: *const c_char, wincx: *mut c_void) -> *mut CERTCertList;
+    pub fn PK11_FindKeyByAnyCert(cert: *mut CERTCertificate, wincx: *mut c_void) -> *mut SECKEYPrivateKey;
+    pub fn PK11_GetCertFromPrivateKey(key: *mut SECKEYPrivateKey) -> *mut CERTCertificate;
+    pub fn PK11_FindCertFromDERCertItem(slot: *mut pk11::PK11SlotInfo, der_cert: *const SECItemFFI,
+                                        wincx: *mut c_void) -> *mut CERTCertificate;
+    pub fn __PK11_SetCertificateNickname(cert: *mut CERTCertificate, nickname: *const c_char) -> SECStatus;
+    pub fn CERT_CreateOCSPRequest(certs: *mut CERTCertList, time: PRTime, add_service_locator: PRBool,
+                                  signer_cert: *mut CERTCertificate) -> *mut CERTOCSPRequest;
+    fn CERT_DestroyOCSPRequest(request: *mut CERTOCSPRequest);
+    pub fn CERT_EncodeOCSPRequest(arena: *mut PLArenaPool, request: *mut CERTOCSPRequest, pwarg: *mut c_void) -> *mut SECItemFFI;
+    pub fn CERT_DecodeOCSPResponse(src: *const SECItemFFI) -> *mut CERTOCSPResponse;
+    fn CERT_DestroyOCSPResponse(response: *mut CERTOCSPResponse);
+    pub fn CERT_GetOCSPResponseStatus(response: *mut CERTOCSPResponse) -> SECStatus;
+    pub fn CERT_VerifyOCSPResponseSignature(response: *mut CERTOCSPResponse, handle: *mut CERTCertDBHandle, pwarg: *mut c_void,
+                                            signer_cert: *mut *mut CERTCertificate, issuer_cert: *mut CERTCertificate) -> SECStatus;
+    pub fn CERT_CreateOCSPCertID(cert: *mut CERTCertificate, time: PRTime) -> *mut CERTOCSPCertID;
+    fn CERT_DestroyOCSPCertID(id: *mut CERTOCSPCertID) -> SECStatus;
+    pub fn CERT_GetOCSPStatusForCertID(handle: *mut CERTCertDBHandle, response: *mut CERTOCSPResponse, id: *mut CERTOCSPCertID,
+                                       signer_cert: *mut CERTCertificate, time: PRTime) -> SECStatus;
+    pub fn CERT_CacheOCSPResponseFromSideChannel(handle: *mut CERTCertDBHandle, cert: *mut CERTCertificate, time: PRTime,
+                                                 encoded_response: *const SECItemFFI, pwarg: *mut c_void) -> SECStatus;
+    pub fn CERT_ClearOCSPCache() -> SECStatus;
+    #[cfg(test)]
+    pub fn CERT_CreateEncodedOCSPErrorResponse(arena: *mut PLArenaPool, error: c_int) -> *mut SECItemFFI;
+    pub fn DER_DecodeTimeChoice(output: *mut PRTime, input: *const SECItemFFI) -> SECStatus;
+    pub fn DER_EncodeTimeChoice(arena: *mut PLArenaPool, output: *mut SECItemFFI, input: PRTime) -> SECStatus;
+    #[cfg(test)]
+    pub fn DER_TimeToGeneralizedTimeArena(arena: *mut PLArenaPool, dst: *mut SECItemFFI, time: PRTime) -> SECStatus;
+    pub fn NSS_Get_SECOID_AlgorithmIDTemplate(arg: *mut c_void, enc: PRBool) -> *const SEC_ASN1Template;
+    pub fn SECOID_SetAlgorithmID(arena: *mut PLArenaPool, aid: *mut SECAlgorithmID, tag: SECOidTag, params: *mut SECItemFFI) -> SECStatus;
+    pub fn SECITEM_CopyItem(arena: *mut PLArenaPool, to: *mut SECItemFFI, from: *const SECItemFFI) -> SECStatus;
+    pub fn SEC_ASN1EncodeInteger(arena: *mut PLArenaPool, dest: *mut SECItemFFI, value: c_ulong) -> *mut SECItemFFI;
+    pub fn NSS_Get_CERT_CertificateTemplate(arg: *mut c_void, enc: PRBool) -> *const SEC_ASN1Template;
+    pub fn CERT_VerifySignedDataWithPublicKeyInfo(sd: *mut CERTSignedData, spki: *mut CERTSubjectPublicKeyInfo,
+                                                  wincx: *mut c_void) -> SECStatus;
+    pub fn SECKEY_CreateSubjectPublicKeyInfo(key: *const SECKEYPublicKey) -> *mut pk11::CERTSubjectPublicKeyInfo;
+    pub fn SEC_GetSignatureAlgorithmOidTag(key_type: pk11::KeyType, hash_alg: SECOidTag) -> SECOidTag;
+    pub fn SEC_DerSignData(arena: *mut PLArenaPool, result: *mut SECItemFFI, buf: *const u8, len: c_int,
+                           key: *mut SECKEYPrivateKey, alg: SECOidTag) -> SECStatus;
+    pub fn SEC_ASN1EncodeItem(arena: *mut PLArenaPool, dest: *mut SECItemFFI, src: *const c_void,
+                              template: *const SEC_ASN1Template) -> *mut SECItemFFI;
+    pub fn SEC_QuickDERDecodeItem(arena: *mut PLArenaPool, dest: *mut c_void, template: *const SEC_ASN1Template,
+                                  src: *const SECItemFFI) -> SECStatus;
+    pub fn NSS_Get_CERT_SignedDataTemplate(arg: *mut c_void, enc: PRBool) -> *const SEC_ASN1Template;
+    pub fn NSS_Get_CERT_CertificateRequestTemplate(arg: *mut c_void, enc: PRBool) -> *const SEC_ASN1Template;
+    pub fn NSS_Get_CERT_NameTemplate(arg: *mut c_void, enc: PRBool) -> *const SEC_ASN1Template;
+    pub fn PK11_HashBuf(hash_alg: SECOidTag, out: *mut u8, data: *const u8, len: i32) -> SECStatus;
+
+    pub fn PORT_NewArena(chunksize: c_uint) -> *mut PLArenaPool;
+    fn PORT_FreeArena(arena: *mut PLArenaPool, zero: PRBool);
+    pub fn PORT_Free(ptr: *mut c_void);
+    pub fn BTOA_DataToAscii(data: *const u8, len: c_uint) -> *mut c_char;
+    pub fn ATOB_AsciiToData(string: *const c_char, len: *mut c_uint) -> *mut u8;
+}
+
+#[link(name="smime3")]
+extern "C"
+{
+    pub fn CERT_DecodeCertFromPackage(certbuf: *mut c_char, certbuflen: c_int) -> *mut CERTCertificate;
+}
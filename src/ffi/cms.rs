@@ -0,0 +1,117 @@
+use ffi::sec::{SECStatus, SECItemFFI};
+use ffi::nspr::PRBool;
+use ffi::cert::{CERTCertDBHandle, CERTCertificate, PLArenaPool, PRTime, SECAlgorithmID, SECOidTag};
+use libc::{c_void, c_char, c_int, c_ulong};
+
+pub const SEC_OID_PKCS7_SIGNED_DATA    : SECOidTag = 26;
+pub const SEC_OID_PKCS7_ENVELOPED_DATA : SECOidTag = 27;
+
+// SECCertUsage, the predecessor of SECCertificateUsage
+pub type SECCertUsage = c_int;
+pub const CERT_USAGE_EMAIL_SIGNER : SECCertUsage = 4;
+
+#[allow(non_camel_case_types)]
+pub type NSSCMSCertChainMode = c_int;
+pub const NSSCMSCM_NONE : NSSCMSCertChainMode = 0;
+pub const NSSCMSCM_CERT_ONLY : NSSCMSCertChainMode = 1;
+pub const NSSCMSCM_CERT_CHAIN : NSSCMSCertChainMode = 2;
+pub const NSSCMSCM_CERT_CHAIN_WITH_ROOT : NSSCMSCertChainMode = 3;
+
+#[allow(non_camel_case_types)]
+pub type NSSCMSVerificationStatus = c_int;
+pub const NSSCMSVS_BAD_SIGNATURE : NSSCMSVerificationStatus = 2;
+pub const NSSCMSVS_DIGEST_MISMATCH : NSSCMSVerificationStatus = 3;
+
+#[repr(C)] pub struct NSSCMSMessage(c_void);
+#[repr(C)] pub struct NSSCMSContentInfo(c_void);
+#[repr(C)] pub struct NSSCMSSignedData(c_void);
+#[repr(C)] pub struct NSSCMSEnvelopedData(c_void);
+#[repr(C)] pub struct NSSCMSRecipientInfo(c_void);
+#[repr(C)] pub struct NSSCMSEncoderContext(c_void);
+#[repr(C)] pub struct NSSCMSDecoderContext(c_void);
+
+pub type NSSCMSContentCallback = extern "C" fn(arg: *mut c_void, buf: *const c_char, len: c_ulong);
+
+// Only the leading fields, the rest is not needed
+#[repr(C)]
+pub struct NSSCMSSignerInfo
+{
+    pub version: SECItemFFI,
+    pub identifier_type: c_int,
+    pub identifier: *mut c_void,
+    pub digest_alg: SECAlgorithmID,
+}
+
+create_wrapper!(Message, NSSCMSMessage, NSS_CMSMessage_Destroy);
+
+#[link(name="smime3")]
+extern "C"
+{
+    pub fn NSS_CMSMessage_Create(poolp: *mut PLArenaPool) -> *mut NSSCMSMessage;
+    pub fn NSS_CMSMessage_CreateFromDER(der_message: *mut SECItemFFI, cb: *mut c_void, cb_arg: *mut c_void,
+                                        pwfn: *mut c_void, pwfn_arg: *mut c_void, decrypt_key_cb: *mut c_void,
+                                        decrypt_key_cb_arg: *mut c_void) -> *mut NSSCMSMessage;
+    fn NSS_CMSMessage_Destroy(cmsg: *mut NSSCMSMessage);
+    pub fn NSS_CMSMessage_GetContentInfo(cmsg: *mut NSSCMSMessage) -> *mut NSSCMSContentInfo;
+    pub fn NSS_CMSMessage_GetContent(cmsg: *mut NSSCMSMessage) -> *mut SECItemFFI;
+    pub fn NSS_CMSMessage_ContentLevel(cmsg: *mut NSSCMSMessage, n: c_int) -> *mut NSSCMSContentInfo;
+
+    pub fn NSS_CMSContentInfo_GetContent(cinfo: *mut NSSCMSContentInfo) -> *mut c_void;
+    pub fn NSS_CMSContentInfo_GetContentTypeTag(cinfo: *mut NSSCMSContentInfo) -> SECOidTag;
+    pub fn NSS_CMSContentInfo_SetContent_Data(cmsg: *mut NSSCMSMessage, cinfo: *mut NSSCMSContentInfo, data: *mut SECItemFFI,
+                                              detached: PRBool) -> SECStatus;
+    pub fn NSS_CMSContentInfo_SetContent_EnvelopedData(cmsg: *mut NSSCMSMessage, cinfo: *mut NSSCMSContentInfo,
+                                                       envd: *mut NSSCMSEnvelopedData) -> SECStatus;
+    pub fn NSS_CMSContentInfo_SetContent_SignedData(cmsg: *mut NSSCMSMessage, cinfo: *mut NSSCMSContentInfo,
+                                                    sigd: *mut NSSCMSSignedData) -> SECStatus;
+
+    pub fn NSS_CMSSignedData_Create(cmsg: *mut NSSCMSMessage) -> *mut NSSCMSSignedData;
+    pub fn NSS_CMSSignedData_Destroy(sigd: *mut NSSCMSSignedData);
+    pub fn NSS_CMSSignedData_GetContentInfo(sigd: *mut NSSCMSSignedData) -> *mut NSSCMSContentInfo;
+    pub fn NSS_CMSSignedData_AddSignerInfo(sigd: *mut NSSCMSSignedData, signerinfo: *mut NSSCMSSignerInfo) -> SECStatus;
+    pub fn NSS_CMSSignedData_AddCertificate(sigd: *mut NSSCMSSignedData, cert: *mut CERTCertificate) -> SECStatus;
+    pub fn NSS_CMSSignedData_SignerInfoCount(sigd: *mut NSSCMSSignedData) -> c_int;
+    pub fn NSS_CMSSignedData_GetSignerInfo(sigd: *mut NSSCMSSignedData, i: c_int) -> *mut NSSCMSSignerInfo;
+    pub fn NSS_CMSSignedData_ImportCerts(sigd: *mut NSSCMSSignedData, certdb: *mut CERTCertDBHandle, certusage: SECCertUsage,
+                                         keepcerts: PRBool) -> SECStatus;
+
+    pub fn NSS_CMSSignerInfo_Create(cmsg: *mut NSSCMSMessage, cert: *mut CERTCertificate,
+                                    digestalgtag: SECOidTag) -> *mut NSSCMSSignerInfo;
+    pub fn NSS_CMSSignerInfo_IncludeCerts(signerinfo: *mut NSSCMSSignerInfo, cm: NSSCMSCertChainMode,
+                                          usage: SECCertUsage) -> SECStatus;
+    pub fn NSS_CMSSignerInfo_AddSigningTime(signerinfo: *mut NSSCMSSignerInfo, t: PRTime) -> SECStatus;
+    pub fn NSS_CMSSignerInfo_GetSigningTime(sinfo: *mut NSSCMSSignerInfo, stime: *mut PRTime) -> SECStatus;
+    pub fn NSS_CMSSignerInfo_GetSigningCertificate(signerinfo: *mut NSSCMSSignerInfo,
+                                                   certdb: *mut CERTCertDBHandle) -> *mut CERTCertificate;
+    pub fn NSS_CMSSignerInfo_Verify(signerinfo: *mut NSSCMSSignerInfo, digest: *mut SECItemFFI,
+                                    content_type: *mut SECItemFFI) -> SECStatus;
+    pub fn NSS_CMSSignerInfo_GetVerificationStatus(signerinfo: *mut NSSCMSSignerInfo) -> NSSCMSVerificationStatus;
+
+    pub fn NSS_CMSEnvelopedData_Create(cmsg: *mut NSSCMSMessage, algorithm: SECOidTag, keysize: c_int) -> *mut NSSCMSEnvelopedData;
+    pub fn NSS_CMSEnvelopedData_Destroy(edp: *mut NSSCMSEnvelopedData);
+    pub fn NSS_CMSEnvelopedData_GetContentInfo(envd: *mut NSSCMSEnvelopedData) -> *mut NSSCMSContentInfo;
+    pub fn NSS_CMSEnvelopedData_AddRecipient(edp: *mut NSSCMSEnvelopedData, rip: *mut NSSCMSRecipientInfo) -> SECStatus;
+
+    pub fn NSS_CMSRecipientInfo_Create(cmsg: *mut NSSCMSMessage, cert: *mut CERTCertificate) -> *mut NSSCMSRecipientInfo;
+    pub fn NSS_CMSRecipientInfo_Destroy(ri: *mut NSSCMSRecipientInfo);
+
+    // The digest arguments are only for detached signatures
+    pub fn NSS_CMSEncoder_Start(cmsg: *mut NSSCMSMessage, outputfn: NSSCMSContentCallback, outputarg: *mut c_void,
+                                dest: *mut SECItemFFI, destpoolp: *mut PLArenaPool, pwfn: *mut c_void, pwfn_arg: *mut c_void,
+                                decrypt_key_cb: *mut c_void, decrypt_key_cb_arg: *mut c_void,
+                                detached_digestalgs: *mut *mut SECAlgorithmID,
+                                detached_digests: *mut *mut SECItemFFI) -> *mut NSSCMSEncoderContext;
+    pub fn NSS_CMSEncoder_Update(p7ecx: *mut NSSCMSEncoderContext, data: *const c_char, len: c_ulong) -> SECStatus;
+    pub fn NSS_CMSEncoder_Cancel(p7ecx: *mut NSSCMSEncoderContext) -> SECStatus;
+    pub fn NSS_CMSEncoder_Finish(p7ecx: *mut NSSCMSEncoderContext) -> SECStatus;
+
+    pub fn NSS_CMSDecoder_Start(poolp: *mut PLArenaPool, cb: NSSCMSContentCallback, cb_arg: *mut c_void,
+                                pwfn: *mut c_void, pwfn_arg: *mut c_void, decrypt_key_cb: *mut c_void,
+                                decrypt_key_cb_arg: *mut c_void) -> *mut NSSCMSDecoderContext;
+    pub fn NSS_CMSDecoder_Update(p7dcx: *mut NSSCMSDecoderContext, buf: *const c_char, len: c_ulong) -> SECStatus;
+    pub fn NSS_CMSDecoder_Cancel(p7dcx: *mut NSSCMSDecoderContext);
+    pub fn NSS_CMSDecoder_Finish(p7dcx: *mut NSSCMSDecoderContext) -> *mut NSSCMSMessage;
+
+    pub fn NSS_CMSDEREncode(cmsg: *mut NSSCMSMessage, input: *mut SECItemFFI, der_out: *mut SECItemFFI,
+                            arena: *mut PLArenaPool) -> SECStatus;
+}
@@ -1,4 +1,50 @@
+macro_rules! create_wrapper(
+    ($newname:ident, $ffiname:ty, $destructor:ident) => (
+        pub struct $newname
+        {
+            ptr: *mut $ffiname
+        }
+
+        impl $newname
+        {
+            pub fn wrap(ptr: *mut $ffiname) -> ::result::NSSResult<$newname>
+            {
+                match ptr.is_null()
+                {
+                    true => Err(::ffi::nspr::get_error_code()),
+                    false => Ok($newname { ptr: ptr }),
+                }
+            }
+
+            #[allow(dead_code)]
+            pub fn get(&self) -> *const $ffiname
+            {
+                self.ptr
+            }
+
+            #[allow(dead_code)]
+            pub fn get_mut(&mut self) -> *mut $ffiname
+            {
+                self.ptr
+            }
+        }
+
+        impl Drop for $newname
+        {
+            fn drop(&mut self)
+            {
+                unsafe { $destructor(self.ptr) }
+            }
+        }
+
+    );
+);
+
+pub mod cert;
+pub mod cms;
 pub mod nss;
 pub mod nspr;
+pub mod p12;
 pub mod pk11;
 pub mod sec;
+pub mod ssl;
@@ -1,5 +1,5 @@
 use result::NSSError;
-use libc::c_uint;
+use libc::{c_char, c_int, c_uint, c_void};
 use std::ffi::CStr;
 
 #[repr(C)]
@@ -35,6 +35,104 @@ pub enum PRBool
     False = 0,
 }
 
+pub const PR_WOULD_BLOCK_ERROR             : i32 = -5998;
+pub const PR_INVALID_METHOD_ERROR          : i32 = -5996;
+pub const PR_IO_ERROR                      : i32 = -5991;
+pub const PR_IO_TIMEOUT_ERROR              : i32 = -5990;
+pub const PR_NOT_CONNECTED_ERROR           : i32 = -5978;
+pub const PR_OPERATION_NOT_SUPPORTED_ERROR : i32 = -5965;
+pub const PR_CONNECT_RESET_ERROR           : i32 = -5961;
+pub const PR_END_OF_FILE_ERROR             : i32 = -5938;
+pub const PR_INVALID_STATE_ERROR           : i32 = -5931;
+pub const PR_CONNECT_ABORTED_ERROR         : i32 = -5928;
+
+pub type PRDescIdentity = c_int;
+pub const PR_INVALID_IO_LAYER : PRDescIdentity = -1;
+
+pub const PR_DESC_LAYERED : c_int = 4;
+pub const PR_SHUTDOWN_SEND : c_int = 1;
+#[allow(non_upper_case_globals)]
+pub const PR_IpAddrAny : c_int = 1;
+#[allow(non_upper_case_globals)]
+pub const PR_SockOpt_Nonblocking : c_int = 0;
+
+#[repr(C)]
+pub struct PRFileDesc
+{
+    pub methods: *const PRIOMethods,
+    pub secret: *mut c_void,
+    pub lower: *mut PRFileDesc,
+    pub higher: *mut PRFileDesc,
+    pub dtor: Option<unsafe extern "C" fn(fd: *mut PRFileDesc)>,
+    pub identity: PRDescIdentity,
+}
+
+/// Only the options a layer answers to, the actual union is larger
+#[repr(C)]
+pub union PRSocketOptionValue
+{
+    pub non_blocking: c_int,
+    _align: usize,
+}
+
+#[repr(C)]
+pub struct PRSocketOptionData
+{
+    pub option: c_int,
+    pub value: PRSocketOptionValue,
+}
+
+/// Stands in for the methods a layer does not implement, as NSPR does itself
+pub type PRInvalidFN = Option<unsafe extern "C" fn() -> isize>;
+
+#[repr(C)]
+pub struct PRIOMethods
+{
+    pub file_type: c_int,
+    pub close: Option<unsafe extern "C" fn(fd: *mut PRFileDesc) -> PRStatus>,
+    pub read: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, buf: *mut c_void, amount: i32) -> i32>,
+    pub write: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, buf: *const c_void, amount: i32) -> i32>,
+    pub available: Option<unsafe extern "C" fn(fd: *mut PRFileDesc) -> i32>,
+    pub available64: Option<unsafe extern "C" fn(fd: *mut PRFileDesc) -> i64>,
+    pub fsync: Option<unsafe extern "C" fn(fd: *mut PRFileDesc) -> PRStatus>,
+    pub seek: PRInvalidFN,
+    pub seek64: PRInvalidFN,
+    pub file_info: PRInvalidFN,
+    pub file_info64: PRInvalidFN,
+    pub writev: PRInvalidFN,
+    pub connect: PRInvalidFN,
+    pub accept: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, addr: *mut c_void, timeout: u32) -> *mut PRFileDesc>,
+    pub bind: PRInvalidFN,
+    pub listen: PRInvalidFN,
+    pub shutdown: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, how: c_int) -> PRStatus>,
+    pub recv: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, buf: *mut c_void, amount: i32, flags: c_int, timeout: u32) -> i32>,
+    pub send: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, buf: *const c_void, amount: i32, flags: c_int, timeout: u32) -> i32>,
+    pub recvfrom: PRInvalidFN,
+    pub sendto: PRInvalidFN,
+    pub poll: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, in_flags: i16, out_flags: *mut i16) -> i16>,
+    pub acceptread: PRInvalidFN,
+    pub transmitfile: PRInvalidFN,
+    pub getsockname: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, addr: *mut c_void) -> PRStatus>,
+    pub getpeername: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, addr: *mut c_void) -> PRStatus>,
+    pub reserved_fn_6: PRInvalidFN,
+    pub reserved_fn_5: PRInvalidFN,
+    pub getsocketoption: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, data: *mut PRSocketOptionData) -> PRStatus>,
+    pub setsocketoption: Option<unsafe extern "C" fn(fd: *mut PRFileDesc, data: *const PRSocketOptionData) -> PRStatus>,
+    pub sendfile: PRInvalidFN,
+    pub connectcontinue: PRInvalidFN,
+    pub reserved_fn_3: PRInvalidFN,
+    pub reserved_fn_2: PRInvalidFN,
+    pub reserved_fn_1: PRInvalidFN,
+    pub reserved_fn_0: PRInvalidFN,
+}
+
+create_wrapper!(FileDesc, PRFileDesc, close_file_desc);
+
+unsafe fn close_file_desc(fd: *mut PRFileDesc)
+{
+    let _ = PR_Close(fd);
+}
+
 pub fn get_error_code() -> NSSError
 {
     let code = unsafe { PR_GetError() };
@@ -72,6 +170,22 @@ extern "C"
                    maxPTDs: c_uint);
     pub fn PR_Cleanup() -> PRStatus;
     fn PR_GetError() -> i32;
+    pub fn PR_SetError(code: i32, os_error: i32);
+    pub fn PR_Now() -> i64;
+    pub fn PR_IntervalToMicroseconds(ticks: u32) -> u32;
     fn PR_ErrorToString(code: i32, language: u32) -> *const ::libc::c_char;
     fn PR_ErrorToName(code: i32) -> *const ::libc::c_char;
 }
+
+#[link(name="nspr4")]
+extern "C"
+{
+    pub fn PR_GetUniqueIdentity(layer_name: *const c_char) -> PRDescIdentity;
+    pub fn PR_InitializeNetAddr(val: c_int, port: u16, addr: *mut c_void) -> PRStatus;
+    pub fn PR_CreateIOLayerStub(ident: PRDescIdentity, methods: *const PRIOMethods) -> *mut PRFileDesc;
+    fn PR_Close(fd: *mut PRFileDesc) -> PRStatus;
+    pub fn PR_Read(fd: *mut PRFileDesc, buf: *mut c_void, amount: i32) -> i32;
+    pub fn PR_Write(fd: *mut PRFileDesc, buf: *const c_void, amount: i32) -> i32;
+    pub fn PR_Sync(fd: *mut PRFileDesc) -> PRStatus;
+    pub fn PR_Shutdown(fd: *mut PRFileDesc, how: c_int) -> PRStatus;
+}
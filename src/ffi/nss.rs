@@ -5,5 +5,7 @@ use libc::c_char;
 extern "C"
 {
     pub fn NSS_NoDB_Init(reserved: *mut c_char) -> SECStatus;
+    pub fn NSS_Initialize(config_dir: *const c_char, cert_prefix: *const c_char, key_prefix: *const c_char,
+                          secmod_name: *const c_char, flags: u32) -> SECStatus;
     pub fn NSS_Shutdown() -> SECStatus;
 }
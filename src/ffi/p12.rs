@@ -0,0 +1,78 @@
+use ffi::sec::{SECStatus, SECItemFFI};
+use ffi::nspr::PRBool;
+use ffi::cert::{CERTCertDBHandle, CERTCertificate, SECAlgorithmID, SECOidTag};
+use ffi::pk11::PK11SlotInfo;
+use libc::{c_void, c_char, c_uint, c_ulong};
+
+pub const SEC_OID_PKCS12_V2_PBE_WITH_SHA1_AND_3KEY_TRIPLE_DES_CBC : SECOidTag = 156;
+pub const SEC_OID_PKCS12_V2_PBE_WITH_SHA1_AND_128_BIT_RC2_CBC     : SECOidTag = 158;
+pub const SEC_OID_PKCS12_V2_PBE_WITH_SHA1_AND_40_BIT_RC2_CBC      : SECOidTag = 159;
+pub const SEC_OID_PKCS12_V1_CERT_BAG_ID                           : SECOidTag = 164;
+
+#[allow(non_camel_case_types)] #[repr(C)] pub struct SEC_PKCS12DecoderContext(c_void);
+#[allow(non_camel_case_types)] #[repr(C)] pub struct SEC_PKCS12ExportContext(c_void);
+#[allow(non_camel_case_types)] #[repr(C)] pub struct SEC_PKCS12SafeInfo(c_void);
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+pub struct SEC_PKCS12DecoderItem
+{
+    pub der: *mut SECItemFFI,
+    pub typ: SECOidTag,
+    pub has_key: PRBool,
+    pub friendly_name: *mut SECItemFFI,
+    pub shroud_alg: *mut SECAlgorithmID,
+}
+
+#[allow(non_camel_case_types)]
+pub type SEC_PKCS12NicknameCollisionCallback = extern "C" fn(old_nickname: *mut SECItemFFI, cancel: *mut PRBool,
+                                                            arg: *mut c_void) -> *mut SECItemFFI;
+pub type PORTCharConversionWSwapFunc = extern "C" fn(to_unicode: PRBool, in_buf: *mut u8, in_buf_len: c_uint, out_buf: *mut u8,
+                                                    max_out_buf_len: c_uint, out_buf_len: *mut c_uint, swap_bytes: PRBool) -> PRBool;
+#[allow(non_camel_case_types)]
+pub type SEC_PKCS12EncoderOutputCallback = extern "C" fn(arg: *mut c_void, buf: *const c_char, len: c_ulong);
+
+create_wrapper!(Decoder, SEC_PKCS12DecoderContext, SEC_PKCS12DecoderFinish);
+create_wrapper!(ExportContext, SEC_PKCS12ExportContext, SEC_PKCS12DestroyExportContext);
+
+#[link(name="smime3")]
+extern "C"
+{
+    // The digest callbacks may all be null, the decoder then buffers the data itself
+    pub fn SEC_PKCS12DecoderStart(pwitem: *mut SECItemFFI, slot: *mut PK11SlotInfo, wincx: *mut c_void,
+                                  d_open: *mut c_void, d_close: *mut c_void, d_read: *mut c_void, d_write: *mut c_void,
+                                  d_arg: *mut c_void) -> *mut SEC_PKCS12DecoderContext;
+    pub fn SEC_PKCS12DecoderUpdate(p12dcx: *mut SEC_PKCS12DecoderContext, data: *mut u8, len: c_ulong) -> SECStatus;
+    pub fn SEC_PKCS12DecoderVerify(p12dcx: *mut SEC_PKCS12DecoderContext) -> SECStatus;
+    pub fn SEC_PKCS12DecoderValidateBags(p12dcx: *mut SEC_PKCS12DecoderContext,
+                                         nickname_cb: SEC_PKCS12NicknameCollisionCallback) -> SECStatus;
+    pub fn SEC_PKCS12DecoderImportBags(p12dcx: *mut SEC_PKCS12DecoderContext) -> SECStatus;
+    pub fn SEC_PKCS12DecoderIterateInit(p12dcx: *mut SEC_PKCS12DecoderContext) -> SECStatus;
+    pub fn SEC_PKCS12DecoderIterateNext(p12dcx: *mut SEC_PKCS12DecoderContext, ipp: *mut *const SEC_PKCS12DecoderItem) -> SECStatus;
+    fn SEC_PKCS12DecoderFinish(p12dcx: *mut SEC_PKCS12DecoderContext);
+
+    pub fn SEC_PKCS12CreateExportContext(pwfn: *mut c_void, pwfnarg: *mut c_void, slot: *mut PK11SlotInfo,
+                                         wincx: *mut c_void) -> *mut SEC_PKCS12ExportContext;
+    pub fn SEC_PKCS12AddPasswordIntegrity(p12ctxt: *mut SEC_PKCS12ExportContext, pwitem: *mut SECItemFFI,
+                                          integ_alg: SECOidTag) -> SECStatus;
+    pub fn SEC_PKCS12CreatePasswordPrivSafe(p12ctxt: *mut SEC_PKCS12ExportContext, pwitem: *mut SECItemFFI,
+                                            priv_alg: SECOidTag) -> *mut SEC_PKCS12SafeInfo;
+    pub fn SEC_PKCS12CreateUnencryptedSafe(p12ctxt: *mut SEC_PKCS12ExportContext) -> *mut SEC_PKCS12SafeInfo;
+    pub fn SEC_PKCS12AddCertOrChainAndKey(p12ctxt: *mut SEC_PKCS12ExportContext, cert_safe: *mut SEC_PKCS12SafeInfo,
+                                          cert_nested_dest: *mut c_void, cert: *mut CERTCertificate,
+                                          cert_db: *mut CERTCertDBHandle, key_safe: *mut SEC_PKCS12SafeInfo,
+                                          key_nested_dest: *mut c_void, shroud_key: PRBool, pwitem: *mut SECItemFFI,
+                                          algorithm: SECOidTag, include_cert_chain: PRBool) -> SECStatus;
+    pub fn SEC_PKCS12Encode(p12exp: *mut SEC_PKCS12ExportContext, output: SEC_PKCS12EncoderOutputCallback,
+                            output_arg: *mut c_void) -> SECStatus;
+    fn SEC_PKCS12DestroyExportContext(p12exp: *mut SEC_PKCS12ExportContext);
+}
+
+#[link(name="nss3")]
+extern "C"
+{
+    pub fn CERT_MakeCANickname(cert: *mut CERTCertificate) -> *mut c_char;
+    pub fn PORT_SetUCS2_ASCIIConversionFunction(conv_func: PORTCharConversionWSwapFunc);
+    pub fn PORT_UCS2_UTF8Conversion(to_unicode: PRBool, in_buf: *mut u8, in_buf_len: c_uint, out_buf: *mut u8,
+                                    max_out_buf_len: c_uint, out_buf_len: *mut c_uint) -> PRBool;
+}
@@ -1,8 +1,9 @@
 use ffi::sec;
-use ffi::sec::{SECStatus, SECItemFFI};
+use ffi::sec::{SECStatus, SECItemFFI, SEC_ERROR_INVALID_ALGORITHM};
 use ffi::nspr::PRBool;
-use result::NSSResult;
-use libc::{c_void, c_int, c_uint, c_ulong};
+use result::{NSSResult, NSSError};
+use libc::{c_char, c_void, c_int, c_uint, c_ulong};
+use std::ffi::CStr;
 use std::ptr;
 
 #[allow(non_camel_case_types)]
@@ -10,6 +11,7 @@ pub type CK_MECHANISM_TYPE = c_ulong;
 
 pub const CKM_RSA_PKCS_KEY_PAIR_GEN : CK_MECHANISM_TYPE = 0x0000_0000;
 pub const CKM_RSA_PKCS      : CK_MECHANISM_TYPE = 0x0000_0001;
+pub const CKM_RSA_X_509     : CK_MECHANISM_TYPE = 0x0000_0003;
 pub const CKM_RSA_PKCS_OAEP : CK_MECHANISM_TYPE = 0x0000_0009;
 
 pub const CKM_SHA_1   : CK_MECHANISM_TYPE = 0x0000_0220;
@@ -24,23 +26,44 @@ pub const CKM_DES_CBC_PAD : CK_MECHANISM_TYPE = 0x0000_0125;
 pub const CKM_AES_ECB     : CK_MECHANISM_TYPE = 0x0000_1081;
 pub const CKM_AES_CBC     : CK_MECHANISM_TYPE = 0x0000_1082;
 pub const CKM_AES_CBC_PAD : CK_MECHANISM_TYPE = 0x0000_1085;
+pub const CKM_AES_KEY_WRAP     : CK_MECHANISM_TYPE = 0x0000_2109;
+pub const CKM_AES_KEY_WRAP_KWP : CK_MECHANISM_TYPE = 0x0000_210B;
+
+pub const CKM_EC_KEY_PAIR_GEN : CK_MECHANISM_TYPE = 0x0000_1040;
+pub const CKM_HKDF_KEY_GEN    : CK_MECHANISM_TYPE = 0x0000_402C;
 // CK_MECHANISM_TYPE
 
 #[allow(non_camel_case_types)]
 pub type CK_ATTRIBUTE_TYPE = c_ulong;
 
+pub const CKA_FLAGS_ONLY : CK_ATTRIBUTE_TYPE = 0x0000_0000;
 pub const CKA_ENCRYPT : CK_ATTRIBUTE_TYPE = 0x0000_0104;
 pub const CKA_DECRYPT : CK_ATTRIBUTE_TYPE = 0x0000_0105;
+pub const CKA_DERIVE  : CK_ATTRIBUTE_TYPE = 0x0000_010C;
 // CK_ATTRIBUTE_TYPE
 
 #[allow(non_camel_case_types)]
-type CK_RSA_PKCS_MGF_TYPE = c_ulong;
+pub type CK_FLAGS = c_ulong;
+
+pub const CKF_ENCRYPT : CK_FLAGS = 0x0000_0100;
+pub const CKF_DECRYPT : CK_FLAGS = 0x0000_0200;
+pub const CKF_WRAP    : CK_FLAGS = 0x0002_0000;
+pub const CKF_UNWRAP  : CK_FLAGS = 0x0004_0000;
+// CK_FLAGS
+
+#[allow(non_camel_case_types)]
+pub type CK_OBJECT_HANDLE = c_ulong;
 
-const CKG_MGF1_SHA1   : CK_RSA_PKCS_MGF_TYPE = 0x0000_0001;
-const CKG_MGF1_SHA224 : CK_RSA_PKCS_MGF_TYPE = 0x0000_0005;
-const CKG_MGF1_SHA256 : CK_RSA_PKCS_MGF_TYPE = 0x0000_0002;
-const CKG_MGF1_SHA384 : CK_RSA_PKCS_MGF_TYPE = 0x0000_0003;
-const CKG_MGF1_SHA512 : CK_RSA_PKCS_MGF_TYPE = 0x0000_0004;
+pub const CK_INVALID_HANDLE : CK_OBJECT_HANDLE = 0;
+
+#[allow(non_camel_case_types)]
+pub type CK_RSA_PKCS_MGF_TYPE = c_ulong;
+
+pub const CKG_MGF1_SHA1   : CK_RSA_PKCS_MGF_TYPE = 0x0000_0001;
+pub const CKG_MGF1_SHA224 : CK_RSA_PKCS_MGF_TYPE = 0x0000_0005;
+pub const CKG_MGF1_SHA256 : CK_RSA_PKCS_MGF_TYPE = 0x0000_0002;
+pub const CKG_MGF1_SHA384 : CK_RSA_PKCS_MGF_TYPE = 0x0000_0003;
+pub const CKG_MGF1_SHA512 : CK_RSA_PKCS_MGF_TYPE = 0x0000_0004;
 // CK_RSA_PKCS_MGF_TYPE
 
 #[repr(C)]
@@ -67,39 +90,107 @@ pub struct CK_RSA_PKCS_OAEP_PARAMS
 
 impl CK_RSA_PKCS_OAEP_PARAMS
 {
-    pub fn from_algorithm(ckm: CK_MECHANISM_TYPE) -> CK_RSA_PKCS_OAEP_PARAMS
+    pub fn from_algorithm(ckm: CK_MECHANISM_TYPE) -> NSSResult<CK_RSA_PKCS_OAEP_PARAMS>
     {
-        let mgf = mgf_type_from_ckm(ckm);
-        CK_RSA_PKCS_OAEP_PARAMS
+        CK_RSA_PKCS_OAEP_PARAMS::new(ckm, ckm, &[])
+    }
+
+    // The label is referenced, not copied, so it has to outlive the returned parameters
+    pub fn new(hash: CK_MECHANISM_TYPE, mgf_hash: CK_MECHANISM_TYPE, label: &[u8]) -> NSSResult<CK_RSA_PKCS_OAEP_PARAMS>
+    {
+        try!(hash_len_from_ckm(hash));
+        let mgf = try!(mgf_type_from_ckm(mgf_hash));
+        let data_source = match label.len()
         {
-            hash_alg: ckm,
+            0 => ptr::null_mut(),
+            _ => label.as_ptr() as *mut c_void,
+        };
+        Ok(CK_RSA_PKCS_OAEP_PARAMS
+        {
+            hash_alg: hash,
             mgf: mgf,
             source: CKZ_DATA_SPECIFIED,
-            data_source: ptr::null_mut(),
-            data_len: 0,
-        }
+            data_source: data_source,
+            data_len: label.len() as c_ulong,
+        })
     }
 }
 
-fn mgf_type_from_ckm(ckm: CK_MECHANISM_TYPE) -> CK_RSA_PKCS_MGF_TYPE
+pub fn mgf_type_from_ckm(ckm: CK_MECHANISM_TYPE) -> NSSResult<CK_RSA_PKCS_MGF_TYPE>
 {
     match ckm
     {
-        CKM_SHA_1 => CKG_MGF1_SHA1,
-        CKM_SHA_224 => CKG_MGF1_SHA224,
-        CKM_SHA_256 => CKG_MGF1_SHA256,
-        CKM_SHA_384 => CKG_MGF1_SHA384,
-        CKM_SHA_512 => CKG_MGF1_SHA512,
-        _ => panic!("Unsupported mechanism provided"),
+        CKM_SHA_1 => Ok(CKG_MGF1_SHA1),
+        CKM_SHA_224 => Ok(CKG_MGF1_SHA224),
+        CKM_SHA_256 => Ok(CKG_MGF1_SHA256),
+        CKM_SHA_384 => Ok(CKG_MGF1_SHA384),
+        CKM_SHA_512 => Ok(CKG_MGF1_SHA512),
+        _ => Err(NSSError::NSS(SEC_ERROR_INVALID_ALGORITHM)),
     }
 }
 
+pub fn hash_len_from_ckm(ckm: CK_MECHANISM_TYPE) -> NSSResult<usize>
+{
+    match ckm
+    {
+        CKM_SHA_1 => Ok(20),
+        CKM_SHA_224 => Ok(28),
+        CKM_SHA_256 => Ok(32),
+        CKM_SHA_384 => Ok(48),
+        CKM_SHA_512 => Ok(64),
+        _ => Err(NSSError::NSS(SEC_ERROR_INVALID_ALGORITHM)),
+    }
+}
+
+#[repr(C)]
 pub struct PK11RSAGenParams
 {
     pub key_size_bits: c_int,
     pub pe: c_ulong,
 }
 
+#[repr(C)]
+#[allow(dead_code)] // List all available options
+pub enum HpkeKemId
+{
+    HpkeDhKemP256Sha256 = 0x10,
+    HpkeDhKemX25519Sha256 = 0x20,
+}
+
+#[repr(C)]
+#[allow(dead_code)] // List all available options
+pub enum HpkeKdfId
+{
+    HpkeKdfHkdfSha256 = 1,
+    HpkeKdfHkdfSha384 = 2,
+    HpkeKdfHkdfSha512 = 3,
+}
+
+#[repr(C)]
+#[allow(dead_code)] // List all available options
+pub enum HpkeAeadId
+{
+    HpkeAeadAes128Gcm = 1,
+    HpkeAeadAes256Gcm = 2,
+    HpkeAeadChaCha20Poly1305 = 3,
+}
+
+#[repr(C)]
+#[allow(dead_code)] // List all available options
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum KeyType
+{
+    Null = 0,
+    RSA = 1,
+    DSA = 2,
+    Fortezza = 3,
+    DH = 4,
+    KEA = 5,
+    EC = 6,
+    RSAPSS = 7,
+    RSAOAEP = 8,
+}
+
 // Opaque structures, with pointer references only
 #[repr(C)] pub struct PK11SlotInfo(c_void);
 #[repr(C)] pub struct PK11Context(c_void);
@@ -107,6 +198,7 @@ pub struct PK11RSAGenParams
 #[repr(C)] pub struct SECKEYPrivateKey(c_void);
 #[repr(C)] pub struct SECKEYPublicKey(c_void);
 #[repr(C)] pub struct CERTSubjectPublicKeyInfo(c_void);
+#[repr(C)] pub struct HpkeContext(c_void);
 
 pub struct SlotInfo
 {
@@ -127,6 +219,13 @@ impl SlotInfo
         SlotInfo::from_ptr(ptr)
     }
 
+    /// Opens an additional softoken database, see SECMOD_OpenUserDB for the specification
+    pub fn open_user_db(spec: &CStr) -> NSSResult<SlotInfo>
+    {
+        let ptr = unsafe { SECMOD_OpenUserDB(spec.as_ptr()) };
+        SlotInfo::from_ptr(ptr)
+    }
+
     fn from_ptr(ptr: *mut PK11SlotInfo) -> NSSResult<SlotInfo>
     {
         if ptr.is_null() {
@@ -137,6 +236,11 @@ impl SlotInfo
         }
     }
 
+    pub fn get(&self) -> *const PK11SlotInfo
+    {
+        self.ptr
+    }
+
     pub fn get_mut(&mut self) -> *mut PK11SlotInfo
     {
         self.ptr
@@ -151,55 +255,15 @@ impl Drop for SlotInfo
     }
 }
 
-macro_rules! create_wrapper(
-    ($newname:ident, $ffiname:ty, $destructor:ident) => (
-        pub struct $newname
-        {
-            ptr: *mut $ffiname
-        }
-
-        impl $newname
-        {
-            pub fn wrap(ptr: *mut $ffiname) -> NSSResult<$newname>
-            {
-                match ptr.is_null()
-                {
-                    true => Err(::ffi::nspr::get_error_code()),
-                    false => Ok($newname { ptr: ptr }),
-                }
-            }
-
-            #[allow(dead_code)]
-            pub fn get(&self) -> *const $ffiname
-            {
-                self.ptr
-            }
-
-            #[allow(dead_code)]
-            pub fn get_mut(&mut self) -> *mut $ffiname
-            {
-                self.ptr
-            }
-        }
-
-        impl Drop for $newname
-        {
-            fn drop(&mut self)
-            {
-                unsafe { $destructor(self.ptr) }
-            }
-        }
-
-    );
-);
-
 unsafe fn context_destructor(context: *mut PK11Context) { PK11_DestroyContext(context, PRBool::True); }
+unsafe fn hpke_context_destructor(context: *mut HpkeContext) { PK11_HPKE_DestroyContext(context, PRBool::True); }
 
 create_wrapper!(SymKey, PK11SymKey, PK11_FreeSymKey);
 create_wrapper!(Context, PK11Context, context_destructor);
 create_wrapper!(PrivateKey, SECKEYPrivateKey, SECKEY_DestroyPrivateKey);
 create_wrapper!(PublicKey, SECKEYPublicKey, SECKEY_DestroyPublicKey);
 create_wrapper!(PublicKeyInfo, CERTSubjectPublicKeyInfo, SECKEY_DestroySubjectPublicKeyInfo);
+create_wrapper!(HpkeCtx, HpkeContext, hpke_context_destructor);
 
 pub const KU_ALL : c_uint = 0xFF;
 pub const CKZ_DATA_SPECIFIED : c_ulong = 0x0000_0001;
@@ -210,10 +274,37 @@ extern "C"
     fn PK11_GetBestSlot(typ: CK_MECHANISM_TYPE, wincx: *mut c_void) -> *mut PK11SlotInfo;
     fn PK11_GetInternalKeySlot() -> *mut PK11SlotInfo;
     fn PK11_FreeSlot(slot: *mut PK11SlotInfo);
-    pub fn PK11_ImportSymKey(slot: *mut PK11SlotInfo, cipher: CK_MECHANISM_TYPE, origin: PK11Origin,
-                             operation: CK_ATTRIBUTE_TYPE, key: *mut sec::SECItemFFI, wincx: *mut c_void)
-        -> *mut PK11SymKey;
+    fn SECMOD_OpenUserDB(module_spec: *const c_char) -> *mut PK11SlotInfo;
+    pub fn SECMOD_CloseUserDB(slot: *mut PK11SlotInfo) -> SECStatus;
+    pub fn PK11_IsInternalKeySlot(slot: *mut PK11SlotInfo) -> PRBool;
+    pub fn PK11_GetTokenName(slot: *mut PK11SlotInfo) -> *const c_char;
+    pub fn PK11_NeedUserInit(slot: *mut PK11SlotInfo) -> PRBool;
+    pub fn PK11_InitPin(slot: *mut PK11SlotInfo, sso_pw: *const c_char, user_pw: *const c_char) -> SECStatus;
+    pub fn PK11_CheckUserPassword(slot: *mut PK11SlotInfo, pw: *const c_char) -> SECStatus;
+    pub fn PK11_ImportSymKeyWithFlags(slot: *mut PK11SlotInfo, cipher: CK_MECHANISM_TYPE, origin: PK11Origin,
+                                      operation: CK_ATTRIBUTE_TYPE, key: *mut sec::SECItemFFI, flags: CK_FLAGS,
+                                      is_perm: PRBool, wincx: *mut c_void) -> *mut PK11SymKey;
     fn PK11_FreeSymKey(key: *mut PK11SymKey);
+    pub fn PK11_ReferenceSymKey(key: *mut PK11SymKey) -> *mut PK11SymKey;
+    pub fn PK11_KeyGen(slot: *mut PK11SlotInfo, typ: CK_MECHANISM_TYPE, param: *mut SECItemFFI, key_size: c_int,
+                       wincx: *mut c_void) -> *mut PK11SymKey;
+    pub fn PK11_GetKeyLength(key: *mut PK11SymKey) -> c_uint;
+    pub fn PK11_WrapSymKey(typ: CK_MECHANISM_TYPE, param: *mut SECItemFFI, wrapping_key: *mut PK11SymKey,
+                           sym_key: *mut PK11SymKey, wrapped_key: *mut SECItemFFI) -> SECStatus;
+    pub fn PK11_UnwrapSymKeyWithFlags(wrapping_key: *mut PK11SymKey, wrap_type: CK_MECHANISM_TYPE, param: *mut SECItemFFI,
+                                      wrapped_key: *mut SECItemFFI, target: CK_MECHANISM_TYPE, operation: CK_ATTRIBUTE_TYPE,
+                                      key_size: c_int, flags: CK_FLAGS) -> *mut PK11SymKey;
+    pub fn PK11_Encrypt(sym_key: *mut PK11SymKey, mechanism: CK_MECHANISM_TYPE, param: *mut SECItemFFI, out: *mut u8,
+                        out_len: *mut c_uint, max_len: c_uint, data: *const u8, data_len: c_uint) -> SECStatus;
+    pub fn PK11_Decrypt(sym_key: *mut PK11SymKey, mechanism: CK_MECHANISM_TYPE, param: *mut SECItemFFI, out: *mut u8,
+                        out_len: *mut c_uint, max_len: c_uint, enc: *const u8, enc_len: c_uint) -> SECStatus;
+    pub fn PK11_ExtractKeyValue(key: *mut PK11SymKey) -> SECStatus;
+    pub fn PK11_GetKeyData(key: *mut PK11SymKey) -> *mut SECItemFFI;
+    pub fn PK11_PubWrapSymKeyWithMechanism(pubkey: *mut SECKEYPublicKey, mech_type: CK_MECHANISM_TYPE, param: *mut SECItemFFI,
+                                           sym_key: *mut PK11SymKey, wrapped_key: *mut SECItemFFI) -> SECStatus;
+    pub fn PK11_PubUnwrapSymKeyWithMechanism(key: *mut SECKEYPrivateKey, mech_type: CK_MECHANISM_TYPE, param: *mut SECItemFFI,
+                                             wrapped_key: *mut SECItemFFI, target: CK_MECHANISM_TYPE,
+                                             operation: CK_ATTRIBUTE_TYPE, key_size: c_int) -> *mut PK11SymKey;
     pub fn PK11_ParamFromIV(typ: CK_MECHANISM_TYPE, iv: *mut sec::SECItemFFI) -> *mut sec::SECItemFFI;
     pub fn PK11_CreateContextBySymKey(typ: CK_MECHANISM_TYPE, operation: CK_ATTRIBUTE_TYPE,
                                       symKey: *mut PK11SymKey, param: *mut sec::SECItemFFI) -> *mut PK11Context;
@@ -224,6 +315,7 @@ extern "C"
 
     pub fn SECKEY_ConvertToPublicKey(private_key: *mut SECKEYPrivateKey) -> *mut SECKEYPublicKey;
     fn SECKEY_DestroyPrivateKey(private_key: *mut SECKEYPrivateKey);
+    pub fn SECKEY_CopyPrivateKey(private_key: *const SECKEYPrivateKey) -> *mut SECKEYPrivateKey;
     fn SECKEY_DestroyPublicKey(private_key: *mut SECKEYPublicKey);
     pub fn SECKEY_DecodeDERSubjectPublicKeyInfo(spkider: *const SECItemFFI) -> *mut CERTSubjectPublicKeyInfo;
     fn SECKEY_DestroySubjectPublicKeyInfo(cert: *mut CERTSubjectPublicKeyInfo);
@@ -234,10 +326,33 @@ extern "C"
     pub fn PK11_PubEncrypt(key: *mut SECKEYPublicKey, mechanism: CK_MECHANISM_TYPE, param: *mut SECItemFFI, out: *mut u8,
                            out_len: *mut c_uint, max_len: c_uint, data: *const u8, data_len: c_uint, wincx: *mut c_void) -> SECStatus;
     pub fn SECKEY_PublicKeyStrength(key: *const SECKEYPublicKey) -> c_uint;
+    pub fn SECKEY_PublicKeyStrengthInBits(key: *const SECKEYPublicKey) -> c_uint;
+    pub fn SECKEY_GetPublicKeyType(key: *const SECKEYPublicKey) -> KeyType;
+    pub fn SECKEY_GetPrivateKeyType(key: *const SECKEYPrivateKey) -> KeyType;
     pub fn PK11_PrivDecrypt(key: *mut SECKEYPrivateKey, mechanism: CK_MECHANISM_TYPE, param: *mut SECItemFFI,
                             out: *mut u8, out_len: *mut c_uint, max_len: c_uint, enc: *const u8, enc_len: c_uint) -> SECStatus;
+    pub fn PK11_GenerateRandom(data: *mut u8, len: c_int) -> SECStatus;
     pub fn PK11_GenerateKeyPair(slot: *mut PK11SlotInfo, kind: CK_MECHANISM_TYPE, param: *mut c_void, pub_key: *mut *mut SECKEYPublicKey,
                                 token: PRBool, sensitive: PRBool, wincx: *mut c_void) -> *mut SECKEYPrivateKey;
     pub fn SECKEY_EncodeDERSubjectPublicKeyInfo(pubk: *const SECKEYPublicKey) -> *mut SECItemFFI;
     pub fn PK11_ExportDERPrivateKeyInfo(privk: *mut SECKEYPrivateKey, wincx: *mut c_void) -> *mut SECItemFFI;
+
+    pub fn PK11_HPKE_ValidateParameters(kem_id: HpkeKemId, kdf_id: HpkeKdfId, aead_id: HpkeAeadId) -> SECStatus;
+    pub fn PK11_HPKE_NewContext(kem_id: HpkeKemId, kdf_id: HpkeKdfId, aead_id: HpkeAeadId, psk: *mut PK11SymKey,
+                                psk_id: *const SECItemFFI) -> *mut HpkeContext;
+    fn PK11_HPKE_DestroyContext(cx: *mut HpkeContext, freeit: PRBool);
+    pub fn PK11_HPKE_Deserialize(cx: *const HpkeContext, enc: *const u8, enc_len: c_uint,
+                                 out_pub_key: *mut *mut SECKEYPublicKey) -> SECStatus;
+    pub fn PK11_HPKE_Serialize(pk: *const SECKEYPublicKey, buf: *mut u8, len: *mut c_uint, max_len: c_uint) -> SECStatus;
+    pub fn PK11_HPKE_SetupS(cx: *mut HpkeContext, pk_e: *const SECKEYPublicKey, sk_e: *mut SECKEYPrivateKey,
+                            pk_r: *mut SECKEYPublicKey, info: *const SECItemFFI) -> SECStatus;
+    pub fn PK11_HPKE_SetupR(cx: *mut HpkeContext, pk_r: *const SECKEYPublicKey, sk_r: *mut SECKEYPrivateKey,
+                            enc: *const SECItemFFI, info: *const SECItemFFI) -> SECStatus;
+    pub fn PK11_HPKE_GetEncapPubKey(cx: *const HpkeContext) -> *const SECItemFFI;
+    pub fn PK11_HPKE_Seal(cx: *mut HpkeContext, aad: *const SECItemFFI, pt: *const SECItemFFI,
+                          out_ct: *mut *mut SECItemFFI) -> SECStatus;
+    pub fn PK11_HPKE_Open(cx: *mut HpkeContext, aad: *const SECItemFFI, ct: *const SECItemFFI,
+                          out_pt: *mut *mut SECItemFFI) -> SECStatus;
+    pub fn PK11_HPKE_ExportSecret(cx: *const HpkeContext, info: *const SECItemFFI, len: c_uint,
+                                  out_key: *mut *mut PK11SymKey) -> SECStatus;
 }
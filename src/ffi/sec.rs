@@ -1,7 +1,7 @@
 use result::NSSResult;
 use ffi::nspr;
 use ffi::nspr::PRBool;
-use libc::{c_uint, c_uchar};
+use libc::{c_uint, c_uchar, c_void};
 use std::{mem, ptr};
 use std::marker::PhantomData;
 
@@ -23,13 +23,41 @@ impl SECStatus
         {
             SECStatus::Success => Ok(()),
             SECStatus::Failure => Err(nspr::get_error_code()),
-            SECStatus::WouldBlock => panic!("Unexpectedly got SECWouldBlock"),
+            SECStatus::WouldBlock => Err(::result::NSSError::NSS(nspr::PR_WOULD_BLOCK_ERROR)),
         }
     }
 }
 
+const SEC_ERROR_BASE : i32 = -0x2000;
+pub const SEC_ERROR_BAD_DATA : i32 = SEC_ERROR_BASE + 2;
+pub const SEC_ERROR_INVALID_ARGS : i32 = SEC_ERROR_BASE + 5;
+pub const SEC_ERROR_INVALID_ALGORITHM : i32 = SEC_ERROR_BASE + 6;
+pub const SEC_ERROR_BAD_DER : i32 = SEC_ERROR_BASE + 9;
+pub const SEC_ERROR_BAD_SIGNATURE : i32 = SEC_ERROR_BASE + 10;
+pub const SEC_ERROR_EXPIRED_CERTIFICATE : i32 = SEC_ERROR_BASE + 11;
+pub const SEC_ERROR_REVOKED_CERTIFICATE : i32 = SEC_ERROR_BASE + 12;
+pub const SEC_ERROR_UNKNOWN_ISSUER : i32 = SEC_ERROR_BASE + 13;
+pub const SEC_ERROR_UNTRUSTED_ISSUER : i32 = SEC_ERROR_BASE + 20;
+pub const SEC_ERROR_UNTRUSTED_CERT : i32 = SEC_ERROR_BASE + 21;
+pub const SEC_ERROR_EXPIRED_ISSUER_CERTIFICATE : i32 = SEC_ERROR_BASE + 30;
+pub const SEC_ERROR_EXTENSION_NOT_FOUND : i32 = SEC_ERROR_BASE + 35;
+pub const SEC_ERROR_CA_CERT_INVALID : i32 = SEC_ERROR_BASE + 36;
+pub const SEC_ERROR_PATH_LEN_CONSTRAINT_INVALID : i32 = SEC_ERROR_BASE + 37;
+pub const SEC_ERROR_INVALID_KEY : i32 = SEC_ERROR_BASE + 40;
+pub const SEC_ERROR_INADEQUATE_KEY_USAGE : i32 = SEC_ERROR_BASE + 90;
+pub const SEC_ERROR_INADEQUATE_CERT_TYPE : i32 = SEC_ERROR_BASE + 91;
+pub const SEC_ERROR_CERT_NOT_IN_NAME_SPACE : i32 = SEC_ERROR_BASE + 112;
+#[cfg(test)]
+pub const SEC_ERROR_OCSP_TRY_SERVER_LATER : i32 = SEC_ERROR_BASE + 122;
+pub const SEC_ERROR_OCSP_UNKNOWN_CERT : i32 = SEC_ERROR_BASE + 126;
+pub const SEC_ERROR_CRL_NOT_FOUND : i32 = SEC_ERROR_BASE + 137;
+pub const SEC_ERROR_REVOKED_CERTIFICATE_CRL : i32 = SEC_ERROR_BASE + 145;
+pub const SEC_ERROR_REVOKED_CERTIFICATE_OCSP : i32 = SEC_ERROR_BASE + 146;
+pub const SEC_ERROR_POLICY_VALIDATION_FAILED : i32 = SEC_ERROR_BASE + 160;
+
 #[repr(C)]
 #[allow(dead_code)] // We just list all available in NSS
+#[derive(Clone, Copy)]
 enum SECItemType
 {
     Buffer = 0,
@@ -51,6 +79,7 @@ enum SECItemType
 }
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct SECItemFFI
 {
     typ: SECItemType,
@@ -58,6 +87,38 @@ pub struct SECItemFFI
     len: c_uint,
 }
 
+impl SECItemFFI
+{
+    /// Fills an empty item with a copy of the data, which `SECITEM_FreeItem` frees
+    pub unsafe fn alloc_copy(&mut self, data: &[u8]) -> NSSResult<()>
+    {
+        if SECITEM_AllocItem(ptr::null_mut(), self, data.len() as c_uint).is_null() {
+            return Err(nspr::get_error_code());
+        }
+        ptr::copy_nonoverlapping(data.as_ptr(), self.data as *mut c_uchar, data.len());
+        Ok(())
+    }
+
+    pub fn as_slice(&self) -> &[u8]
+    {
+        match self.len
+        {
+            0 => &[],
+            len => unsafe { ::std::slice::from_raw_parts(self.data, len as usize) },
+        }
+    }
+
+    // NSS keeps the length of decoded BIT STRINGs in bits
+    pub fn as_bit_slice(&self) -> &[u8]
+    {
+        match self.len
+        {
+            0 => &[],
+            len => unsafe { ::std::slice::from_raw_parts(self.data, (len as usize).div_ceil(8)) },
+        }
+    }
+}
+
 pub enum SECItem<'a>
 {
     Boxed(*mut SECItemFFI),
@@ -76,6 +137,25 @@ impl SECItem<'static>
         }
     }
 
+    // A copy owned by NSS, for items NSS keeps a pointer to
+    pub fn dup(buffer: &[u8]) -> NSSResult<SECItem<'static>>
+    {
+        let item = SECItem::from_buf(buffer);
+        SECItem::wrap(unsafe { SECITEM_DupItem(item.get()) })
+    }
+
+    // Hands a boxed item over to NSS, for callbacks that return an item NSS frees
+    pub fn into_raw(self) -> *mut SECItemFFI
+    {
+        let result = match self
+        {
+            SECItem::Boxed(ptr) => ptr,
+            SECItem::Data(..) => panic!("Only boxed items are allocated by NSS"),
+        };
+        mem::forget(self);
+        result
+    }
+
     pub fn empty() -> SECItem<'static>
     {
         SECItem::Data(SECItemFFI {
@@ -99,6 +179,18 @@ impl<'a> SECItem<'a>
         SECItem::Data(si, PhantomData)
     }
 
+    // For output parameters, NSS writes into the buffer and updates the length
+    pub fn from_mut_buf(buffer: &'a mut [u8]) -> SECItem<'a>
+    {
+        let si = SECItemFFI
+        {
+            typ: SECItemType::Buffer,
+            data: buffer.as_mut_ptr(),
+            len: buffer.len() as c_uint,
+        };
+        SECItem::Data(si, PhantomData)
+    }
+
     pub fn from_struct<T>(data: &'a T) -> SECItem<'a>
     {
         let len = mem::size_of::<T>() as c_uint;
@@ -134,11 +226,24 @@ impl<'a> SECItem<'a>
         }
     }
 
+    pub fn len(&self) -> usize
+    {
+        self.get().len as usize
+    }
+
     pub fn copy_buf(&self) -> Vec<u8>
     {
-        let si = self.get();
-        let buf : &[u8] = unsafe { ::std::slice::from_raw_parts(si.data, si.len as usize) };
-        buf.to_vec()
+        self.get().as_slice().to_vec()
+    }
+
+    // For output parameters where NSS allocated the data, this copies it out and releases the allocation
+    pub fn take_buf(mut self) -> Vec<u8>
+    {
+        let result = self.copy_buf();
+        if let SECItem::Data(ref mut si, _) = self {
+            unsafe { SECITEM_FreeItem(si, PRBool::False) };
+        }
+        result
     }
 }
 
@@ -158,4 +263,6 @@ impl<'a> Drop for SECItem<'a>
 extern "C"
 {
     fn SECITEM_FreeItem(item: *mut SECItemFFI, freeitem: PRBool);
+    fn SECITEM_DupItem(from: *const SECItemFFI) -> *mut SECItemFFI;
+    fn SECITEM_AllocItem(arena: *mut c_void, item: *mut SECItemFFI, len: c_uint) -> *mut SECItemFFI;
 }
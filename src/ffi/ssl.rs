@@ -0,0 +1,213 @@
+use result::{NSSResult, NSSError};
+use ffi::sec::SECStatus;
+use ffi::nspr::{PRBool, PRFileDesc};
+use ffi::cert::{CERTCertificate, CERTCertificateList, CERTCertList};
+use ffi::sec::SECItemFFI;
+use ffi::pk11::{PK11SymKey, SECKEYPrivateKey};
+use libc::{c_char, c_int, c_uint, c_void};
+use std::mem;
+
+pub const SSL_SECURITY            : i32 = 1;
+pub const SSL_HANDSHAKE_AS_CLIENT : i32 = 5;
+pub const SSL_REQUEST_CERTIFICATE : i32 = 3;
+pub const SSL_NO_CACHE            : i32 = 9;
+pub const SSL_REQUIRE_CERTIFICATE : i32 = 10;
+pub const SSL_ENABLE_SESSION_TICKETS : i32 = 18;
+pub const SSL_ENABLE_0RTT_DATA    : i32 = 33;
+
+pub const SSL_REQUIRE_NEVER  : c_int = 0;
+pub const SSL_REQUIRE_ALWAYS : c_int = 1;
+
+const SSL_ERROR_BASE : i32 = -0x3000;
+pub const SSL_ERROR_BAD_CERTIFICATE : i32 = SSL_ERROR_BASE + 4;
+pub const SSL_ERROR_UNSUPPORTED_VERSION : i32 = SSL_ERROR_BASE + 9;
+#[cfg(test)]
+pub const SSL_ERROR_BAD_CERT_DOMAIN : i32 = SSL_ERROR_BASE + 12;
+pub const SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API : i32 = SSL_ERROR_BASE + 164;
+
+pub const SSL_NEXT_PROTO_NEGOTIATED : c_int = 1;
+pub const SSL_NEXT_PROTO_SELECTED : c_int = 3;
+
+pub const SSL_ALLOWED : i32 = 1;
+
+pub const SSL_PREINFO_0RTT_CIPHER_SUITE : u32 = 1 << 2;
+
+#[repr(C)]
+pub struct SSLExtraServerCertData
+{
+    pub auth_type: c_int,
+    pub cert_chain: *const CERTCertificateList,
+    pub stapled_ocsp_responses: *const c_void,
+    pub signed_cert_timestamps: *const SECItemFFI,
+    pub deleg_cred: *const SECItemFFI,
+    pub deleg_cred_priv_key: *const SECKEYPrivateKey,
+}
+
+#[repr(C)]
+pub struct SSLChannelInfo
+{
+    pub length: u32,
+    pub protocol_version: u16,
+    pub cipher_suite: u16,
+    pub auth_key_bits: u32,
+    pub kea_key_bits: u32,
+    pub creation_time: u32,
+    pub last_access_time: u32,
+    pub expiration_time: u32,
+    pub session_id_length: u32,
+    pub session_id: [u8; 32],
+    pub compression_method_name: *const c_char,
+    pub compression_method: c_int,
+    pub extended_master_secret_used: PRBool,
+    pub early_data_accepted: PRBool,
+    pub kea_type: c_int,
+    pub kea_group: c_int,
+    pub sym_cipher: c_int,
+    pub mac_algorithm: c_int,
+    pub auth_type: c_int,
+    pub signature_scheme: c_int,
+    pub original_kea_group: c_int,
+    pub resumed: PRBool,
+    pub peer_deleg_cred: PRBool,
+    pub psk_type: c_int,
+    pub ech_accepted: PRBool,
+    pub is_fips: PRBool,
+}
+
+#[repr(C)]
+pub struct SSLVersionRange
+{
+    pub min: u16,
+    pub max: u16,
+}
+
+#[repr(C)]
+pub struct SSLCipherSuiteInfo
+{
+    pub length: u16,
+    pub cipher_suite: u16,
+    pub cipher_suite_name: *const c_char,
+    pub auth_algorithm_name: *const c_char,
+    pub auth_algorithm: c_int,
+    pub kea_type_name: *const c_char,
+    pub kea_type: c_int,
+    pub sym_cipher_name: *const c_char,
+    pub sym_cipher: c_int,
+    pub sym_key_bits: u16,
+    pub sym_key_space: u16,
+    pub effective_key_bits: u16,
+    pub mac_algorithm_name: *const c_char,
+    pub mac_algorithm: c_int,
+    pub mac_bits: u16,
+    /// The bit fields isFIPS, isExportable and nonStandard
+    pub flags: c_uint,
+    pub auth_type: c_int,
+    pub kdf_hash: c_int,
+}
+
+#[repr(C)]
+pub struct SSLPreliminaryChannelInfo
+{
+    pub length: u32,
+    pub values_set: u32,
+    pub protocol_version: u16,
+    pub cipher_suite: u16,
+    pub can_send_early_data: PRBool,
+    pub max_early_data_size: u32,
+    pub zero_rtt_cipher_suite: u16,
+    pub peer_deleg_cred: PRBool,
+    pub auth_key_bits: u32,
+    pub signature_scheme: c_int,
+    pub ech_accepted: PRBool,
+    pub ech_public_name: *const c_char,
+}
+
+#[repr(C)] pub struct SSLAntiReplayContext(c_void);
+
+pub type SSLAuthCertificate = extern "C" fn(arg: *mut c_void, fd: *mut PRFileDesc, check_sig: PRBool,
+                                           is_server: PRBool) -> SECStatus;
+pub type SSLGetClientAuthData = extern "C" fn(arg: *mut c_void, fd: *mut PRFileDesc, ca_names: *mut c_void,
+                                             ret_cert: *mut *mut CERTCertificate, ret_key: *mut *mut SECKEYPrivateKey) -> SECStatus;
+
+// The experimental API, of which libssl only exports the names
+pub type SSLCreateAntiReplayContext = unsafe extern "C" fn(now: i64, window: i64, k: c_uint, bits: c_uint,
+                                                           ctx: *mut *mut SSLAntiReplayContext) -> SECStatus;
+pub type SSLAddExternalPsk = unsafe extern "C" fn(fd: *mut PRFileDesc, psk: *mut PK11SymKey, identity: *const u8,
+                                                  identity_len: c_uint, hash: c_int) -> SECStatus;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SSLCertificateCompressionAlgorithm
+{
+    pub id: u16,
+    pub name: *const c_char,
+    pub encode: Option<unsafe extern "C" fn(input: *const SECItemFFI, output: *mut SECItemFFI) -> SECStatus>,
+    pub decode: Option<unsafe extern "C" fn(input: *const SECItemFFI, output: *mut u8, output_len: usize,
+                                            used_len: *mut usize) -> SECStatus>,
+}
+
+pub type SSLSetCertificateCompressionAlgorithm = unsafe extern "C" fn(fd: *mut PRFileDesc,
+                                                                      alg: SSLCertificateCompressionAlgorithm) -> SECStatus;
+#[cfg(feature = "keylog")]
+pub const SSL_SECRET_READ : c_int = 1;
+
+#[cfg(feature = "keylog")]
+pub type SSLSecretCallback = extern "C" fn(fd: *mut PRFileDesc, epoch: u16, dir: c_int, secret: *mut PK11SymKey, arg: *mut c_void);
+#[cfg(feature = "keylog")]
+pub type SSLSetSecretCallback = unsafe extern "C" fn(fd: *mut PRFileDesc, cb: SSLSecretCallback, arg: *mut c_void) -> SECStatus;
+
+pub type SSLReleaseAntiReplayContext = unsafe extern "C" fn(ctx: *mut SSLAntiReplayContext);
+pub type SSLSetAntiReplayContext = unsafe extern "C" fn(fd: *mut PRFileDesc, ctx: *mut SSLAntiReplayContext) -> SECStatus;
+
+/// Looks up a function of the experimental API, with a name that ends with a nul
+pub unsafe fn experimental_api<F: Copy>(name: &[u8]) -> NSSResult<F>
+{
+    assert_eq!(mem::size_of::<F>(), mem::size_of::<*mut c_void>());
+    let function = SSL_GetExperimentalAPI(name.as_ptr() as *const c_char);
+    match function.is_null()
+    {
+        true => Err(NSSError::NSS(SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API)),
+        false => Ok(mem::transmute_copy(&function)),
+    }
+}
+
+#[link(name="ssl3")]
+extern "C"
+{
+    pub fn NSS_SetDomesticPolicy() -> SECStatus;
+    pub fn SSL_ImportFD(model: *mut PRFileDesc, fd: *mut PRFileDesc) -> *mut PRFileDesc;
+    pub fn DTLS_ImportFD(model: *mut PRFileDesc, fd: *mut PRFileDesc) -> *mut PRFileDesc;
+    pub fn DTLS_GetHandshakeTimeout(fd: *mut PRFileDesc, timeout: *mut u32) -> SECStatus;
+    pub fn SSL_OptionSet(fd: *mut PRFileDesc, option: i32, val: c_int) -> SECStatus;
+    #[cfg(feature = "keylog")]
+    pub fn SSL_OptionGet(fd: *mut PRFileDesc, option: i32, val: *mut c_int) -> SECStatus;
+    pub fn SSL_SetURL(fd: *mut PRFileDesc, url: *const c_char) -> SECStatus;
+    pub fn SSL_SetSockPeerID(fd: *mut PRFileDesc, peer_id: *const c_char) -> SECStatus;
+    pub fn SSL_AuthCertificateHook(fd: *mut PRFileDesc, f: SSLAuthCertificate, arg: *mut c_void) -> SECStatus;
+    pub fn SSL_ResetHandshake(fd: *mut PRFileDesc, as_server: PRBool) -> SECStatus;
+    pub fn SSL_ForceHandshake(fd: *mut PRFileDesc) -> SECStatus;
+    pub fn SSL_PeerCertificate(fd: *mut PRFileDesc) -> *mut CERTCertificate;
+    pub fn SSL_GetClientAuthDataHook(fd: *mut PRFileDesc, f: SSLGetClientAuthData, arg: *mut c_void) -> SECStatus;
+    pub fn SSL_ConfigServerCert(fd: *mut PRFileDesc, cert: *mut CERTCertificate, key: *mut SECKEYPrivateKey,
+                                data: *const SSLExtraServerCertData, data_len: c_uint) -> SECStatus;
+    pub fn SSL_SetNextProtoNego(fd: *mut PRFileDesc, data: *const u8, length: c_uint) -> SECStatus;
+    pub fn SSL_GetNextProto(fd: *mut PRFileDesc, state: *mut c_int, buf: *mut u8, buf_len: *mut c_uint,
+                            buf_len_max: c_uint) -> SECStatus;
+    pub fn SSL_SendAdditionalKeyShares(fd: *mut PRFileDesc, count: c_uint) -> SECStatus;
+    pub fn SSL_GetChannelInfo(fd: *mut PRFileDesc, info: *mut SSLChannelInfo, len: c_uint) -> SECStatus;
+    pub fn SSL_GetImplementedCiphers() -> *const u16;
+    pub fn SSL_GetNumImplementedCiphers() -> u16;
+    pub fn SSL_CipherPolicyGet(cipher: i32, policy: *mut i32) -> SECStatus;
+    pub fn SSL_CipherPrefSet(fd: *mut PRFileDesc, cipher: i32, enabled: PRBool) -> SECStatus;
+    pub fn SSL_VersionRangeSet(fd: *mut PRFileDesc, range: *const SSLVersionRange) -> SECStatus;
+    pub fn SSL_NamedGroupConfig(fd: *mut PRFileDesc, groups: *const c_int, num_groups: c_uint) -> SECStatus;
+    pub fn SSL_SignatureSchemePrefSet(fd: *mut PRFileDesc, schemes: *const c_int, count: c_uint) -> SECStatus;
+    pub fn SSL_GetCipherSuiteInfo(cipher_suite: u16, info: *mut SSLCipherSuiteInfo, len: c_uint) -> SECStatus;
+    pub fn SSL_PeerCertificateChain(fd: *mut PRFileDesc) -> *mut CERTCertList;
+    pub fn SSL_ExportKeyingMaterial(fd: *mut PRFileDesc, label: *const c_char, label_len: c_uint, has_context: PRBool,
+                                    context: *const u8, context_len: c_uint, out: *mut u8, out_len: c_uint) -> SECStatus;
+    pub fn SSL_GetPreliminaryChannelInfo(fd: *mut PRFileDesc, info: *mut SSLPreliminaryChannelInfo, len: c_uint) -> SECStatus;
+    fn SSL_GetExperimentalAPI(name: *const c_char) -> *mut c_void;
+    pub fn SSL_ConfigServerSessionIDCache(max_cache_entries: c_int, timeout: u32, ssl3_timeout: u32,
+                                          directory: *const c_char) -> SECStatus;
+}
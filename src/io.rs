@@ -0,0 +1,399 @@
+use result::NSSResult;
+use ffi::nspr::{self, PRFileDesc, PRIOMethods, PRSocketOptionData, PRStatus};
+use std::io::{self, Read, Write};
+use std::sync::Once;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::{ptr, slice};
+#[cfg(feature = "keylog")]
+use std::cmp;
+use libc::{c_int, c_void};
+
+/// What a layer reads from and writes to
+trait Stream: Read + Write {}
+
+impl<S: Read + Write> Stream for S {}
+
+/// What the file descriptor of a layer refers to
+struct Secret
+{
+    stream: Box<dyn Stream>,
+    nonblocking: bool,
+    /// Reading fails with `PR_WOULD_BLOCK_ERROR`, to stop a handshake where it would wait for the peer
+    hold_reads: bool,
+    /// The first bytes read and written, which start with a ClientHello for TLS and DTLS
+    #[cfg(feature = "keylog")]
+    read_prefix: Vec<u8>,
+    #[cfg(feature = "keylog")]
+    written_prefix: Vec<u8>,
+}
+
+/// A TLS record header, the header of a ClientHello and its version come before its random
+#[cfg(feature = "keylog")]
+const RECORD_HEADER_LEN : usize = 5;
+#[cfg(feature = "keylog")]
+const RANDOM_OFFSET : usize = RECORD_HEADER_LEN + 4 + 2;
+/// DTLS has longer headers for records and handshake messages
+#[cfg(feature = "keylog")]
+const DTLS_RECORD_HEADER_LEN : usize = 13;
+#[cfg(feature = "keylog")]
+const DTLS_RANDOM_OFFSET : usize = DTLS_RECORD_HEADER_LEN + 12 + 2;
+#[cfg(feature = "keylog")]
+const PREFIX_LEN : usize = DTLS_RANDOM_OFFSET + 32;
+
+static IDENTITY: AtomicI32 = AtomicI32::new(nspr::PR_INVALID_IO_LAYER);
+
+static METHODS: PRIOMethods = PRIOMethods {
+    file_type: nspr::PR_DESC_LAYERED,
+    close: Some(close),
+    read: Some(read),
+    write: Some(write),
+    available: Some(available),
+    available64: Some(available64),
+    fsync: Some(fsync),
+    seek: Some(invalid),
+    seek64: Some(invalid),
+    file_info: Some(invalid),
+    file_info64: Some(invalid),
+    writev: Some(invalid),
+    connect: Some(invalid),
+    accept: Some(accept),
+    bind: Some(invalid),
+    listen: Some(invalid),
+    shutdown: Some(shutdown),
+    recv: Some(recv),
+    send: Some(send),
+    recvfrom: Some(invalid),
+    sendto: Some(invalid),
+    poll: Some(poll),
+    acceptread: Some(invalid),
+    transmitfile: Some(invalid),
+    getsockname: Some(getname),
+    getpeername: Some(getname),
+    reserved_fn_6: Some(invalid),
+    reserved_fn_5: Some(invalid),
+    getsocketoption: Some(getsocketoption),
+    setsocketoption: Some(setsocketoption),
+    sendfile: Some(invalid),
+    connectcontinue: Some(invalid),
+    reserved_fn_3: Some(invalid),
+    reserved_fn_2: Some(invalid),
+    reserved_fn_1: Some(invalid),
+    reserved_fn_0: Some(invalid),
+};
+
+fn identity() -> nspr::PRDescIdentity
+{
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        let identity = unsafe { nspr::PR_GetUniqueIdentity(b"nss-rs\0".as_ptr() as *const _) };
+        IDENTITY.store(identity, Ordering::SeqCst);
+    });
+    IDENTITY.load(Ordering::SeqCst)
+}
+
+/// Creates a file descriptor that reads from and writes to the stream, for NSS code that takes one
+///
+/// A nonblocking stream fails with `WouldBlock` instead of blocking, which NSS then passes on.
+/// Its addresses are unspecified, and it is dropped when the file descriptor is closed.
+pub(crate) fn layer<S>(stream: S, nonblocking: bool) -> NSSResult<nspr::FileDesc>
+    where S: Read + Write + Send + 'static
+{
+    let secret = Box::new(Secret {
+        stream: Box::new(stream),
+        nonblocking: nonblocking,
+        hold_reads: false,
+        #[cfg(feature = "keylog")]
+        read_prefix: Vec::new(),
+        #[cfg(feature = "keylog")]
+        written_prefix: Vec::new(),
+    });
+    let mut fd = try!(unsafe { nspr::FileDesc::wrap(nspr::PR_CreateIOLayerStub(identity(), &METHODS)) });
+    unsafe { (*fd.get_mut()).secret = Box::into_raw(secret) as *mut c_void };
+    Ok(fd)
+}
+
+/// Lets reading from a layer fail until it is released again
+pub(crate) unsafe fn hold_reads(fd: *mut PRFileDesc, hold: bool)
+{
+    assert_eq!(identity(), (*fd).identity);
+    secret(fd).hold_reads = hold;
+}
+
+/// The random of the ClientHello that a client wrote to a layer, or a server read from it
+#[cfg(feature = "keylog")]
+pub(crate) unsafe fn client_random(fd: *mut PRFileDesc, server: bool) -> Option<[u8; 32]>
+{
+    assert_eq!(identity(), (*fd).identity);
+    let secret = secret(fd);
+    let prefix = if server { &secret.read_prefix } else { &secret.written_prefix };
+    // DTLS versions start with 0xfe, where TLS versions start with 3
+    let (header_len, offset) = match prefix.get(1)
+    {
+        Some(&0xfe) => (DTLS_RECORD_HEADER_LEN, DTLS_RANDOM_OFFSET),
+        _ => (RECORD_HEADER_LEN, RANDOM_OFFSET),
+    };
+    // A handshake record with a ClientHello
+    if prefix.len() < offset + 32 || prefix[0] != 22 || prefix[header_len] != 1 {
+        return None;
+    }
+    let mut random = [0; 32];
+    random.copy_from_slice(&prefix[offset..offset + 32]);
+    Some(random)
+}
+
+/// Keeps the first bytes of the data that pass a layer, for `client_random`
+#[cfg(feature = "keylog")]
+fn record_prefix(prefix: &mut Vec<u8>, data: &[u8])
+{
+    let len = cmp::min(PREFIX_LEN - prefix.len(), data.len());
+    prefix.extend_from_slice(&data[..len]);
+}
+
+unsafe fn secret<'a>(fd: *mut PRFileDesc) -> &'a mut Secret
+{
+    &mut *((*fd).secret as *mut Secret)
+}
+
+unsafe fn stream<'a>(fd: *mut PRFileDesc) -> &'a mut dyn Stream
+{
+    &mut *secret(fd).stream
+}
+
+fn set_error(error: io::Error)
+{
+    let code = match error.kind()
+    {
+        io::ErrorKind::WouldBlock => nspr::PR_WOULD_BLOCK_ERROR,
+        io::ErrorKind::TimedOut => nspr::PR_IO_TIMEOUT_ERROR,
+        io::ErrorKind::ConnectionReset => nspr::PR_CONNECT_RESET_ERROR,
+        io::ErrorKind::ConnectionAborted => nspr::PR_CONNECT_ABORTED_ERROR,
+        io::ErrorKind::NotConnected => nspr::PR_NOT_CONNECTED_ERROR,
+        io::ErrorKind::UnexpectedEof => nspr::PR_END_OF_FILE_ERROR,
+        _ => nspr::PR_IO_ERROR,
+    };
+    unsafe { nspr::PR_SetError(code, error.raw_os_error().unwrap_or(0)) };
+}
+
+/// Runs an operation of the stream until it is not interrupted, returning -1 on an error as NSPR does
+fn retry<F>(mut f: F) -> i32
+    where F: FnMut() -> io::Result<usize>
+{
+    loop
+    {
+        match f()
+        {
+            Ok(len) => return len as i32,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+            Err(e) => {
+                set_error(e);
+                return -1;
+            },
+        }
+    }
+}
+
+unsafe extern "C" fn close(fd: *mut PRFileDesc) -> PRStatus
+{
+    drop(Box::from_raw((*fd).secret as *mut Secret));
+    (*fd).secret = ptr::null_mut();
+    if let Some(dtor) = (*fd).dtor {
+        dtor(fd);
+    }
+    PRStatus::PR_SUCCESS
+}
+
+unsafe extern "C" fn read(fd: *mut PRFileDesc, buf: *mut c_void, amount: i32) -> i32
+{
+    if secret(fd).hold_reads {
+        nspr::PR_SetError(nspr::PR_WOULD_BLOCK_ERROR, 0);
+        return -1;
+    }
+    if amount <= 0 {
+        return 0;
+    }
+    let buf = slice::from_raw_parts_mut(buf as *mut u8, amount as usize);
+    let read = retry(|| stream(fd).read(buf));
+    #[cfg(feature = "keylog")]
+    {
+        if read > 0 {
+            record_prefix(&mut secret(fd).read_prefix, &buf[..read as usize]);
+        }
+    }
+    read
+}
+
+unsafe extern "C" fn write(fd: *mut PRFileDesc, buf: *const c_void, amount: i32) -> i32
+{
+    if amount <= 0 {
+        return 0;
+    }
+    let buf = slice::from_raw_parts(buf as *const u8, amount as usize);
+    let written = retry(|| stream(fd).write(buf));
+    #[cfg(feature = "keylog")]
+    {
+        if written > 0 {
+            record_prefix(&mut secret(fd).written_prefix, &buf[..written as usize]);
+        }
+    }
+    written
+}
+
+unsafe extern "C" fn recv(fd: *mut PRFileDesc, buf: *mut c_void, amount: i32, _flags: c_int, _timeout: u32) -> i32
+{
+    read(fd, buf, amount)
+}
+
+unsafe extern "C" fn send(fd: *mut PRFileDesc, buf: *const c_void, amount: i32, _flags: c_int, _timeout: u32) -> i32
+{
+    write(fd, buf, amount)
+}
+
+unsafe extern "C" fn available(_fd: *mut PRFileDesc) -> i32
+{
+    0
+}
+
+unsafe extern "C" fn available64(_fd: *mut PRFileDesc) -> i64
+{
+    0
+}
+
+unsafe extern "C" fn fsync(fd: *mut PRFileDesc) -> PRStatus
+{
+    match retry(|| stream(fd).flush().map(|()| 0))
+    {
+        -1 => PRStatus::PR_FAILURE,
+        _ => PRStatus::PR_SUCCESS,
+    }
+}
+
+unsafe extern "C" fn shutdown(fd: *mut PRFileDesc, _how: c_int) -> PRStatus
+{
+    fsync(fd)
+}
+
+unsafe extern "C" fn poll(_fd: *mut PRFileDesc, in_flags: i16, out_flags: *mut i16) -> i16
+{
+    // Nothing is known about the stream, so the caller should just try
+    *out_flags = 0;
+    in_flags
+}
+
+unsafe extern "C" fn getname(_fd: *mut PRFileDesc, addr: *mut c_void) -> PRStatus
+{
+    // libssl needs an address, so all streams are at 0.0.0.0:0
+    nspr::PR_InitializeNetAddr(nspr::PR_IpAddrAny, 0, addr)
+}
+
+unsafe extern "C" fn getsocketoption(fd: *mut PRFileDesc, data: *mut PRSocketOptionData) -> PRStatus
+{
+    if (*data).option == nspr::PR_SockOpt_Nonblocking {
+        (*data).value.non_blocking = secret(fd).nonblocking as c_int;
+        return PRStatus::PR_SUCCESS;
+    }
+    nspr::PR_SetError(nspr::PR_OPERATION_NOT_SUPPORTED_ERROR, 0);
+    PRStatus::PR_FAILURE
+}
+
+unsafe extern "C" fn setsocketoption(_fd: *mut PRFileDesc, _data: *const PRSocketOptionData) -> PRStatus
+{
+    nspr::PR_SetError(nspr::PR_OPERATION_NOT_SUPPORTED_ERROR, 0);
+    PRStatus::PR_FAILURE
+}
+
+unsafe extern "C" fn accept(_fd: *mut PRFileDesc, _addr: *mut c_void, _timeout: u32) -> *mut PRFileDesc
+{
+    nspr::PR_SetError(nspr::PR_INVALID_METHOD_ERROR, 0);
+    ptr::null_mut()
+}
+
+unsafe extern "C" fn invalid() -> isize
+{
+    nspr::PR_SetError(nspr::PR_INVALID_METHOD_ERROR, 0);
+    -1
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::AtomicBool;
+
+    /// Reads from a fixed input and records what is written and whether it is dropped
+    struct Pipe
+    {
+        input: Cursor<Vec<u8>>,
+        output: Arc<Mutex<Vec<u8>>>,
+        dropped: Arc<AtomicBool>,
+    }
+
+    impl Read for Pipe
+    {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+        {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe
+    {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+        {
+            self.output.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()>
+        {
+            Err(io::Error::new(io::ErrorKind::WouldBlock, "flush"))
+        }
+    }
+
+    impl Drop for Pipe
+    {
+        fn drop(&mut self)
+        {
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn read_write()
+    {
+        ::nss::init().unwrap();
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let dropped = Arc::new(AtomicBool::new(false));
+        let pipe = Pipe { input: Cursor::new(b"input".to_vec()), output: output.clone(), dropped: dropped.clone() };
+        let mut fd = layer(pipe, false).unwrap();
+
+        let mut buf = [0; 8];
+        unsafe
+        {
+            assert_eq!(5, nspr::PR_Read(fd.get_mut(), buf.as_mut_ptr() as *mut c_void, 8));
+            assert_eq!(0, nspr::PR_Read(fd.get_mut(), buf.as_mut_ptr() as *mut c_void, 8));
+            assert_eq!(6, nspr::PR_Write(fd.get_mut(), b"output".as_ptr() as *const c_void, 6));
+        }
+        assert_eq!(b"input", &buf[..5]);
+        assert_eq!(b"output", &output.lock().unwrap()[..]);
+
+        assert!(!dropped.load(Ordering::SeqCst));
+        drop(fd);
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn errors()
+    {
+        ::nss::init().unwrap();
+        let pipe = Pipe { input: Cursor::new(Vec::new()), output: Arc::default(), dropped: Arc::default() };
+        let mut fd = layer(pipe, false).unwrap();
+
+        match unsafe { (METHODS.fsync.unwrap())(fd.get_mut()) }
+        {
+            PRStatus::PR_FAILURE => {},
+            PRStatus::PR_SUCCESS => panic!("flush succeeded"),
+        }
+        assert_eq!(::result::NSSError::NSS(nspr::PR_WOULD_BLOCK_ERROR), nspr::get_error_code());
+    }
+}
@@ -1,8 +1,14 @@
 extern crate libc;
+#[cfg(feature = "tokio")]
+extern crate tokio;
 
-pub use nss::{init, close};
+pub use nss::{init, init_with_db, close};
 
 mod ffi;
+mod io;
 mod nss;
+pub mod cert;
+pub mod cms;
 pub mod crypto;
 pub mod result;
+pub mod ssl;
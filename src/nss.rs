@@ -1,18 +1,45 @@
 use std::sync::atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
-use result::NSSResult;
-use ffi::{nss, nspr};
+use result::{NSSResult, NSSError};
+use ffi::{nss, nspr, sec};
+use std::ffi::CString;
+use std::ptr;
 
 static NSSBUSY: AtomicBool = ATOMIC_BOOL_INIT;
 static NSSINIT: AtomicBool = ATOMIC_BOOL_INIT;
 
 pub fn init() -> NSSResult<()>
+{
+    initialize(Ok(()), || unsafe { nss::NSS_NoDB_Init(ptr::null_mut()).to_result() })
+}
+
+/// Initializes NSS with the certificate and key database in the directory, like `certutil -d`
+///
+/// This fails once NSS is initialized, which any other use of NSS does without a database.
+pub fn init_with_db(config_dir: &str) -> NSSResult<()>
+{
+    let config_dir = match CString::new(config_dir)
+    {
+        Ok(config_dir) => config_dir,
+        Err(..) => return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS)),
+    };
+    let empty = CString::new("").unwrap();
+    let secmod = CString::new("secmod.db").unwrap();
+
+    initialize(Err(NSSError::NSS(nspr::PR_INVALID_STATE_ERROR)), || unsafe
+    {
+        nss::NSS_Initialize(config_dir.as_ptr(), empty.as_ptr(), empty.as_ptr(), secmod.as_ptr(), 0).to_result()
+    })
+}
+
+fn initialize<F>(initialized: NSSResult<()>, initializer: F) -> NSSResult<()>
+    where F: FnOnce() -> NSSResult<()>
 {
     while NSSBUSY.compare_and_swap(false, true, Ordering::SeqCst) != false {};
 
     let result =
     if NSSINIT.load(Ordering::SeqCst) == false {
         unsafe { nspr::PR_Init(nspr::PRThreadType::PR_SYSTEM_THREAD, nspr::PRThreadPriority::PR_PRIORITY_NORMAL, 0) };
-        match unsafe { nss::NSS_NoDB_Init(::std::ptr::null_mut()).to_result() }
+        match initializer()
         {
             Ok(..) => {
                 NSSINIT.store(true, Ordering::SeqCst);
@@ -22,7 +49,7 @@ pub fn init() -> NSSResult<()>
         }
     }
     else {
-        Ok(())
+        initialized
     };
 
     NSSBUSY.store(false, Ordering::SeqCst);
@@ -63,4 +90,11 @@ mod test
     {
         super::init().unwrap();
     }
+
+    #[test]
+    fn init_with_db_after_init()
+    {
+        super::init().unwrap();
+        assert!(super::init_with_db("sql:/nonexistent").is_err());
+    }
 }
@@ -2,11 +2,13 @@ use std::fmt;
 
 pub type NSSResult<T> = Result<T, NSSError>;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub enum NSSError
 {
     /// An error generated by NSPR or NSS
     NSS(i32),
+    /// The input is longer than the operation accepts
+    InputTooLong { len: usize, max: usize },
 }
 
 impl fmt::Display for NSSError
@@ -25,10 +27,15 @@ impl fmt::Display for NSSError
                     None => write!(formatter, "Unknown error {}", code),
                 }
             },
+            NSSError::InputTooLong { len, max } => {
+                write!(formatter, "Input of {} bytes exceeds the maximum of {} bytes", len, max)
+            },
         }
     }
 }
 
+impl ::std::error::Error for NSSError {}
+
 impl fmt::Debug for NSSError
 {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error>
@@ -0,0 +1,299 @@
+use result::{NSSResult, NSSError};
+use ffi::nspr;
+use cert::Certificate;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use super::{TlsAcceptor, TlsConnector, TlsStream};
+
+/// An async stream and the context of the task that is polling the TLS stream on top of it
+struct Inner<S>
+{
+    stream: S,
+    context: *mut (),
+}
+
+/// What NSS reads from and writes to, which fails with `WouldBlock` while the async stream is pending
+struct Bridge<S>(*mut Inner<S>);
+
+// It only refers to the stream of the `AsyncTlsStream` that owns the socket it is below
+unsafe impl<S: Send> Send for Bridge<S> {}
+
+impl<S> Bridge<S>
+{
+    fn poll<F, T>(&mut self, f: F) -> io::Result<T>
+        where F: FnOnce(Pin<&mut S>, &mut Context) -> Poll<io::Result<T>>, S: Unpin
+    {
+        let inner = unsafe { &mut *self.0 };
+        // Closing the socket may try to write without a task
+        if inner.context.is_null() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let context = unsafe { &mut *(inner.context as *mut Context) };
+        match f(Pin::new(&mut inner.stream), context)
+        {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Read for Bridge<S>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        let mut buf = ReadBuf::new(buf);
+        try!(self.poll(|stream, context| stream.poll_read(context, &mut buf)));
+        Ok(buf.filled().len())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Write for Bridge<S>
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        self.poll(|stream, context| stream.poll_write(context, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        self.poll(|stream, context| stream.poll_flush(context))
+    }
+}
+
+fn would_block<T>(result: io::Result<T>) -> Poll<io::Result<T>>
+{
+    match result
+    {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+        result => Poll::Ready(result),
+    }
+}
+
+/// A TLS connection over an async stream, with NSS in nonblocking mode
+pub struct AsyncTlsStream<S>
+{
+    // Closed before the stream it refers to is dropped
+    tls: ManuallyDrop<TlsStream>,
+    inner: *mut Inner<S>,
+}
+
+// NSS sockets may be used from any thread, and only one at a time uses this one
+unsafe impl<S: Send> Send for AsyncTlsStream<S> {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + 'static> AsyncTlsStream<S>
+{
+    fn new<F>(stream: S, start: F) -> NSSResult<AsyncTlsStream<S>>
+        where F: FnOnce(Bridge<S>) -> NSSResult<TlsStream>
+    {
+        let inner = Box::into_raw(Box::new(Inner { stream: stream, context: ptr::null_mut() }));
+        match start(Bridge(inner))
+        {
+            Ok(tls) => Ok(AsyncTlsStream { tls: ManuallyDrop::new(tls), inner: inner }),
+            Err(e) => {
+                drop(unsafe { Box::from_raw(inner) });
+                Err(e)
+            },
+        }
+    }
+}
+
+impl<S> AsyncTlsStream<S>
+{
+    /// The TLS connection, for what it knows about its peer
+    pub fn get_ref(&self) -> &TlsStream
+    {
+        &self.tls
+    }
+
+    pub fn peer_certificate(&self) -> Option<Certificate>
+    {
+        self.tls.peer_certificate()
+    }
+
+    /// Lets NSS use the async stream with the context of the task
+    fn with_context<F, T>(&mut self, context: &mut Context, f: F) -> T
+        where F: FnOnce(&mut TlsStream) -> T
+    {
+        unsafe { (*self.inner).context = context as *mut Context as *mut () };
+        let result = f(&mut self.tls);
+        unsafe { (*self.inner).context = ptr::null_mut() };
+        result
+    }
+}
+
+impl<S> Drop for AsyncTlsStream<S>
+{
+    fn drop(&mut self)
+    {
+        unsafe
+        {
+            ManuallyDrop::drop(&mut self.tls);
+            drop(Box::from_raw(self.inner));
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for AsyncTlsStream<S>
+{
+    fn poll_read(self: Pin<&mut Self>, context: &mut Context, buf: &mut ReadBuf) -> Poll<io::Result<()>>
+    {
+        let result = self.get_mut().with_context(context, |tls| tls.read(buf.initialize_unfilled()));
+        would_block(result).map_ok(|read| buf.advance(read))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for AsyncTlsStream<S>
+{
+    fn poll_write(self: Pin<&mut Self>, context: &mut Context, buf: &[u8]) -> Poll<io::Result<usize>>
+    {
+        would_block(self.get_mut().with_context(context, |tls| tls.write(buf)))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>>
+    {
+        // Writing nothing sends what NSS could not send before
+        would_block(self.get_mut().with_context(context, |tls| tls.write(&[]).and_then(|_| tls.flush())))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, context: &mut Context) -> Poll<io::Result<()>>
+    {
+        let this = self.get_mut();
+        // Nothing can be written after the shutdown, which flushes the stream below
+        match would_block(this.with_context(context, |tls| tls.write(&[]).and_then(|_| tls.shutdown())))
+        {
+            Poll::Ready(Ok(())) => {},
+            other => return other,
+        }
+        Pin::new(unsafe { &mut (*this.inner).stream }).poll_shutdown(context)
+    }
+}
+
+/// Finishes with the TLS stream once its handshake is done
+pub struct Handshake<S>
+{
+    stream: Option<NSSResult<AsyncTlsStream<S>>>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Future for Handshake<S>
+{
+    type Output = NSSResult<AsyncTlsStream<S>>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<Self::Output>
+    {
+        let this = self.get_mut();
+        let mut stream = match this.stream.take().expect("Handshake polled after it finished")
+        {
+            Ok(stream) => stream,
+            Err(e) => return Poll::Ready(Err(e)),
+        };
+        match stream.with_context(context, |tls| tls.handshake())
+        {
+            Ok(()) => Poll::Ready(Ok(stream)),
+            Err(NSSError::NSS(nspr::PR_WOULD_BLOCK_ERROR)) => {
+                this.stream = Some(Ok(stream));
+                Poll::Pending
+            },
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+impl TlsConnector
+{
+    /// Performs the handshake over an async stream, like `connect`
+    pub fn connect_async<S>(&self, hostname: &str, stream: S) -> Handshake<S>
+        where S: AsyncRead + AsyncWrite + Unpin + Send + 'static
+    {
+        Handshake { stream: Some(AsyncTlsStream::new(stream, |bridge| self.start(hostname, bridge, true))) }
+    }
+}
+
+impl TlsAcceptor
+{
+    /// Performs the handshake over an async stream accepted from a client, like `accept`
+    pub fn accept_async<S>(&self, stream: S) -> Handshake<S>
+        where S: AsyncRead + AsyncWrite + Unpin + Send + 'static
+    {
+        Handshake { stream: Some(AsyncTlsStream::new(stream, |bridge| self.start(bridge, true))) }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use cert::Certificate;
+    use cert::verify::{Usage, Verifier};
+    use crypto::pkey::PrivateKey;
+    use ssl::{TlsAcceptor, TlsConnector};
+    use ssl::test::pki;
+    use std::net;
+    use std::thread;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::runtime::{Builder, Runtime};
+
+    fn connected(runtime: &Runtime) -> (TcpStream, TcpStream)
+    {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let server = listener.accept().unwrap().0;
+        client.set_nonblocking(true).unwrap();
+        server.set_nonblocking(true).unwrap();
+        let _guard = runtime.enter();
+        (TcpStream::from_std(client).unwrap(), TcpStream::from_std(server).unwrap())
+    }
+
+    #[test]
+    fn connect_and_accept()
+    {
+        let pki = pki();
+        let runtime = Builder::new_multi_thread().worker_threads(2).enable_io().build().unwrap();
+        let (client, server) = connected(&runtime);
+        let acceptor = TlsAcceptor::new(&[Certificate::from_der(&pki.server).unwrap()], &PrivateKey::load(&pki.server_key).unwrap()).unwrap();
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root));
+
+        let server = runtime.spawn(acceptor.accept_async(server));
+        let mut client = runtime.block_on(connector.connect_async("localhost", client)).unwrap();
+        let mut server = runtime.block_on(server).unwrap().unwrap();
+        assert_eq!(Some("localhost".to_string()), client.peer_certificate().unwrap().subject().common_name());
+
+        // More than the socket buffers hold, so both sides have to wait for the other
+        let handle = runtime.handle().clone();
+        let reader = thread::spawn(move ||
+        {
+            let mut read = Vec::new();
+            handle.block_on(server.read_to_end(&mut read)).unwrap();
+            (read, server)
+        });
+        let data = vec![7; 10000000];
+        runtime.block_on(client.write_all(&data)).unwrap();
+        runtime.block_on(client.shutdown()).unwrap();
+        let (read, mut server) = reader.join().unwrap();
+        assert!(read == data);
+
+        runtime.block_on(server.write_all(b"bye")).unwrap();
+        runtime.block_on(server.flush()).unwrap();
+        let mut buf = [0; 3];
+        runtime.block_on(client.read_exact(&mut buf)).unwrap();
+        assert_eq!(b"bye", &buf);
+    }
+
+    #[test]
+    fn untrusted()
+    {
+        let pki = pki();
+        let runtime = Builder::new_current_thread().enable_io().build().unwrap();
+        let (client, server) = connected(&runtime);
+        let acceptor = TlsAcceptor::new(&[Certificate::from_der(&pki.server).unwrap()], &PrivateKey::load(&pki.server_key).unwrap()).unwrap();
+
+        let server = runtime.spawn(acceptor.accept_async(server));
+        assert!(runtime.block_on(TlsConnector::new().connect_async("localhost", client)).is_err());
+        assert!(runtime.block_on(server).unwrap().is_err());
+    }
+}
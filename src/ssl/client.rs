@@ -0,0 +1,301 @@
+use result::NSSResult;
+use ffi::{nspr, ssl};
+use ffi::nspr::PRBool;
+use cert::Certificate;
+use cert::verify::{Usage, Verifier};
+use crypto::pkey::{HashAlgorithm, PrivateKey};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use libc::c_void;
+use super::{Callbacks, CertificateCompression, Datagram, DtlsStream, EarlyData, ExternalPsk, TlsPolicy, TlsStream};
+use super::dtls::Datagrams;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn next_id() -> usize
+{
+    NEXT_ID.fetch_add(1, Ordering::SeqCst)
+}
+
+/// Connects to TLS servers, checking their certificate and host name
+#[derive(Clone)]
+pub struct TlsConnector
+{
+    verifier: Verifier,
+    identity: Option<(Certificate, PrivateKey)>,
+    alpn: Vec<Vec<u8>>,
+    session_tickets: bool,
+    key_shares: u32,
+    early_data: Option<Vec<u8>>,
+    policy: Option<TlsPolicy>,
+    psk: Option<ExternalPsk>,
+    compression: Vec<ssl::SSLCertificateCompressionAlgorithm>,
+    #[cfg(feature = "keylog")]
+    secrets: Option<super::keylog::SecretCallback>,
+    /// Sessions are only resumed by connectors with the same id, which changes with the configuration
+    id: usize,
+}
+
+impl TlsConnector
+{
+    /// Trusts the certificate authorities in the open databases
+    pub fn new() -> TlsConnector
+    {
+        TlsConnector {
+            verifier: Verifier::new(Usage::TLSServer),
+            identity: None,
+            alpn: Vec::new(),
+            session_tickets: false,
+            key_shares: 0,
+            early_data: None,
+            policy: None,
+            psk: None,
+            compression: Vec::new(),
+            #[cfg(feature = "keylog")]
+            secrets: None,
+            id: next_id(),
+        }
+    }
+
+    /// Verifies the server certificate with the verifier, which should be for `Usage::TLSServer`
+    pub fn verifier(mut self, verifier: Verifier) -> TlsConnector
+    {
+        self.verifier = verifier;
+        self.id = next_id();
+        self
+    }
+
+    /// The certificate and key to authenticate with, when the server asks for a certificate
+    pub fn client_certificate(mut self, certificate: &Certificate, key: &PrivateKey) -> TlsConnector
+    {
+        self.identity = Some((certificate.clone(), key.clone()));
+        self.id = next_id();
+        self
+    }
+
+    /// Offers the protocols with ALPN, in the order of preference
+    pub fn alpn_protocols(mut self, protocols: &[&[u8]]) -> TlsConnector
+    {
+        self.alpn = protocols.iter().map(|p| p.to_vec()).collect();
+        self
+    }
+
+    /// Resumes sessions with tickets from the server, besides session IDs
+    pub fn session_tickets(mut self, enable: bool) -> TlsConnector
+    {
+        self.session_tickets = enable;
+        self
+    }
+
+    /// Sends key shares for more groups with TLS 1.3, so a server that prefers one of them needs no extra round trip
+    pub fn additional_key_shares(mut self, count: u32) -> TlsConnector
+    {
+        self.key_shares = count;
+        self
+    }
+
+    /// Sends the data first on every connection, as TLS 1.3 early data when a resumed session allows it
+    ///
+    /// The server may reject the early data, and then it is sent again after the handshake.
+    /// Early data can be replayed by an attacker, so it should not change anything on the server.
+    pub fn early_data(mut self, data: &[u8]) -> TlsConnector
+    {
+        self.early_data = Some(data.to_vec());
+        self
+    }
+
+    /// Limits the versions and algorithms to negotiate, instead of the default policy
+    pub fn policy(mut self, policy: TlsPolicy) -> TlsConnector
+    {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Authenticates with the TLS 1.3 key, falling back to the certificate of the server when it does not know it
+    pub fn external_psk(mut self, psk: &ExternalPsk) -> TlsConnector
+    {
+        self.psk = Some(psk.clone());
+        self.id = next_id();
+        self
+    }
+
+    /// Lets TLS 1.3 servers compress their certificate chain with the algorithm
+    pub fn certificate_compression<C: CertificateCompression>(mut self) -> TlsConnector
+    {
+        self.compression.push(super::compression::algorithm::<C>());
+        self
+    }
+
+    /// Called with the TLS 1.3 traffic secrets of every connection, such as a key log `writer`
+    #[cfg(feature = "keylog")]
+    pub fn secret_callback<F>(mut self, callback: F) -> TlsConnector
+        where F: Fn(&super::TrafficSecret) + Send + Sync + 'static
+    {
+        self.secrets = Some(::std::sync::Arc::new(callback));
+        self
+    }
+
+    /// Performs the handshake over the stream, sending the host name with SNI and matching it with the certificate
+    ///
+    /// The stream may be any transport, such as a `TcpStream` or a `UnixStream`.
+    pub fn connect<S>(&self, hostname: &str, stream: S) -> NSSResult<TlsStream>
+        where S: Read + Write + Send + 'static
+    {
+        let mut stream = try!(self.start(hostname, stream, false));
+        try!(stream.handshake());
+        Ok(stream)
+    }
+
+    /// Sets up a DTLS connection over the transport, whose handshake the caller drives with `DtlsStream::handshake`
+    ///
+    /// The host name is checked like with `connect`. Early data is not sent over DTLS.
+    pub fn connect_dtls<D>(&self, hostname: &str, transport: D) -> NSSResult<DtlsStream>
+        where D: Datagram + Send + 'static
+    {
+        let fd = try!(super::import(Datagrams(transport), true, true));
+        self.configure(hostname, fd).map(DtlsStream::new)
+    }
+
+    /// Sets up a connection for its handshake
+    pub(crate) fn start<S>(&self, hostname: &str, stream: S, nonblocking: bool) -> NSSResult<TlsStream>
+        where S: Read + Write + Send + 'static
+    {
+        let fd = try!(super::import(stream, nonblocking, false));
+        self.configure(hostname, fd)
+    }
+
+    fn configure(&self, hostname: &str, mut fd: nspr::FileDesc) -> NSSResult<TlsStream>
+    {
+        let url = try!(::cert::db::c_string(hostname));
+        // Sessions are cached per peer, which includes the client certificate so no session is resumed with another
+        let fingerprint: String = match self.identity
+        {
+            Some((ref certificate, _)) => try!(certificate.fingerprint(HashAlgorithm::SHA256)).iter().map(|b| format!("{:02x}", b)).collect(),
+            None => String::new(),
+        };
+        // All streams have the same address, so the connector is part of the peer as well
+        let peer_id = try!(::cert::db::c_string(&format!("{}:{}", self.id, fingerprint)));
+        let callbacks = Box::new(Callbacks {
+            verifier: self.verifier.clone(),
+            hostname: Some(hostname.to_string()),
+            check: None,
+            identity: self.identity.clone(),
+            #[cfg(feature = "keylog")]
+            secrets: self.secrets.clone(),
+        });
+        let arg = &*callbacks as *const Callbacks as *mut c_void;
+        unsafe
+        {
+            try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_SECURITY, 1).to_result());
+            try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_HANDSHAKE_AS_CLIENT, 1).to_result());
+            try!(ssl::SSL_SetURL(fd.get_mut(), url.as_ptr()).to_result());
+            try!(ssl::SSL_SetSockPeerID(fd.get_mut(), peer_id.as_ptr()).to_result());
+            try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_ENABLE_SESSION_TICKETS, self.session_tickets as i32).to_result());
+            try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_ENABLE_0RTT_DATA, self.early_data.is_some() as i32).to_result());
+            try!(ssl::SSL_SendAdditionalKeyShares(fd.get_mut(), self.key_shares).to_result());
+            try!(TlsPolicy::apply(self.policy.as_ref(), &mut fd));
+            try!(ssl::SSL_AuthCertificateHook(fd.get_mut(), super::auth_certificate, arg).to_result());
+            try!(ssl::SSL_GetClientAuthDataHook(fd.get_mut(), super::client_auth_data, arg).to_result());
+            try!(ssl::SSL_ResetHandshake(fd.get_mut(), PRBool::False).to_result());
+        }
+        try!(super::set_alpn(&mut fd, &self.alpn));
+        if let Some(ref psk) = self.psk {
+            try!(psk.add(&mut fd));
+        }
+        try!(super::compression::set_algorithms(&mut fd, &self.compression));
+        #[cfg(feature = "keylog")]
+        try!(super::keylog::install(&mut fd, &callbacks));
+        Ok(TlsStream {
+            fd: fd,
+            _callbacks: callbacks,
+            server: false,
+            early_data: self.early_data.clone().map(EarlyData::new),
+            received: Vec::new(),
+        })
+    }
+}
+
+impl Default for TlsConnector
+{
+    fn default() -> TlsConnector
+    {
+        TlsConnector::new()
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use ffi::ssl;
+    use cert::Certificate;
+    use cert::verify::{Usage, Verifier};
+    use result::NSSError;
+    use ssl::TlsAcceptor;
+    use ssl::test::{pki, serve};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+
+    #[test]
+    fn connect()
+    {
+        let pki = pki();
+        let port = serve(&pki, 3, |acceptor, stream|
+        {
+            if let Ok(mut stream) = acceptor.accept(stream) {
+                let mut line = String::new();
+                BufReader::new(&mut stream).read_line(&mut line).unwrap();
+                stream.write_all(line.as_bytes()).unwrap();
+            }
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root));
+
+        let mut stream = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+        assert_eq!(Some("localhost".to_string()), stream.peer_certificate().unwrap().subject().common_name());
+        stream.write_all(b"hello\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line).unwrap();
+        assert_eq!("hello\n", line);
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+
+        let wrong_name = connector.connect("example.com", TcpStream::connect(("127.0.0.1", port)).unwrap());
+        assert_eq!(Some(NSSError::NSS(ssl::SSL_ERROR_BAD_CERT_DOMAIN)), wrong_name.err());
+
+        // The root is not in any database
+        let untrusted = TlsConnector::new().connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap());
+        assert!(untrusted.is_err());
+    }
+
+    #[test]
+    fn unix_stream()
+    {
+        let pki = pki();
+        let (client, server) = UnixStream::pair().unwrap();
+        let (certificate, key) = (pki.server.clone(), pki.server_key.clone());
+        let server = thread::spawn(move ||
+        {
+            let certificate = Certificate::from_der(&certificate).unwrap();
+            let acceptor = TlsAcceptor::new(&[certificate], &PrivateKey::load(&key).unwrap()).unwrap();
+            let mut stream = acceptor.accept(server).unwrap();
+            let mut buf = Vec::new();
+            stream.read_to_end(&mut buf).unwrap();
+            stream.write_all(&buf).unwrap();
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root));
+
+        let mut stream = connector.connect("localhost", client).unwrap();
+        stream.write_all(b"hello").unwrap();
+        stream.flush().unwrap();
+        stream.shutdown().unwrap();
+        let mut echo = Vec::new();
+        stream.read_to_end(&mut echo).unwrap();
+        assert_eq!(b"hello", &echo[..]);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use result::{NSSResult, NSSError};
+use ffi::{nspr, sec, ssl};
+use ffi::sec::SECStatus;
+use std::ptr;
+
+/// An algorithm to compress certificate chains with, as in RFC 8879
+///
+/// NSS only supports this from version 3.93, and earlier versions fail with
+/// `SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API` on connections that configure it.
+pub trait CertificateCompression
+{
+    /// The IANA number of the algorithm, such as 1 for zlib
+    const ID: u16;
+    /// The name of the algorithm, which ends with a nul
+    const NAME: &'static [u8];
+
+    fn compress(input: &[u8]) -> NSSResult<Vec<u8>>;
+
+    /// Decompresses to exactly `len` bytes, which the peer announced
+    fn decompress(input: &[u8], len: usize) -> NSSResult<Vec<u8>>;
+}
+
+fn status(result: NSSResult<()>) -> SECStatus
+{
+    match result
+    {
+        Ok(()) => SECStatus::Success,
+        Err(error) => {
+            let code = match error
+            {
+                NSSError::NSS(code) => code,
+                _ => sec::SEC_ERROR_INVALID_ARGS,
+            };
+            unsafe { nspr::PR_SetError(code, 0) };
+            SECStatus::Failure
+        },
+    }
+}
+
+unsafe extern "C" fn encode<C: CertificateCompression>(input: *const sec::SECItemFFI, output: *mut sec::SECItemFFI) -> SECStatus
+{
+    status(C::compress((*input).as_slice()).and_then(|compressed| (*output).alloc_copy(&compressed)))
+}
+
+unsafe extern "C" fn decode<C: CertificateCompression>(input: *const sec::SECItemFFI, output: *mut u8, output_len: usize,
+                                                       used_len: *mut usize) -> SECStatus
+{
+    status(C::decompress((*input).as_slice(), output_len).and_then(|decompressed| {
+        if decompressed.len() != output_len {
+            return Err(NSSError::NSS(sec::SEC_ERROR_BAD_DATA));
+        }
+        ptr::copy_nonoverlapping(decompressed.as_ptr(), output, output_len);
+        *used_len = output_len;
+        Ok(())
+    }))
+}
+
+pub(crate) fn algorithm<C: CertificateCompression>() -> ssl::SSLCertificateCompressionAlgorithm
+{
+    assert_eq!(Some(&0), C::NAME.last());
+    ssl::SSLCertificateCompressionAlgorithm {
+        id: C::ID,
+        name: C::NAME.as_ptr() as *const _,
+        encode: Some(encode::<C>),
+        decode: Some(decode::<C>),
+    }
+}
+
+pub(crate) fn set_algorithms(fd: &mut nspr::FileDesc, algorithms: &[ssl::SSLCertificateCompressionAlgorithm]) -> NSSResult<()>
+{
+    if algorithms.is_empty() {
+        return Ok(());
+    }
+    let set: ssl::SSLSetCertificateCompressionAlgorithm =
+        try!(unsafe { ssl::experimental_api(b"SSL_SetCertificateCompressionAlgorithm\0") });
+    for &algorithm in algorithms
+    {
+        try!(unsafe { set(fd.get_mut(), algorithm).to_result() });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::Certificate;
+    use cert::verify::{Usage, Verifier};
+    use ffi::sec::SECItem;
+    use crypto::pkey::PrivateKey;
+    use ssl::{TlsAcceptor, TlsConnector};
+    use ssl::test::{pki, serve};
+    use std::ffi::CStr;
+    use std::io::{Cursor, Read, Write};
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COMPRESSED: AtomicUsize = AtomicUsize::new(0);
+
+    /// Stores the certificates with their bytes reversed
+    struct Reverse;
+
+    impl CertificateCompression for Reverse
+    {
+        const ID: u16 = 0xfe00;
+        const NAME: &'static [u8] = b"reverse\0";
+
+        fn compress(input: &[u8]) -> NSSResult<Vec<u8>>
+        {
+            COMPRESSED.fetch_add(1, Ordering::SeqCst);
+            Ok(input.iter().rev().cloned().collect())
+        }
+
+        fn decompress(input: &[u8], _len: usize) -> NSSResult<Vec<u8>>
+        {
+            Ok(input.iter().rev().cloned().collect())
+        }
+    }
+
+    /// Stores the certificates with their bits inverted, and fails on empty input
+    struct Invert;
+
+    impl CertificateCompression for Invert
+    {
+        const ID: u16 = 0xfe01;
+        const NAME: &'static [u8] = b"invert\0";
+
+        fn compress(input: &[u8]) -> NSSResult<Vec<u8>>
+        {
+            if input.is_empty() {
+                return Err(NSSError::NSS(sec::SEC_ERROR_BAD_DATA));
+            }
+            Ok(input.iter().map(|b| !b).collect())
+        }
+
+        fn decompress(input: &[u8], _len: usize) -> NSSResult<Vec<u8>>
+        {
+            Ok(input.iter().map(|b| !b).collect())
+        }
+    }
+
+    #[test]
+    fn callbacks()
+    {
+        ::nss::init().unwrap();
+        let algorithm = algorithm::<Invert>();
+        assert_eq!(0xfe01, algorithm.id);
+        assert_eq!(b"invert", unsafe { CStr::from_ptr(algorithm.name) }.to_bytes());
+        let (encode, decode) = (algorithm.encode.unwrap(), algorithm.decode.unwrap());
+
+        let input = SECItem::from_buf(b"certificate");
+        let mut output = SECItem::empty();
+        unsafe { encode(input.get(), output.get_mut()).to_result().unwrap() };
+        let compressed = output.take_buf();
+        assert_eq!(b"certificate".iter().map(|b| !b).collect::<Vec<u8>>(), compressed);
+
+        let input = SECItem::from_buf(&compressed);
+        let mut decompressed = [0; 11];
+        let mut used = 0;
+        unsafe { decode(input.get(), decompressed.as_mut_ptr(), decompressed.len(), &mut used).to_result().unwrap() };
+        assert_eq!(b"certificate", &decompressed);
+        assert_eq!(11, used);
+
+        // The peer announced another length
+        let mut short = [0; 5];
+        let result = unsafe { decode(input.get(), short.as_mut_ptr(), short.len(), &mut used).to_result() };
+        assert_eq!(Err(NSSError::NSS(sec::SEC_ERROR_BAD_DATA)), result);
+
+        // Errors of the algorithm are passed on to NSS
+        let empty = SECItem::from_buf(b"");
+        let mut output = SECItem::empty();
+        let result = unsafe { encode(empty.get(), output.get_mut()).to_result() };
+        assert_eq!(Err(NSSError::NSS(sec::SEC_ERROR_BAD_DATA)), result);
+    }
+
+    #[test]
+    fn compression()
+    {
+        let pki = pki();
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root))
+                            .certificate_compression::<Reverse>();
+
+        // NSS only supports this from version 3.93, before which configuring a connection fails before any I/O
+        let supported: NSSResult<ssl::SSLSetCertificateCompressionAlgorithm> =
+            unsafe { ssl::experimental_api(b"SSL_SetCertificateCompressionAlgorithm\0") };
+        if supported.is_err() {
+            let unsupported = Err(NSSError::NSS(ssl::SSL_ERROR_UNSUPPORTED_EXPERIMENTAL_API));
+            assert_eq!(unsupported, connector.connect("localhost", Cursor::new(Vec::new())).map(|_| ()));
+            let acceptor = TlsAcceptor::new(&[Certificate::from_der(&pki.server).unwrap()],
+                                            &PrivateKey::load(&pki.server_key).unwrap()).unwrap();
+            assert_eq!(unsupported, acceptor.certificate_compression::<Reverse>().accept(Cursor::new(Vec::new())).map(|_| ()));
+            assert_eq!(0, COMPRESSED.load(Ordering::SeqCst));
+            return;
+        }
+
+        let port = serve(&pki, 1, |acceptor, stream|
+        {
+            let mut stream = acceptor.clone().certificate_compression::<Reverse>().accept(stream).unwrap();
+            stream.write_all(b"x").unwrap();
+        });
+
+        let mut stream = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+        stream.read_exact(&mut [0]).unwrap();
+        assert_eq!(1, COMPRESSED.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,263 @@
+use result::NSSResult;
+use ffi::{nspr, ssl};
+use std::io::{self, Read, Write};
+use std::net::UdpSocket;
+use std::ops::Deref;
+use std::time::Duration;
+use super::TlsStream;
+
+/// A transport of datagrams to a single peer, such as a connected `UdpSocket`
+///
+/// It should not block, and fail with `WouldBlock` instead, so the handshake can return to the
+/// caller to wait for the next datagram or the retransmission timer.
+pub trait Datagram
+{
+    /// Sends the whole buffer as one datagram
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Receives one datagram, of which what does not fit in the buffer is lost
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+impl Datagram for UdpSocket
+{
+    fn send(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        UdpSocket::send(self, buf)
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        UdpSocket::recv(self, buf)
+    }
+}
+
+/// A stream of which every read and write is a datagram, as DTLS expects from the layer below it
+pub(crate) struct Datagrams<D>(pub(crate) D);
+
+impl<D: Datagram> Read for Datagrams<D>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        self.0.recv(buf)
+    }
+}
+
+impl<D: Datagram> Write for Datagrams<D>
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        self.0.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        Ok(())
+    }
+}
+
+/// A DTLS connection, which is only usable once `handshake` succeeds
+///
+/// What the handshake negotiated is available like for TLS, where DTLS 1.2 is `TlsVersion::TLS12`.
+pub struct DtlsStream
+{
+    inner: TlsStream,
+}
+
+impl DtlsStream
+{
+    pub(super) fn new(mut stream: TlsStream) -> DtlsStream
+    {
+        stream.early_data = None;
+        DtlsStream { inner: stream }
+    }
+
+    /// Continues the handshake with the datagrams that arrived, or sends the last flight again once its timer expired
+    ///
+    /// It fails with `PR_WOULD_BLOCK_ERROR` until the handshake is done, after which the caller
+    /// should call it again when a datagram arrives or after `handshake_timeout`.
+    pub fn handshake(&mut self) -> NSSResult<()>
+    {
+        self.inner.force_handshake()
+    }
+
+    /// How long until the last flight of the handshake should be sent again, if it is waiting for one
+    pub fn handshake_timeout(&self) -> Option<Duration>
+    {
+        let mut ticks = 0;
+        match unsafe { ssl::DTLS_GetHandshakeTimeout(self.inner.fd.get() as *mut _, &mut ticks).to_result() }
+        {
+            Ok(()) => Some(Duration::from_micros(unsafe { nspr::PR_IntervalToMicroseconds(ticks) } as u64)),
+            Err(_) => None,
+        }
+    }
+
+    /// Sends the message in a record of its own, which should fit in a datagram
+    ///
+    /// Like UDP, the message may be lost or arrive more than once.
+    pub fn send(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        self.inner.write_raw(buf).map_err(super::io_error)
+    }
+
+    /// Receives the next message, of which what does not fit in the buffer is lost
+    ///
+    /// This also handles the datagrams of the handshake that the peer may still send.
+    pub fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        self.inner.read_raw(buf).map_err(super::io_error)
+    }
+}
+
+impl Deref for DtlsStream
+{
+    type Target = TlsStream;
+
+    fn deref(&self) -> &TlsStream
+    {
+        &self.inner
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::Certificate;
+    use cert::verify::{Usage, Verifier};
+    use crypto::pkey::PrivateKey;
+    use result::NSSError;
+    use ssl::{TlsAcceptor, TlsConnector, TlsPolicy, TlsVersion};
+    use ssl::test::pki;
+    use std::thread;
+
+    /// A pair of nonblocking UDP sockets on the loopback interface, connected to each other
+    fn socket_pair() -> (UdpSocket, UdpSocket)
+    {
+        let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        a.connect(b.local_addr().unwrap()).unwrap();
+        b.connect(a.local_addr().unwrap()).unwrap();
+        a.set_nonblocking(true).unwrap();
+        b.set_nonblocking(true).unwrap();
+        (a, b)
+    }
+
+    /// Loses the first datagrams that it sends
+    struct Lossy
+    {
+        socket: UdpSocket,
+        lose: usize,
+    }
+
+    impl Datagram for Lossy
+    {
+        fn send(&mut self, buf: &[u8]) -> io::Result<usize>
+        {
+            if self.lose > 0 {
+                self.lose -= 1;
+                return Ok(buf.len());
+            }
+            self.socket.send(buf)
+        }
+
+        fn recv(&mut self, buf: &mut [u8]) -> io::Result<usize>
+        {
+            self.socket.recv(buf)
+        }
+    }
+
+    fn wait(timeout: Option<Duration>)
+    {
+        let poll = Duration::from_millis(5);
+        thread::sleep(timeout.map(|timeout| ::std::cmp::min(timeout, poll)).unwrap_or(poll));
+    }
+
+    /// Drives the handshake as a caller would, returning whether the timer was ever set
+    fn handshake(stream: &mut DtlsStream) -> bool
+    {
+        let mut timer = false;
+        loop
+        {
+            match stream.handshake()
+            {
+                Ok(()) => return timer,
+                Err(NSSError::NSS(nspr::PR_WOULD_BLOCK_ERROR)) => {
+                    let timeout = stream.handshake_timeout();
+                    timer |= timeout.is_some();
+                    wait(timeout);
+                },
+                Err(e) => panic!("handshake failed: {:?}", e),
+            }
+        }
+    }
+
+    fn recv(stream: &mut DtlsStream) -> Vec<u8>
+    {
+        let mut buf = [0; 1500];
+        loop
+        {
+            match stream.recv(&mut buf)
+            {
+                Ok(len) => return buf[..len].to_vec(),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => wait(None),
+                Err(e) => panic!("recv failed: {:?}", e),
+            }
+        }
+    }
+
+    fn echo_server<D>(pki: &::ssl::test::Pki, transport: D, policy: TlsPolicy) -> thread::JoinHandle<()>
+        where D: Datagram + Send + 'static
+    {
+        let (certificate, key) = (pki.server.clone(), pki.server_key.clone());
+        thread::spawn(move ||
+        {
+            let certificate = Certificate::from_der(&certificate).unwrap();
+            let acceptor = TlsAcceptor::new(&[certificate], &PrivateKey::load(&key).unwrap()).unwrap().policy(policy);
+            let mut stream = acceptor.accept_dtls(transport).unwrap();
+            handshake(&mut stream);
+            let message = recv(&mut stream);
+            stream.send(&message).unwrap();
+        })
+    }
+
+    #[test]
+    fn dtls()
+    {
+        let pki = pki();
+        let root = Certificate::from_der(&pki.root).unwrap();
+        for &version in &[TlsVersion::TLS12, TlsVersion::TLS13]
+        {
+            let (client, server) = socket_pair();
+            let policy = TlsPolicy::new().versions(TlsVersion::TLS12, version);
+            let server = echo_server(&pki, server, policy.clone());
+            let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root)).policy(policy);
+
+            let mut stream = connector.connect_dtls("localhost", client).unwrap();
+            handshake(&mut stream);
+            assert_eq!(version, stream.info().unwrap().version);
+            assert_eq!(Some("localhost".to_string()), stream.peer_certificate().unwrap().subject().common_name());
+            stream.send(b"telemetry").unwrap();
+            assert_eq!(b"telemetry", &recv(&mut stream)[..]);
+            server.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn retransmission()
+    {
+        let pki = pki();
+        let (client, server) = socket_pair();
+        let server = echo_server(&pki, server, TlsPolicy::new());
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root));
+
+        // The first ClientHello is lost, so the handshake only succeeds once the timer sends it again
+        let mut stream = connector.connect_dtls("localhost", Lossy { socket: client, lose: 1 }).unwrap();
+        assert!(handshake(&mut stream));
+        assert!(stream.handshake_timeout().is_none());
+        stream.send(b"telemetry").unwrap();
+        assert_eq!(b"telemetry", &recv(&mut stream)[..]);
+        server.join().unwrap();
+    }
+}
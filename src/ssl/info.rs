@@ -0,0 +1,184 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, ssl};
+use ffi::nspr::PRBool;
+use cert::Certificate;
+use std::ffi::CStr;
+use std::{mem, ptr};
+use libc::c_char;
+use super::TlsStream;
+
+/// A version of the TLS protocol, which DTLS versions map to as NSS does
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum TlsVersion
+{
+    TLS10,
+    /// Also DTLS 1.0
+    TLS11,
+    /// Also DTLS 1.2
+    TLS12,
+    /// Also DTLS 1.3
+    TLS13,
+}
+
+impl TlsVersion
+{
+    pub(crate) fn to_raw(self) -> u16
+    {
+        match self
+        {
+            TlsVersion::TLS10 => 0x0301,
+            TlsVersion::TLS11 => 0x0302,
+            TlsVersion::TLS12 => 0x0303,
+            TlsVersion::TLS13 => 0x0304,
+        }
+    }
+
+    pub(crate) fn from_raw(version: u16) -> NSSResult<TlsVersion>
+    {
+        match version
+        {
+            0x0301 => Ok(TlsVersion::TLS10),
+            0x0302 => Ok(TlsVersion::TLS11),
+            0x0303 => Ok(TlsVersion::TLS12),
+            0x0304 => Ok(TlsVersion::TLS13),
+            _ => Err(NSSError::NSS(ssl::SSL_ERROR_UNSUPPORTED_VERSION)),
+        }
+    }
+}
+
+/// What the handshake of a connection negotiated
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChannelInfo
+{
+    pub version: TlsVersion,
+    /// The IANA number of the cipher suite
+    pub cipher_suite: u16,
+    /// Such as `TLS_AES_128_GCM_SHA256`
+    pub cipher_suite_name: String,
+    /// Such as `ECDHE`
+    pub key_exchange: String,
+    /// The IANA number of the group of the key exchange, or 0 without one
+    pub key_exchange_group: u16,
+    pub key_exchange_bits: u32,
+    /// The size of the key that authenticated the server
+    pub authentication_bits: u32,
+    pub cipher_key_bits: u16,
+    pub resumed: bool,
+    pub early_data_accepted: bool,
+    pub extended_master_secret: bool,
+}
+
+fn to_string(name: *const c_char) -> String
+{
+    match name.is_null()
+    {
+        true => String::new(),
+        false => unsafe { CStr::from_ptr(name).to_string_lossy().into_owned() },
+    }
+}
+
+fn to_bool(value: PRBool) -> bool
+{
+    match value { PRBool::True => true, PRBool::False => false }
+}
+
+impl TlsStream
+{
+    /// What the handshake negotiated
+    pub fn info(&self) -> NSSResult<ChannelInfo>
+    {
+        let info = try!(self.channel_info());
+        let mut suite: ssl::SSLCipherSuiteInfo = unsafe { mem::zeroed() };
+        try!(unsafe { ssl::SSL_GetCipherSuiteInfo(info.cipher_suite, &mut suite, mem::size_of_val(&suite) as u32).to_result() });
+        Ok(ChannelInfo {
+            version: try!(TlsVersion::from_raw(info.protocol_version)),
+            cipher_suite: info.cipher_suite,
+            cipher_suite_name: to_string(suite.cipher_suite_name),
+            key_exchange: to_string(suite.kea_type_name),
+            key_exchange_group: info.kea_group as u16,
+            key_exchange_bits: info.kea_key_bits,
+            authentication_bits: info.auth_key_bits,
+            cipher_key_bits: suite.effective_key_bits,
+            resumed: to_bool(info.resumed),
+            early_data_accepted: to_bool(info.early_data_accepted),
+            extended_master_secret: to_bool(info.extended_master_secret_used),
+        })
+    }
+
+    /// The certificate chain that the peer sent, starting with its own certificate
+    ///
+    /// The chain is as sent, and only its first certificate is what the verifier checked.
+    pub fn peer_certificate_chain(&self) -> Vec<Certificate>
+    {
+        let list = unsafe { ssl::SSL_PeerCertificateChain(self.fd.get() as *mut _) };
+        match cert::CertList::wrap(list)
+        {
+            Ok(list) => ::cert::from_cert_list(&list).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Derives keying material from the secrets of the connection, as RFC 5705 and RFC 8446 describe
+    ///
+    /// Both peers derive the same material for the same label and context, which binds it to the
+    /// connection. TLS 1.3 does not tell a missing context from an empty one.
+    pub fn export_keying_material(&self, label: &str, context: Option<&[u8]>, len: usize) -> NSSResult<Vec<u8>>
+    {
+        let mut out = vec![0; len];
+        let (has_context, context) = match context
+        {
+            Some(context) => (PRBool::True, context),
+            None => (PRBool::False, &[][..]),
+        };
+        let context_ptr = if context.is_empty() { ptr::null() } else { context.as_ptr() };
+        try!(unsafe { ssl::SSL_ExportKeyingMaterial(self.fd.get() as *mut _, label.as_ptr() as *const c_char, label.len() as u32,
+                                                    has_context, context_ptr, context.len() as u32,
+                                                    out.as_mut_ptr(), out.len() as u32).to_result() });
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::Certificate;
+    use cert::verify::{Usage, Verifier};
+    use ssl::TlsConnector;
+    use ssl::test::{pki, serve};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    #[test]
+    fn channel_info()
+    {
+        let pki = pki();
+        let port = serve(&pki, 1, |acceptor, stream|
+        {
+            let mut stream = acceptor.accept(stream).unwrap();
+            let info = stream.info().unwrap();
+            assert_eq!(TlsVersion::TLS13, info.version);
+            assert!(!info.resumed);
+            assert!(stream.peer_certificate_chain().is_empty());
+            let material = stream.export_keying_material("EXPORTER-test", Some(b"context"), 32).unwrap();
+            stream.write_all(&material).unwrap();
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root));
+        let mut stream = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+
+        let info = stream.info().unwrap();
+        assert_eq!(TlsVersion::TLS13, info.version);
+        assert!(info.cipher_suite_name.starts_with("TLS_"));
+        assert!(info.key_exchange_group != 0);
+        assert!(info.cipher_key_bits >= 128);
+        let chain = stream.peer_certificate_chain();
+        assert_eq!(Some("localhost".to_string()), chain[0].subject().common_name());
+
+        let material = stream.export_keying_material("EXPORTER-test", Some(b"context"), 32).unwrap();
+        let mut peer_material = vec![0; 32];
+        stream.read_exact(&mut peer_material).unwrap();
+        assert_eq!(material, peer_material);
+        assert!(material != stream.export_keying_material("EXPORTER-other", Some(b"context"), 32).unwrap());
+    }
+}
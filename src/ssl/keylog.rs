@@ -0,0 +1,175 @@
+use result::NSSResult;
+use ffi::{nspr, pk11, ssl};
+use crypto::symm::Key;
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use libc::{c_int, c_void};
+use super::Callbacks;
+
+/// Called with every secret that a TLS 1.3 connection installs
+pub(crate) type SecretCallback = Arc<dyn Fn(&TrafficSecret) + Send + Sync>;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SecretDirection
+{
+    Read,
+    Write,
+}
+
+/// A TLS 1.3 traffic secret, which decrypts what is sent in its direction
+pub struct TrafficSecret
+{
+    /// 1 for early data, 2 for the handshake and from 3 on for application data, as in DTLS 1.3
+    pub epoch: u16,
+    pub direction: SecretDirection,
+    /// Whether it is a secret of the client, which it writes with and the server reads with
+    pub client: bool,
+    pub secret: Vec<u8>,
+    /// The random of the ClientHello, which identifies the connection in a key log
+    pub client_random: Option<[u8; 32]>,
+}
+
+impl TrafficSecret
+{
+    /// The line for the secret in the key log format of NSS, which Wireshark reads
+    pub fn keylog_line(&self) -> Option<String>
+    {
+        let side = if self.client { "CLIENT" } else { "SERVER" };
+        let label = match self.epoch
+        {
+            1 if self.client => "CLIENT_EARLY_TRAFFIC_SECRET".to_string(),
+            2 => format!("{}_HANDSHAKE_TRAFFIC_SECRET", side),
+            epoch if epoch >= 3 => format!("{}_TRAFFIC_SECRET_{}", side, epoch - 3),
+            _ => return None,
+        };
+        self.client_random.map(|random| {
+            let mut line = label;
+            line.push(' ');
+            for b in random.iter()
+            {
+                write!(line, "{:02x}", b).unwrap();
+            }
+            line.push(' ');
+            for b in self.secret.iter()
+            {
+                write!(line, "{:02x}", b).unwrap();
+            }
+            line.push('\n');
+            line
+        })
+    }
+}
+
+/// A secret callback that writes the secrets in the key log format of NSS, like `SSLKEYLOGFILE`
+///
+/// Anyone with the log can decrypt the connections, so it is only for debugging.
+pub fn writer<W>(writer: W) -> impl Fn(&TrafficSecret) + Send + Sync
+    where W: Write + Send + 'static
+{
+    let writer = Mutex::new(writer);
+    move |secret: &TrafficSecret|
+    {
+        if let Some(line) = secret.keylog_line() {
+            let mut writer = writer.lock().unwrap();
+            // Failing to log should not fail the connection
+            let _ = writer.write_all(line.as_bytes()).and_then(|()| writer.flush());
+        }
+    }
+}
+
+extern "C" fn secret_callback(fd: *mut nspr::PRFileDesc, epoch: u16, dir: c_int, secret: *mut pk11::PK11SymKey, arg: *mut c_void)
+{
+    let callbacks = unsafe { &*(arg as *const Callbacks) };
+    let callback = match callbacks.secrets
+    {
+        Some(ref callback) => callback,
+        None => return,
+    };
+    let mut as_client = 0;
+    let server = unsafe { ssl::SSL_OptionGet(fd, ssl::SSL_HANDSHAKE_AS_CLIENT, &mut as_client).to_result() }.is_ok() && as_client == 0;
+    let direction = if dir == ssl::SSL_SECRET_READ { SecretDirection::Read } else { SecretDirection::Write };
+    let key = unsafe { pk11::SymKey::wrap(pk11::PK11_ReferenceSymKey(secret)) };
+    let secret = match key.and_then(|key| Key::from_raw(key).extract())
+    {
+        Ok(secret) => secret,
+        Err(_) => return,
+    };
+    callback(&TrafficSecret {
+        epoch: epoch,
+        direction: direction,
+        client: (direction == SecretDirection::Write) != server,
+        secret: secret,
+        client_random: unsafe { ::io::client_random((*fd).lower, server) },
+    });
+}
+
+pub(super) fn install(fd: &mut nspr::FileDesc, callbacks: &Callbacks) -> NSSResult<()>
+{
+    if callbacks.secrets.is_none() {
+        return Ok(());
+    }
+    let set: ssl::SSLSetSecretCallback = try!(unsafe { ssl::experimental_api(b"SSL_SecretCallback\0") });
+    let arg = callbacks as *const Callbacks as *mut c_void;
+    unsafe { set(fd.get_mut(), secret_callback, arg).to_result() }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::Certificate;
+    use cert::verify::{Usage, Verifier};
+    use ssl::TlsConnector;
+    use ssl::test::{pki, serve};
+    use std::io::{self, Read, Write};
+    use std::net::TcpStream;
+
+    /// A log that the test can read while the connector writes to it
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Log
+    {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+        {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()>
+        {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keylog()
+    {
+        let pki = pki();
+        let server_log = Log::default();
+        let log = server_log.clone();
+        let port = serve(&pki, 1, move |acceptor, stream|
+        {
+            let mut stream = acceptor.clone().secret_callback(writer(log.clone())).accept(stream).unwrap();
+            stream.write_all(b"x").unwrap();
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let client_log = Log::default();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root))
+                            .secret_callback(writer(client_log.clone()));
+        let mut stream = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+        stream.read_exact(&mut [0]).unwrap();
+
+        let client_log = String::from_utf8(client_log.0.lock().unwrap().clone()).unwrap();
+        let mut lines: Vec<_> = client_log.lines().collect();
+        lines.sort();
+        let labels: Vec<_> = lines.iter().map(|line| line.split(' ').next().unwrap()).collect();
+        assert_eq!(vec!["CLIENT_HANDSHAKE_TRAFFIC_SECRET", "CLIENT_TRAFFIC_SECRET_0",
+                        "SERVER_HANDSHAKE_TRAFFIC_SECRET", "SERVER_TRAFFIC_SECRET_0"], labels);
+        // Both ends log the same secrets for the same connection
+        let server_log = String::from_utf8(server_log.0.lock().unwrap().clone()).unwrap();
+        let mut server_lines: Vec<_> = server_log.lines().collect();
+        server_lines.sort();
+        assert_eq!(lines, server_lines);
+    }
+}
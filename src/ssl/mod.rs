@@ -0,0 +1,492 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, nspr, pk11, sec, ssl};
+use ffi::nspr::PRBool;
+use cert::Certificate;
+use cert::verify::Verifier;
+use crypto::pkey::PrivateKey;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Once};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::{cmp, mem, ptr};
+use libc::c_void;
+
+pub use self::client::TlsConnector;
+pub use self::info::{ChannelInfo, TlsVersion};
+pub use self::policy::{NamedGroup, SignatureScheme, TlsPolicy};
+pub use self::psk::ExternalPsk;
+pub use self::compression::CertificateCompression;
+pub use self::dtls::{Datagram, DtlsStream};
+#[cfg(feature = "keylog")]
+pub use self::keylog::{SecretDirection, TrafficSecret};
+pub use self::server::{ClientAuth, TlsAcceptor};
+#[cfg(feature = "tokio")]
+pub use self::async_io::{AsyncTlsStream, Handshake};
+
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod client;
+pub mod compression;
+pub mod dtls;
+pub mod info;
+#[cfg(feature = "keylog")]
+pub mod keylog;
+pub mod policy;
+pub mod psk;
+pub mod server;
+
+fn init() -> NSSResult<()>
+{
+    try!(::nss::init());
+
+    // The error code of setting the policy, or 0 if it succeeded, for every caller after the first
+    static POLICY: Once = Once::new();
+    static POLICY_ERROR: AtomicI32 = AtomicI32::new(0);
+    POLICY.call_once(|| {
+        if let Err(NSSError::NSS(code)) = unsafe { ssl::NSS_SetDomesticPolicy().to_result() } {
+            POLICY_ERROR.store(code, Ordering::SeqCst);
+        }
+    });
+    match POLICY_ERROR.load(Ordering::SeqCst)
+    {
+        0 => Ok(()),
+        code => Err(NSSError::NSS(code)),
+    }
+}
+
+/// Layers SSL on top of the stream, or DTLS when every read and write of the stream is a datagram
+fn import<S>(stream: S, nonblocking: bool, datagram: bool) -> NSSResult<nspr::FileDesc>
+    where S: Read + Write + Send + 'static
+{
+    try!(init());
+
+    let mut lower = try!(::io::layer(stream, nonblocking));
+    let fd = match datagram
+    {
+        true => unsafe { ssl::DTLS_ImportFD(ptr::null_mut(), lower.get_mut()) },
+        false => unsafe { ssl::SSL_ImportFD(ptr::null_mut(), lower.get_mut()) },
+    };
+    if fd.is_null() {
+        return Err(nspr::get_error_code());
+    }
+    // The SSL layer now owns the stream
+    mem::forget(lower);
+    nspr::FileDesc::wrap(fd)
+}
+
+/// Offers the protocols with ALPN, in the order of preference
+fn set_alpn(fd: &mut nspr::FileDesc, protocols: &[Vec<u8>]) -> NSSResult<()>
+{
+    if protocols.is_empty() {
+        return Ok(());
+    }
+    let mut data = Vec::new();
+    for protocol in protocols
+    {
+        if protocol.is_empty() || protocol.len() > 255 {
+            return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+        }
+        data.push(protocol.len() as u8);
+        data.extend_from_slice(protocol);
+    }
+    unsafe { ssl::SSL_SetNextProtoNego(fd.get_mut(), data.as_ptr(), data.len() as u32).to_result() }
+}
+
+/// A check of a verified peer certificate and its chain, which rejects the peer by returning false
+pub(crate) type CertificateCheck = Arc<dyn Fn(&Certificate, &[Certificate]) -> bool + Send + Sync>;
+
+/// What the callbacks of a socket refer to, which lives as long as the socket
+struct Callbacks
+{
+    verifier: Verifier,
+    hostname: Option<String>,
+    check: Option<CertificateCheck>,
+    /// The certificate and key of a client, for servers that ask for one
+    identity: Option<(Certificate, PrivateKey)>,
+    #[cfg(feature = "keylog")]
+    secrets: Option<keylog::SecretCallback>,
+}
+
+impl Callbacks
+{
+    fn check_peer(&self, fd: *mut nspr::PRFileDesc) -> NSSResult<()>
+    {
+        let certificate = try!(unsafe { Certificate::from_raw(ssl::SSL_PeerCertificate(fd)) });
+        let chain = try!(self.verifier.verify(&certificate).map_err(|e| e.to_nss_error()));
+        if let Some(ref hostname) = self.hostname {
+            let hostname = try!(::cert::db::c_string(hostname));
+            try!(unsafe { cert::CERT_VerifyCertName(certificate.get(), hostname.as_ptr()).to_result() });
+        }
+        if let Some(ref check) = self.check {
+            if !check(&certificate, &chain) {
+                return Err(NSSError::NSS(ssl::SSL_ERROR_BAD_CERTIFICATE));
+            }
+        }
+        Ok(())
+    }
+}
+
+extern "C" fn auth_certificate(arg: *mut c_void, fd: *mut nspr::PRFileDesc, _check_sig: PRBool, _is_server: PRBool) -> sec::SECStatus
+{
+    let callbacks = unsafe { &*(arg as *const Callbacks) };
+    match callbacks.check_peer(fd)
+    {
+        Ok(()) => sec::SECStatus::Success,
+        Err(error) => {
+            let code = match error
+            {
+                NSSError::NSS(code) => code,
+                _ => sec::SEC_ERROR_INVALID_ARGS,
+            };
+            unsafe { nspr::PR_SetError(code, 0) };
+            sec::SECStatus::Failure
+        },
+    }
+}
+
+extern "C" fn client_auth_data(arg: *mut c_void, _fd: *mut nspr::PRFileDesc, _ca_names: *mut c_void,
+                               ret_cert: *mut *mut cert::CERTCertificate, ret_key: *mut *mut pk11::SECKEYPrivateKey) -> sec::SECStatus
+{
+    let callbacks = unsafe { &*(arg as *const Callbacks) };
+    match callbacks.identity
+    {
+        Some((ref certificate, ref key)) => unsafe {
+            // NSS takes over both references
+            *ret_cert = cert::CERT_DupCertificate(certificate.get() as *mut _);
+            *ret_key = pk11::SECKEY_CopyPrivateKey(key.get());
+            sec::SECStatus::Success
+        },
+        None => sec::SECStatus::Failure,
+    }
+}
+
+fn io_error(error: NSSError) -> io::Error
+{
+    let kind = match error
+    {
+        NSSError::NSS(nspr::PR_WOULD_BLOCK_ERROR) => io::ErrorKind::WouldBlock,
+        NSSError::NSS(nspr::PR_IO_TIMEOUT_ERROR) => io::ErrorKind::TimedOut,
+        NSSError::NSS(nspr::PR_CONNECT_RESET_ERROR) => io::ErrorKind::ConnectionReset,
+        NSSError::NSS(nspr::PR_END_OF_FILE_ERROR) => io::ErrorKind::UnexpectedEof,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, error)
+}
+
+fn io_status(status: nspr::PRStatus) -> io::Result<()>
+{
+    match status
+    {
+        nspr::PRStatus::PR_SUCCESS => Ok(()),
+        nspr::PRStatus::PR_FAILURE => Err(io_error(nspr::get_error_code())),
+    }
+}
+
+/// Data a client sends during its handshake, as early data when the session allows it
+struct EarlyData
+{
+    data: Vec<u8>,
+    /// How much of the data was written, which is reset when the server rejects early data
+    written: usize,
+    hello_sent: bool,
+    handshake_done: bool,
+}
+
+impl EarlyData
+{
+    fn new(data: Vec<u8>) -> EarlyData
+    {
+        EarlyData { data: data, written: 0, hello_sent: false, handshake_done: false }
+    }
+}
+
+/// A TLS connection that finished its handshake
+pub struct TlsStream
+{
+    fd: nspr::FileDesc,
+    // Referenced by the socket, so it is dropped after it
+    _callbacks: Box<Callbacks>,
+    server: bool,
+    early_data: Option<EarlyData>,
+    /// Early data that a server read to finish its handshake, which is read before the rest
+    received: Vec<u8>,
+}
+
+// NSS sockets may be used from any thread, and only one at a time uses this one. The stream below it is `Send`, and
+// the certificates and keys of the callbacks are reference counted by NSS, which is thread safe.
+unsafe impl Send for TlsStream {}
+
+impl TlsStream
+{
+    /// Continues the handshake, which is only done once it succeeds
+    fn handshake(&mut self) -> NSSResult<()>
+    {
+        let mut early_data = match self.early_data.take()
+        {
+            Some(early_data) => early_data,
+            None => return self.force_handshake(),
+        };
+        let result = self.handshake_with(&mut early_data);
+        if let Err(NSSError::NSS(nspr::PR_WOULD_BLOCK_ERROR)) = result {
+            self.early_data = Some(early_data);
+        }
+        result
+    }
+
+    fn force_handshake(&mut self) -> NSSResult<()>
+    {
+        loop
+        {
+            match unsafe { ssl::SSL_ForceHandshake(self.fd.get_mut()).to_result() }
+            {
+                // The handshake of a server only continues once the early data is read
+                Err(NSSError::NSS(nspr::PR_WOULD_BLOCK_ERROR)) if self.server && self.early_data_accepted() => {
+                    let mut buf = [0; 4096];
+                    match try!(self.read_raw(&mut buf))
+                    {
+                        0 => return Err(NSSError::NSS(nspr::PR_END_OF_FILE_ERROR)),
+                        read => self.received.extend_from_slice(&buf[..read]),
+                    }
+                },
+                result => return result,
+            }
+        }
+    }
+
+    fn handshake_with(&mut self, early_data: &mut EarlyData) -> NSSResult<()>
+    {
+        if !early_data.hello_sent {
+            // Early data can only be written once the ClientHello is sent, so the handshake stops there
+            let lower = unsafe { (*self.fd.get_mut()).lower };
+            unsafe { ::io::hold_reads(lower, true) };
+            let result = unsafe { ssl::SSL_ForceHandshake(self.fd.get_mut()).to_result() };
+            unsafe { ::io::hold_reads(lower, false) };
+            match result
+            {
+                Ok(()) | Err(NSSError::NSS(nspr::PR_WOULD_BLOCK_ERROR)) => {},
+                Err(e) => return Err(e),
+            }
+            early_data.hello_sent = true;
+
+            let info = try!(self.preliminary_info());
+            if let PRBool::True = info.can_send_early_data {
+                let len = cmp::min(early_data.data.len(), info.max_early_data_size as usize);
+                early_data.written = try!(self.write_raw(&early_data.data[..len]));
+            }
+        }
+        if !early_data.handshake_done {
+            try!(self.force_handshake());
+            early_data.handshake_done = true;
+            // The server discarded the early data, so it is sent again
+            if !self.early_data_accepted() {
+                early_data.written = 0;
+            }
+        }
+        while early_data.written < early_data.data.len()
+        {
+            early_data.written += try!(self.write_raw(&early_data.data[early_data.written..]));
+        }
+        Ok(())
+    }
+
+    fn read_raw(&mut self, buf: &mut [u8]) -> NSSResult<usize>
+    {
+        let len = cmp::min(buf.len(), i32::MAX as usize) as i32;
+        match unsafe { nspr::PR_Read(self.fd.get_mut(), buf.as_mut_ptr() as *mut c_void, len) }
+        {
+            read if read < 0 => Err(nspr::get_error_code()),
+            read => Ok(read as usize),
+        }
+    }
+
+    fn write_raw(&mut self, buf: &[u8]) -> NSSResult<usize>
+    {
+        let len = cmp::min(buf.len(), i32::MAX as usize) as i32;
+        match unsafe { nspr::PR_Write(self.fd.get_mut(), buf.as_ptr() as *const c_void, len) }
+        {
+            written if written < 0 => Err(nspr::get_error_code()),
+            written => Ok(written as usize),
+        }
+    }
+
+    fn channel_info(&self) -> NSSResult<ssl::SSLChannelInfo>
+    {
+        unsafe
+        {
+            let mut info: ssl::SSLChannelInfo = mem::zeroed();
+            try!(ssl::SSL_GetChannelInfo(self.fd.get() as *mut _, &mut info, mem::size_of_val(&info) as u32).to_result());
+            Ok(info)
+        }
+    }
+
+    fn preliminary_info(&self) -> NSSResult<ssl::SSLPreliminaryChannelInfo>
+    {
+        unsafe
+        {
+            let mut info: ssl::SSLPreliminaryChannelInfo = mem::zeroed();
+            try!(ssl::SSL_GetPreliminaryChannelInfo(self.fd.get() as *mut _, &mut info, mem::size_of_val(&info) as u32).to_result());
+            Ok(info)
+        }
+    }
+
+    pub fn peer_certificate(&self) -> Option<Certificate>
+    {
+        unsafe { Certificate::from_raw(ssl::SSL_PeerCertificate(self.fd.get() as *mut _)).ok() }
+    }
+
+    /// The protocol that was agreed on with ALPN
+    pub fn alpn_protocol(&self) -> Option<Vec<u8>>
+    {
+        let mut state = 0;
+        let mut buf = [0; 255];
+        let mut len = 0;
+        unsafe
+        {
+            if ssl::SSL_GetNextProto(self.fd.get() as *mut _, &mut state, buf.as_mut_ptr(), &mut len, buf.len() as u32).to_result().is_err() {
+                return None;
+            }
+        }
+        match state
+        {
+            ssl::SSL_NEXT_PROTO_NEGOTIATED | ssl::SSL_NEXT_PROTO_SELECTED => Some(buf[..len as usize].to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Whether the session of an earlier connection was resumed, with a ticket or session ID
+    pub fn resumed(&self) -> bool
+    {
+        match self.channel_info()
+        {
+            Ok(info) => match info.resumed { PRBool::True => true, PRBool::False => false },
+            Err(_) => false,
+        }
+    }
+
+    /// Whether the server accepted the early data of the client, which an attacker could have replayed
+    pub fn early_data_accepted(&self) -> bool
+    {
+        if self.server {
+            return self.preliminary_info().map(|info| info.values_set & ssl::SSL_PREINFO_0RTT_CIPHER_SUITE != 0).unwrap_or(false);
+        }
+        match self.channel_info()
+        {
+            Ok(info) => match info.early_data_accepted { PRBool::True => true, PRBool::False => false },
+            Err(_) => false,
+        }
+    }
+
+    /// Tells the peer that nothing more will be written, after which writing fails
+    pub fn shutdown(&mut self) -> io::Result<()>
+    {
+        io_status(unsafe { nspr::PR_Shutdown(self.fd.get_mut(), nspr::PR_SHUTDOWN_SEND) })
+    }
+}
+
+impl Read for TlsStream
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        if !self.received.is_empty() {
+            let len = cmp::min(buf.len(), self.received.len());
+            buf[..len].copy_from_slice(&self.received[..len]);
+            self.received.drain(..len);
+            return Ok(len);
+        }
+        self.read_raw(buf).map_err(io_error)
+    }
+}
+
+impl Write for TlsStream
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize>
+    {
+        self.write_raw(buf).map_err(io_error)
+    }
+
+    fn flush(&mut self) -> io::Result<()>
+    {
+        // The SSL layer writes records right away, but it cannot flush the stream below
+        io_status(unsafe { nspr::PR_Sync((*self.fd.get_mut()).lower) })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test
+{
+    use super::TlsAcceptor;
+    use super::server::configure_session_cache;
+    use cert::{BasicConstraints, Certificate, ExtendedKeyUsage, Extension, GeneralName, KeyUsage, NameBuilder};
+    use cert::builder::CertificateBuilder;
+    use crypto::pkey::{Curve, HashAlgorithm, PrivateKey};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Once;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    /// A root with a server and a client certificate, encoded to pass them between threads
+    pub struct Pki
+    {
+        pub root: Vec<u8>,
+        pub server: Vec<u8>,
+        pub server_key: Vec<u8>,
+        pub client: Vec<u8>,
+        pub client_key: Vec<u8>,
+    }
+
+    fn leaf(name: &str, usage: ExtendedKeyUsage, root: &Certificate, root_key: &mut PrivateKey) -> (Vec<u8>, Vec<u8>)
+    {
+        let mut key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let certificate = CertificateBuilder::new(NameBuilder::new().common_name(name))
+                            .extension(Extension::key_usage(&[KeyUsage::DigitalSignature]))
+                            .extension(Extension::extended_key_usage(&[usage]).unwrap())
+                            .extension(Extension::subject_alt_name(&[GeneralName::DNS(name.to_string())]).unwrap())
+                            .sign(&key.public_key().unwrap(), root, root_key, HashAlgorithm::SHA256).unwrap();
+        (certificate.to_der(), key.save().unwrap())
+    }
+
+    /// Configures the session cache of the process once, returning whether that succeeded
+    ///
+    /// The cache is global and can only be configured once, so all tests use it to not depend on their order.
+    pub fn configure_cache() -> bool
+    {
+        static CACHE: Once = Once::new();
+        static CONFIGURED: AtomicBool = AtomicBool::new(false);
+        CACHE.call_once(|| CONFIGURED.store(configure_session_cache(100, Duration::from_secs(3600)).is_ok(), Ordering::SeqCst));
+        CONFIGURED.load(Ordering::SeqCst)
+    }
+
+    pub fn pki() -> Pki
+    {
+        assert!(configure_cache());
+        let mut root_key = PrivateKey::gen_ec(Curve::P256).unwrap();
+        let root = CertificateBuilder::new(NameBuilder::new().common_name("nss-rs ssl root"))
+                    .extension(Extension::basic_constraints(BasicConstraints { ca: true, path_len: None }))
+                    .extension(Extension::key_usage(&[KeyUsage::KeyCertSign]))
+                    .self_signed(&mut root_key, HashAlgorithm::SHA256).unwrap();
+        let (server, server_key) = leaf("localhost", ExtendedKeyUsage::ServerAuth, &root, &mut root_key);
+        let (client, client_key) = leaf("client.localhost", ExtendedKeyUsage::ClientAuth, &root, &mut root_key);
+        Pki { root: root.to_der(), server: server, server_key: server_key, client: client, client_key: client_key }
+    }
+
+    /// Hands the given number of loopback connections to the handler on another thread, with an acceptor for the
+    /// server certificate, returning the port
+    pub fn serve<F>(pki: &Pki, connections: usize, handler: F) -> u16
+        where F: Fn(&TlsAcceptor, TcpStream) + Send + 'static
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let certificate = pki.server.clone();
+        let key = pki.server_key.clone();
+        thread::spawn(move ||
+        {
+            let certificate = Certificate::from_der(&certificate).unwrap();
+            let acceptor = TlsAcceptor::new(&[certificate], &PrivateKey::load(&key).unwrap()).unwrap();
+            for stream in listener.incoming().take(connections)
+            {
+                handler(&acceptor, stream.unwrap());
+            }
+        });
+        port
+    }
+}
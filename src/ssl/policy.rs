@@ -0,0 +1,284 @@
+use result::{NSSResult, NSSError};
+use ffi::{nspr, sec, ssl};
+use ffi::nspr::PRBool;
+use std::collections::HashSet;
+use std::slice;
+use std::sync::Mutex;
+use libc::c_int;
+use super::TlsVersion;
+
+pub const TLS_AES_128_GCM_SHA256 : u16 = 0x1301;
+pub const TLS_AES_256_GCM_SHA384 : u16 = 0x1302;
+pub const TLS_CHACHA20_POLY1305_SHA256 : u16 = 0x1303;
+pub const TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256 : u16 = 0xc02b;
+pub const TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384 : u16 = 0xc02c;
+pub const TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256 : u16 = 0xc02f;
+pub const TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384 : u16 = 0xc030;
+pub const TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256 : u16 = 0xcca8;
+pub const TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256 : u16 = 0xcca9;
+
+static DEFAULT: Mutex<Option<TlsPolicy>> = Mutex::new(None);
+
+/// A group for the key exchange
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum NamedGroup
+{
+    P256,
+    P384,
+    P521,
+    X25519,
+    FFDHE2048,
+    FFDHE3072,
+    FFDHE4096,
+    FFDHE6144,
+    FFDHE8192,
+}
+
+impl NamedGroup
+{
+    /// The IANA number of the group, as in `ChannelInfo`
+    pub fn to_raw(&self) -> u16
+    {
+        match *self
+        {
+            NamedGroup::P256 => 23,
+            NamedGroup::P384 => 24,
+            NamedGroup::P521 => 25,
+            NamedGroup::X25519 => 29,
+            NamedGroup::FFDHE2048 => 256,
+            NamedGroup::FFDHE3072 => 257,
+            NamedGroup::FFDHE4096 => 258,
+            NamedGroup::FFDHE6144 => 259,
+            NamedGroup::FFDHE8192 => 260,
+        }
+    }
+}
+
+/// A signature algorithm for the handshake
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SignatureScheme
+{
+    RSA_PKCS1_SHA1,
+    RSA_PKCS1_SHA256,
+    RSA_PKCS1_SHA384,
+    RSA_PKCS1_SHA512,
+    ECDSA_SHA1,
+    ECDSA_P256_SHA256,
+    ECDSA_P384_SHA384,
+    ECDSA_P521_SHA512,
+    RSA_PSS_RSAE_SHA256,
+    RSA_PSS_RSAE_SHA384,
+    RSA_PSS_RSAE_SHA512,
+    RSA_PSS_PSS_SHA256,
+    RSA_PSS_PSS_SHA384,
+    RSA_PSS_PSS_SHA512,
+}
+
+impl SignatureScheme
+{
+    fn to_ffi(&self) -> c_int
+    {
+        match *self
+        {
+            SignatureScheme::RSA_PKCS1_SHA1 => 0x0201,
+            SignatureScheme::RSA_PKCS1_SHA256 => 0x0401,
+            SignatureScheme::RSA_PKCS1_SHA384 => 0x0501,
+            SignatureScheme::RSA_PKCS1_SHA512 => 0x0601,
+            SignatureScheme::ECDSA_SHA1 => 0x0203,
+            SignatureScheme::ECDSA_P256_SHA256 => 0x0403,
+            SignatureScheme::ECDSA_P384_SHA384 => 0x0503,
+            SignatureScheme::ECDSA_P521_SHA512 => 0x0603,
+            SignatureScheme::RSA_PSS_RSAE_SHA256 => 0x0804,
+            SignatureScheme::RSA_PSS_RSAE_SHA384 => 0x0805,
+            SignatureScheme::RSA_PSS_RSAE_SHA512 => 0x0806,
+            SignatureScheme::RSA_PSS_PSS_SHA256 => 0x0809,
+            SignatureScheme::RSA_PSS_PSS_SHA384 => 0x080a,
+            SignatureScheme::RSA_PSS_PSS_SHA512 => 0x080b,
+        }
+    }
+}
+
+fn implemented_cipher_suites() -> &'static [u16]
+{
+    unsafe { slice::from_raw_parts(ssl::SSL_GetImplementedCiphers(), ssl::SSL_GetNumImplementedCiphers() as usize) }
+}
+
+/// Which versions and algorithms connections may use, where NSS decides what is not set
+///
+/// NSS keeps its own order of preference for cipher suites, and may disallow some with its policy.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TlsPolicy
+{
+    versions: Option<(TlsVersion, TlsVersion)>,
+    cipher_suites: Option<Vec<u16>>,
+    groups: Option<Vec<NamedGroup>>,
+    signature_schemes: Option<Vec<SignatureScheme>>,
+}
+
+impl TlsPolicy
+{
+    pub fn new() -> TlsPolicy
+    {
+        TlsPolicy {
+            versions: None,
+            cipher_suites: None,
+            groups: None,
+            signature_schemes: None,
+        }
+    }
+
+    /// The oldest and newest version to negotiate
+    ///
+    /// DTLS connections use the DTLS versions that these map to, so they fail with `TLS10`.
+    pub fn versions(mut self, min: TlsVersion, max: TlsVersion) -> TlsPolicy
+    {
+        self.versions = Some((min, max));
+        self
+    }
+
+    /// The IANA numbers of the cipher suites to enable, such as `TLS_AES_128_GCM_SHA256`, disabling the others
+    pub fn cipher_suites(mut self, suites: &[u16]) -> TlsPolicy
+    {
+        self.cipher_suites = Some(suites.to_vec());
+        self
+    }
+
+    /// The groups to enable for the key exchange, in the order of preference
+    ///
+    /// NSS may only use an ECDSA certificate when the group of its curve is enabled.
+    pub fn groups(mut self, groups: &[NamedGroup]) -> TlsPolicy
+    {
+        self.groups = Some(groups.to_vec());
+        self
+    }
+
+    /// The signature schemes to enable, in the order of preference
+    pub fn signature_schemes(mut self, schemes: &[SignatureScheme]) -> TlsPolicy
+    {
+        self.signature_schemes = Some(schemes.to_vec());
+        self
+    }
+
+    /// The cipher suites of the policy that this build of NSS does not implement or its policy disallows
+    pub fn unsupported_cipher_suites(&self) -> Vec<u16>
+    {
+        let implemented: HashSet<u16> = implemented_cipher_suites().iter().cloned().collect();
+        let suites = match self.cipher_suites
+        {
+            Some(ref suites) => suites,
+            None => return Vec::new(),
+        };
+        suites.iter().cloned().filter(|&suite| {
+            let mut policy = 0;
+            let allowed = unsafe { ssl::SSL_CipherPolicyGet(suite as i32, &mut policy).to_result() }.is_ok() && policy == ssl::SSL_ALLOWED;
+            !implemented.contains(&suite) || !allowed
+        }).collect()
+    }
+
+    /// Makes this the policy of all connectors and acceptors in the process without their own
+    pub fn set_default(&self)
+    {
+        *DEFAULT.lock().unwrap() = Some(self.clone());
+    }
+
+    /// Applies the policy, or the default one without it, to a socket
+    pub(crate) fn apply(policy: Option<&TlsPolicy>, fd: &mut nspr::FileDesc) -> NSSResult<()>
+    {
+        match policy
+        {
+            Some(policy) => policy.apply_to(fd),
+            None => match *DEFAULT.lock().unwrap()
+            {
+                Some(ref policy) => policy.apply_to(fd),
+                None => Ok(()),
+            },
+        }
+    }
+
+    fn apply_to(&self, fd: &mut nspr::FileDesc) -> NSSResult<()>
+    {
+        if let Some((min, max)) = self.versions {
+            let range = ssl::SSLVersionRange { min: min.to_raw(), max: max.to_raw() };
+            try!(unsafe { ssl::SSL_VersionRangeSet(fd.get_mut(), &range).to_result() });
+        }
+        if let Some(ref suites) = self.cipher_suites {
+            let unsupported = self.unsupported_cipher_suites();
+            if !unsupported.is_empty() {
+                return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS));
+            }
+            for &suite in implemented_cipher_suites()
+            {
+                let enabled = if suites.contains(&suite) { PRBool::True } else { PRBool::False };
+                try!(unsafe { ssl::SSL_CipherPrefSet(fd.get_mut(), suite as i32, enabled).to_result() });
+            }
+        }
+        if let Some(ref groups) = self.groups {
+            let groups: Vec<c_int> = groups.iter().map(|g| g.to_raw() as c_int).collect();
+            try!(unsafe { ssl::SSL_NamedGroupConfig(fd.get_mut(), groups.as_ptr(), groups.len() as u32).to_result() });
+        }
+        if let Some(ref schemes) = self.signature_schemes {
+            let schemes: Vec<c_int> = schemes.iter().map(|s| s.to_ffi()).collect();
+            try!(unsafe { ssl::SSL_SignatureSchemePrefSet(fd.get_mut(), schemes.as_ptr(), schemes.len() as u32).to_result() });
+        }
+        Ok(())
+    }
+}
+
+impl Default for TlsPolicy
+{
+    fn default() -> TlsPolicy
+    {
+        TlsPolicy::new()
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::Certificate;
+    use cert::verify::{Usage, Verifier};
+    use ssl::{TlsConnector, TlsVersion};
+    use ssl::test::{pki, serve};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    #[test]
+    fn unsupported()
+    {
+        ::nss::init().unwrap();
+        let policy = TlsPolicy::new().cipher_suites(&[TLS_AES_128_GCM_SHA256, 0xfefe, TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256]);
+        assert_eq!(vec![0xfefe], policy.unsupported_cipher_suites());
+        assert!(TlsPolicy::new().unsupported_cipher_suites().is_empty());
+    }
+
+    #[test]
+    fn negotiate()
+    {
+        let pki = pki();
+        let port = serve(&pki, 2, |acceptor, stream|
+        {
+            let policy = TlsPolicy::new()
+                             .versions(TlsVersion::TLS12, TlsVersion::TLS12)
+                             .cipher_suites(&[TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384])
+                             .groups(&[NamedGroup::P384, NamedGroup::P256])
+                             .signature_schemes(&[SignatureScheme::ECDSA_P256_SHA256]);
+            if let Ok(mut stream) = acceptor.clone().policy(policy).accept(stream) {
+                stream.write_all(b"x").unwrap();
+            }
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root));
+
+        let mut stream = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+        stream.read_exact(&mut [0]).unwrap();
+        let info = stream.info().unwrap();
+        assert_eq!(TlsVersion::TLS12, info.version);
+        assert_eq!(TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384, info.cipher_suite);
+        assert_eq!(NamedGroup::P384.to_raw(), info.key_exchange_group);
+
+        let tls13 = connector.policy(TlsPolicy::new().versions(TlsVersion::TLS13, TlsVersion::TLS13));
+        assert!(tls13.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).is_err());
+    }
+}
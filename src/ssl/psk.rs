@@ -0,0 +1,90 @@
+use result::NSSResult;
+use ffi::{nspr, ssl};
+use crypto::pkey::HashAlgorithm;
+use crypto::symm::Key;
+use libc::c_int;
+
+/// A TLS 1.3 key that both peers know in advance, which authenticates them instead of certificates
+///
+/// NSS uses it for all connections of a connector or acceptor that negotiate TLS 1.3, and falls
+/// back to certificates when the server does not know the identity.
+#[derive(Clone)]
+pub struct ExternalPsk
+{
+    key: Key,
+    identity: Vec<u8>,
+    hash: HashAlgorithm,
+}
+
+impl ExternalPsk
+{
+    /// The identity names the key to the server, and the hash is that of the cipher suite, `SHA256` or `SHA384`
+    pub fn new(key: &Key, identity: &[u8], hash: HashAlgorithm) -> ExternalPsk
+    {
+        ExternalPsk {
+            key: key.clone(),
+            identity: identity.to_vec(),
+            hash: hash,
+        }
+    }
+
+    fn hash_type(&self) -> c_int
+    {
+        match self.hash
+        {
+            HashAlgorithm::SHA1 => 2,
+            HashAlgorithm::SHA224 => 3,
+            HashAlgorithm::SHA256 => 4,
+            HashAlgorithm::SHA384 => 5,
+            HashAlgorithm::SHA512 => 6,
+        }
+    }
+
+    pub(crate) fn add(&self, fd: &mut nspr::FileDesc) -> NSSResult<()>
+    {
+        let add: ssl::SSLAddExternalPsk = try!(unsafe { ssl::experimental_api(b"SSL_AddExternalPsk\0") });
+        let mut key = self.key.clone();
+        unsafe
+        {
+            // NSS takes its own reference to the key
+            add(fd.get_mut(), key.get_mut(), self.identity.as_ptr(), self.identity.len() as u32, self.hash_type()).to_result()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crypto::symm::{Key, Kind};
+    use ssl::{TlsConnector, TlsVersion};
+    use ssl::test::{pki, serve};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    #[test]
+    fn psk()
+    {
+        let pki = pki();
+        let port = serve(&pki, 2, |acceptor, stream|
+        {
+            let key = Key::import(Kind::AES_ECB, &[7; 32]).unwrap();
+            let acceptor = acceptor.clone().external_psk(&ExternalPsk::new(&key, b"device-1", HashAlgorithm::SHA256));
+            if let Ok(mut stream) = acceptor.accept(stream) {
+                stream.write_all(b"x").unwrap();
+            }
+        });
+
+        // The certificate of the server is not trusted, so only the key authenticates it
+        let key = Key::import(Kind::AES_ECB, &[7; 32]).unwrap();
+        let connector = TlsConnector::new().external_psk(&ExternalPsk::new(&key, b"device-1", HashAlgorithm::SHA256));
+        let mut stream = connector.connect("device", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+        stream.read_exact(&mut [0]).unwrap();
+        assert!(stream.peer_certificate().is_none());
+        assert_eq!(TlsVersion::TLS13, stream.info().unwrap().version);
+
+        let other = Key::import(Kind::AES_ECB, &[8; 32]).unwrap();
+        let connector = TlsConnector::new().external_psk(&ExternalPsk::new(&other, b"device-1", HashAlgorithm::SHA256));
+        assert!(connector.connect("device", TcpStream::connect(("127.0.0.1", port)).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,443 @@
+use result::{NSSResult, NSSError};
+use ffi::{cert, nspr, sec, ssl};
+use ffi::nspr::PRBool;
+use cert::Certificate;
+use cert::verify::{Usage, Verifier};
+use crypto::pkey::PrivateKey;
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::time::Duration;
+use std::{mem, ptr};
+use libc::c_void;
+use super::{Callbacks, CertificateCompression, Datagram, DtlsStream, ExternalPsk, TlsPolicy, TlsStream};
+use super::dtls::Datagrams;
+
+static SESSION_CACHE: AtomicBool = AtomicBool::new(false);
+
+/// Configures the session cache of all servers in the process, which session resumption needs
+///
+/// Servers do not cache sessions until this is called, which is only possible once.
+pub fn configure_session_cache(max_entries: u32, timeout: Duration) -> NSSResult<()>
+{
+    try!(super::init());
+
+    if SESSION_CACHE.swap(true, Ordering::SeqCst) {
+        return Err(NSSError::NSS(nspr::PR_INVALID_STATE_ERROR));
+    }
+    let timeout = timeout.as_secs() as u32;
+    let result = unsafe { ssl::SSL_ConfigServerSessionIDCache(max_entries as i32, timeout, timeout, ptr::null()).to_result() };
+    if result.is_err() {
+        SESSION_CACHE.store(false, Ordering::SeqCst);
+    }
+    result
+}
+
+static ANTI_REPLAY: AtomicPtr<ssl::SSLAntiReplayContext> = AtomicPtr::new(ptr::null_mut());
+
+/// Configures the replay protection of all servers in the process, which accepting early data needs
+///
+/// Early data is rejected during the first window, and afterwards when it may be a replay from an
+/// earlier window. Like the session cache, this is only possible once.
+pub fn configure_anti_replay(window: Duration) -> NSSResult<()>
+{
+    try!(super::init());
+
+    if !ANTI_REPLAY.load(Ordering::SeqCst).is_null() {
+        return Err(NSSError::NSS(nspr::PR_INVALID_STATE_ERROR));
+    }
+    let create: ssl::SSLCreateAntiReplayContext = try!(unsafe { ssl::experimental_api(b"SSL_CreateAntiReplayContext\0") });
+    let window = window.as_secs() as i64 * 1000000 + window.subsec_nanos() as i64 / 1000;
+    let mut context = ptr::null_mut();
+    // A bloom filter with 7 hashes of 2^14 bits, as NSS recommends
+    try!(unsafe { create(nspr::PR_Now(), window, 7, 14, &mut context).to_result() });
+    // Another thread may have configured it in the meantime
+    if ANTI_REPLAY.compare_exchange(ptr::null_mut(), context, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        let release: ssl::SSLReleaseAntiReplayContext = try!(unsafe { ssl::experimental_api(b"SSL_ReleaseAntiReplayContext\0") });
+        unsafe { release(context) };
+        return Err(NSSError::NSS(nspr::PR_INVALID_STATE_ERROR));
+    }
+    Ok(())
+}
+
+/// Whether the server asks clients for a certificate
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClientAuth
+{
+    None,
+    /// Clients without a certificate are accepted as well
+    Request,
+    Require,
+}
+
+/// Accepts TLS connections with a certificate chain and its private key
+#[derive(Clone)]
+pub struct TlsAcceptor
+{
+    certificate: Certificate,
+    key: PrivateKey,
+    chain: Vec<Vec<u8>>,
+    client_auth: ClientAuth,
+    verifier: Verifier,
+    check: Option<super::CertificateCheck>,
+    alpn: Vec<Vec<u8>>,
+    session_tickets: bool,
+    early_data: bool,
+    policy: Option<TlsPolicy>,
+    psk: Option<ExternalPsk>,
+    compression: Vec<ssl::SSLCertificateCompressionAlgorithm>,
+    #[cfg(feature = "keylog")]
+    secrets: Option<super::keylog::SecretCallback>,
+}
+
+impl TlsAcceptor
+{
+    /// The chain starts with the server certificate of the key, followed by its issuers
+    pub fn new(chain: &[Certificate], key: &PrivateKey) -> NSSResult<TlsAcceptor>
+    {
+        let certificate = match chain.first()
+        {
+            Some(certificate) => certificate.clone(),
+            None => return Err(NSSError::NSS(sec::SEC_ERROR_INVALID_ARGS)),
+        };
+        Ok(TlsAcceptor {
+            certificate: certificate,
+            key: key.clone(),
+            chain: chain.iter().map(|c| c.to_der()).collect(),
+            client_auth: ClientAuth::None,
+            verifier: Verifier::new(Usage::TLSClient),
+            check: None,
+            alpn: Vec::new(),
+            session_tickets: false,
+            early_data: false,
+            policy: None,
+            psk: None,
+            compression: Vec::new(),
+            #[cfg(feature = "keylog")]
+            secrets: None,
+        })
+    }
+
+    /// Asks clients for a certificate, which is verified with the verifier for `Usage::TLSClient`
+    pub fn client_auth(mut self, client_auth: ClientAuth, verifier: Verifier) -> TlsAcceptor
+    {
+        self.client_auth = client_auth;
+        self.verifier = verifier;
+        self
+    }
+
+    /// Called with a client certificate that verified and its chain, rejecting the client when it returns false
+    pub fn client_certificate_check<F>(mut self, check: F) -> TlsAcceptor
+        where F: Fn(&Certificate, &[Certificate]) -> bool + Send + Sync + 'static
+    {
+        self.check = Some(Arc::new(check));
+        self
+    }
+
+    /// Selects the first of the protocols that the client offers with ALPN
+    pub fn alpn_protocols(mut self, protocols: &[&[u8]]) -> TlsAcceptor
+    {
+        self.alpn = protocols.iter().map(|p| p.to_vec()).collect();
+        self
+    }
+
+    /// Sends session tickets to clients, which lets them resume sessions with TLS 1.2 as well
+    pub fn session_tickets(mut self, enable: bool) -> TlsAcceptor
+    {
+        self.session_tickets = enable;
+        self
+    }
+
+    /// Accepts TLS 1.3 early data in resumed sessions, once `configure_anti_replay` was called
+    ///
+    /// The early data is read like the rest, but it may be a replay by an attacker.
+    pub fn early_data(mut self, enable: bool) -> TlsAcceptor
+    {
+        self.early_data = enable;
+        self
+    }
+
+    /// Limits the versions and algorithms to negotiate, instead of the default policy
+    pub fn policy(mut self, policy: TlsPolicy) -> TlsAcceptor
+    {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Accepts clients that authenticate with the TLS 1.3 key, besides those that check the certificate
+    ///
+    /// NSS cannot accept connections without a certificate, even when all clients use the key.
+    pub fn external_psk(mut self, psk: &ExternalPsk) -> TlsAcceptor
+    {
+        self.psk = Some(psk.clone());
+        self
+    }
+
+    /// Compresses the certificate chain with the algorithm for TLS 1.3 clients that support it
+    pub fn certificate_compression<C: CertificateCompression>(mut self) -> TlsAcceptor
+    {
+        self.compression.push(super::compression::algorithm::<C>());
+        self
+    }
+
+    /// Called with the TLS 1.3 traffic secrets of every connection, such as a key log `writer`
+    #[cfg(feature = "keylog")]
+    pub fn secret_callback<F>(mut self, callback: F) -> TlsAcceptor
+        where F: Fn(&super::TrafficSecret) + Send + Sync + 'static
+    {
+        self.secrets = Some(::std::sync::Arc::new(callback));
+        self
+    }
+
+    /// Performs the handshake over a stream accepted from a client, which may be any transport
+    pub fn accept<S>(&self, stream: S) -> NSSResult<TlsStream>
+        where S: Read + Write + Send + 'static
+    {
+        let mut stream = try!(self.start(stream, false));
+        try!(stream.handshake());
+        Ok(stream)
+    }
+
+    /// Sets up a DTLS connection over a transport to a client, whose handshake the caller drives with `DtlsStream::handshake`
+    pub fn accept_dtls<D>(&self, transport: D) -> NSSResult<DtlsStream>
+        where D: Datagram + Send + 'static
+    {
+        let fd = try!(super::import(Datagrams(transport), true, true));
+        self.configure(fd).map(DtlsStream::new)
+    }
+
+    /// Sets up a connection for its handshake
+    pub(crate) fn start<S>(&self, stream: S, nonblocking: bool) -> NSSResult<TlsStream>
+        where S: Read + Write + Send + 'static
+    {
+        let fd = try!(super::import(stream, nonblocking, false));
+        self.configure(fd)
+    }
+
+    fn configure(&self, mut fd: nspr::FileDesc) -> NSSResult<TlsStream>
+    {
+        let callbacks = Box::new(Callbacks {
+            verifier: self.verifier.clone(),
+            hostname: None,
+            check: self.check.clone(),
+            identity: None,
+            #[cfg(feature = "keylog")]
+            secrets: self.secrets.clone(),
+        });
+        let arg = &*callbacks as *const Callbacks as *mut c_void;
+
+        let mut items: Vec<_> = self.chain.iter().map(|der| *sec::SECItem::from_buf(der).get()).collect();
+        // NSS keeps a copy of the list
+        let chain = cert::CERTCertificateList { certs: items.as_mut_ptr(), len: items.len() as i32, arena: ptr::null_mut() };
+        let data = ssl::SSLExtraServerCertData {
+            auth_type: 0,
+            cert_chain: &chain,
+            stapled_ocsp_responses: ptr::null(),
+            signed_cert_timestamps: ptr::null(),
+            deleg_cred: ptr::null(),
+            deleg_cred_priv_key: ptr::null(),
+        };
+
+        unsafe
+        {
+            try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_SECURITY, 1).to_result());
+            if !SESSION_CACHE.load(Ordering::SeqCst) {
+                try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_NO_CACHE, 1).to_result());
+            }
+            try!(ssl::SSL_ConfigServerCert(fd.get_mut(), self.certificate.get() as *mut _, self.key.get() as *mut _,
+                                           &data, mem::size_of::<ssl::SSLExtraServerCertData>() as u32).to_result());
+            if self.client_auth != ClientAuth::None {
+                try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_REQUEST_CERTIFICATE, 1).to_result());
+                try!(ssl::SSL_AuthCertificateHook(fd.get_mut(), super::auth_certificate, arg).to_result());
+            }
+            // NSS requires a certificate on the first handshake by default
+            let require = if self.client_auth == ClientAuth::Require { ssl::SSL_REQUIRE_ALWAYS } else { ssl::SSL_REQUIRE_NEVER };
+            try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_REQUIRE_CERTIFICATE, require).to_result());
+            try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_ENABLE_SESSION_TICKETS, self.session_tickets as i32).to_result());
+            try!(TlsPolicy::apply(self.policy.as_ref(), &mut fd));
+            let anti_replay = ANTI_REPLAY.load(Ordering::SeqCst);
+            // Without replay protection, NSS rejects all early data
+            if self.early_data && !anti_replay.is_null() {
+                let set: ssl::SSLSetAntiReplayContext = try!(ssl::experimental_api(b"SSL_SetAntiReplayContext\0"));
+                try!(ssl::SSL_OptionSet(fd.get_mut(), ssl::SSL_ENABLE_0RTT_DATA, 1).to_result());
+                try!(set(fd.get_mut(), anti_replay).to_result());
+            }
+            try!(ssl::SSL_ResetHandshake(fd.get_mut(), PRBool::True).to_result());
+        }
+        try!(super::set_alpn(&mut fd, &self.alpn));
+        if let Some(ref psk) = self.psk {
+            try!(psk.add(&mut fd));
+        }
+        try!(super::compression::set_algorithms(&mut fd, &self.compression));
+        #[cfg(feature = "keylog")]
+        try!(super::keylog::install(&mut fd, &callbacks));
+        Ok(TlsStream {
+            fd: fd,
+            _callbacks: callbacks,
+            server: true,
+            early_data: None,
+            received: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use cert::Certificate;
+    use cert::verify::{Usage, Verifier};
+    use crypto::pkey::PrivateKey;
+    use ssl::TlsConnector;
+    use ssl::test::{configure_cache, pki, serve};
+    use std::cell::Cell;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn worker_thread()
+    {
+        let pki = pki();
+        let (sender, receiver) = mpsc::channel();
+        // The stream is handed to a worker once the handshake is done
+        let port = serve(&pki, 1, move |acceptor, stream|
+        {
+            let mut stream = acceptor.accept(stream).unwrap();
+            sender.send(thread::spawn(move ||
+            {
+                let mut line = String::new();
+                BufReader::new(&mut stream).read_line(&mut line).unwrap();
+                stream.write_all(line.as_bytes()).unwrap();
+            })).unwrap();
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root));
+
+        let mut stream = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+        let worker = receiver.recv().unwrap();
+        stream.write_all(b"hello\n").unwrap();
+        let mut line = String::new();
+        BufReader::new(&mut stream).read_line(&mut line).unwrap();
+        assert_eq!("hello\n", line);
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn client_auth()
+    {
+        let pki = pki();
+        let root_der = pki.root.clone();
+        let count = Cell::new(0);
+        let (sender, receiver) = mpsc::channel();
+        let port = serve(&pki, 4, move |acceptor, stream|
+        {
+            let root = Certificate::from_der(&root_der).unwrap();
+            let verifier = Verifier::new(Usage::TLSClient).trust_anchor(&root);
+            let accept = count.get() != 0;
+            let acceptor = match count.get()
+            {
+                3 => acceptor.clone().client_auth(ClientAuth::Request, verifier),
+                _ => acceptor.clone().client_auth(ClientAuth::Require, verifier),
+            };
+            let acceptor = acceptor.client_certificate_check(move |_, chain| accept && chain.len() == 2);
+            count.set(count.get() + 1);
+
+            let result = acceptor.accept(stream).map(|mut stream| {
+                stream.write_all(b"x").unwrap();
+                stream.peer_certificate().and_then(|c| c.subject().common_name())
+            });
+            sender.send(result.ok()).unwrap();
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let client = Certificate::from_der(&pki.client).unwrap();
+        let client_key = PrivateKey::load(&pki.client_key).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root));
+        let with_certificate = connector.clone().client_certificate(&client, &client_key);
+        let connect = |connector: &TlsConnector| {
+            // With TLS 1.3 the client only learns about a rejected certificate after its handshake
+            let _ = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap())
+                        .map(|mut stream| stream.read(&mut [0]));
+        };
+
+        // Rejected by the check
+        connect(&with_certificate);
+        assert_eq!(None, receiver.recv().unwrap());
+        connect(&with_certificate);
+        assert_eq!(Some(Some("client.localhost".to_string())), receiver.recv().unwrap());
+        // Neither resumes the session of the client certificate, which the server caches as in all tests
+        connect(&connector);
+        assert_eq!(None, receiver.recv().unwrap());
+        connect(&connector);
+        assert_eq!(Some(None), receiver.recv().unwrap());
+    }
+
+    #[test]
+    fn session_cache()
+    {
+        // The first configuration is done for all tests
+        assert!(configure_cache());
+        assert!(configure_session_cache(100, Duration::from_secs(3600)).is_err());
+
+        let pki = pki();
+        let port = serve(&pki, 3, |acceptor, stream|
+        {
+            let mut stream = acceptor.clone().session_tickets(true).accept(stream).unwrap();
+            let reply = format!("{}\n", stream.resumed());
+            stream.write_all(reply.as_bytes()).unwrap();
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root)).session_tickets(true);
+        let connect = |connector: &TlsConnector| {
+            let mut stream = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+            // The server sends its ticket before the reply
+            let mut reply = String::new();
+            BufReader::new(&mut stream).read_line(&mut reply).unwrap();
+            assert_eq!(format!("{}\n", stream.resumed()), reply);
+            reply
+        };
+
+        assert_eq!("false\n", connect(&connector));
+        assert_eq!("true\n", connect(&connector));
+        // Another configuration does not resume the session
+        assert_eq!("false\n", connect(&connector.clone().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root))));
+    }
+
+    #[test]
+    fn alpn_and_early_data()
+    {
+        configure_anti_replay(Duration::from_millis(100)).unwrap();
+        assert!(configure_anti_replay(Duration::from_millis(100)).is_err());
+        // Early data is rejected until the first window is over
+        thread::sleep(Duration::from_millis(300));
+
+        let pki = pki();
+        let port = serve(&pki, 2, |acceptor, stream|
+        {
+            let acceptor = acceptor.clone().alpn_protocols(&[b"h2", b"http/1.1"]).session_tickets(true).early_data(true);
+            let mut stream = acceptor.accept(stream).unwrap();
+            let mut line = String::new();
+            BufReader::new(&mut stream).read_line(&mut line).unwrap();
+            assert_eq!("hello\n", line);
+            let reply = format!("{}\n", stream.early_data_accepted());
+            stream.write_all(reply.as_bytes()).unwrap();
+        });
+        let root = Certificate::from_der(&pki.root).unwrap();
+        let connector = TlsConnector::new().verifier(Verifier::new(Usage::TLSServer).trust_anchor(&root))
+                            .alpn_protocols(&[b"http/1.1"]).session_tickets(true).additional_key_shares(1).early_data(b"hello\n");
+        let connect = || {
+            let mut stream = connector.connect("localhost", TcpStream::connect(("127.0.0.1", port)).unwrap()).unwrap();
+            assert_eq!(Some(b"http/1.1".to_vec()), stream.alpn_protocol());
+            let mut reply = String::new();
+            BufReader::new(&mut stream).read_line(&mut reply).unwrap();
+            assert_eq!(format!("{}\n", stream.early_data_accepted()), reply);
+            reply
+        };
+
+        // Only a resumed session allows early data
+        assert_eq!("false\n", connect());
+        assert_eq!("true\n", connect());
+    }
+}